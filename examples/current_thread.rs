@@ -0,0 +1,53 @@
+//! Runs a handful of delays on the current thread, with the timer driven as
+//! a task by the same loop instead of a helper thread. The loop sleeps until
+//! the next deadline, or until one of the tasks is woken.
+
+extern crate futures;
+extern crate futures_timer;
+
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use futures::prelude::*;
+use futures::future;
+use futures::task::{self, LocalMap, Wake, Waker};
+use futures_timer::{Delay, Timer};
+
+// Wakes the loop by unparking its thread.
+struct Unpark(thread::Thread);
+
+impl Wake for Unpark {
+    fn wake(arc_self: &Arc<Self>) {
+        arc_self.0.unpark()
+    }
+}
+
+fn main() {
+    let (mut timer, handle) = Timer::task();
+
+    let start = Instant::now();
+    let delays = (1..4).map(|i| {
+        let at = handle.now() + Duration::from_millis(i * 100);
+        Delay::new_handle(at, handle.clone()).then(move |res| {
+            println!("delay {} fired after {:?}", i, start.elapsed());
+            Ok::<_, ()>(res.is_ok())
+        })
+    });
+    let mut done = future::join_all(delays);
+
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut map = LocalMap::new();
+    let mut cx = task::Context::without_spawn(&mut map, &waker);
+    let fired = loop {
+        let _ = timer.poll(&mut cx);
+        if let Async::Ready(fired) = done.poll(&mut cx).unwrap() {
+            break fired
+        }
+        match timer.next_event() {
+            Some(at) => thread::park_timeout(at.saturating_duration_since(handle.now())),
+            None => thread::park(),
+        }
+    };
+    assert!(fired.into_iter().all(|ok| ok));
+}
@@ -1,7 +1,7 @@
 //! An atomically managed intrusive linked list of `Arc` nodes
 
 use std::marker;
-use std::ops::Deref;
+use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
 use std::sync::atomic::Ordering::SeqCst;
 use std::sync::atomic::{AtomicUsize, AtomicBool};
@@ -124,6 +124,12 @@ impl<T> Deref for Node<T> {
     }
 }
 
+impl<T> DerefMut for Node<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,84 @@
+use std::cmp;
+use std::time::Duration;
+use std::io;
+
+use futures::prelude::*;
+
+use {Delay, TimerHandle};
+use delay;
+
+/// A stream of notifications spaced by exponentially increasing intervals.
+///
+/// The first notification fires `base` after the stream is created, and each
+/// notification after that waits `factor` times as long as the previous one,
+/// up to at most `max`. Calling `reset` goes back to waiting `base`, which
+/// makes this suitable for reconnection loops which back off while a peer is
+/// unreachable and retry quickly again once it's healthy.
+///
+/// Each interval is measured from the moment the previous notification was
+/// observed, so a slow consumer never sees a burst of notifications.
+pub struct BackoffInterval {
+    delay: Delay,
+    base: Duration,
+    factor: f64,
+    max: Duration,
+    current: Duration,
+}
+
+impl BackoffInterval {
+    /// Creates a new backoff interval which first fires `base` time into the
+    /// future, multiplying the interval by `factor` after every notification
+    /// but never exceeding `max`.
+    ///
+    /// The returned object will be bound to the default timer for this thread.
+    /// The default timer will be spun up in a helper thread on first use.
+    pub fn new(base: Duration, factor: f64, max: Duration) -> BackoffInterval {
+        BackoffInterval::new_handle(base, factor, max, TimerHandle::default())
+    }
+
+    /// Creates a new backoff interval like `new`, but bound to the timer
+    /// specified by `handle`.
+    pub fn new_handle(base: Duration,
+                      factor: f64,
+                      max: Duration,
+                      handle: TimerHandle) -> BackoffInterval {
+        let base = cmp::min(base, max);
+        BackoffInterval {
+            delay: Delay::new_handle(delay::after(handle.now(), base), handle),
+            base,
+            factor,
+            max,
+            current: base,
+        }
+    }
+
+    /// Returns the interval the next notification is waiting for.
+    pub fn current(&self) -> Duration {
+        self.current
+    }
+
+    /// Goes back to the base interval, so that the next notification fires
+    /// `base` time from now.
+    pub fn reset(&mut self) {
+        self.current = self.base;
+        self.delay.reset(self.base);
+    }
+}
+
+impl Stream for BackoffInterval {
+    type Item = ();
+    type Error = io::Error;
+
+    fn poll_next(&mut self, cx: &mut task::Context) -> Poll<Option<()>, io::Error> {
+        if self.delay.poll(cx)?.is_pending() {
+            return Ok(Async::Pending)
+        }
+        let next = self.current.as_secs_f64() * self.factor;
+        self.current = match Duration::try_from_secs_f64(next) {
+            Ok(next) => cmp::min(next, self.max),
+            Err(_) => self.max,
+        };
+        self.delay.reset(self.current);
+        Ok(Async::Ready(Some(())))
+    }
+}
@@ -0,0 +1,325 @@
+//! Configuration for creating a `Timer` or a `Delay`.
+
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::AtomicUsize;
+use std::time::{Duration, Instant};
+
+use futures::task::AtomicWaker;
+
+use arc_list::ArcList;
+use global::{TimerThread, DEFAULT_THREAD_NAME};
+use wheel::Wheel;
+use delay;
+use {Clock, Delay, Inner, SystemClock, Timer, TimerHandle, POOL_SHARDS};
+
+/// Builder for a `Timer` with a non-default configuration.
+///
+/// A builder is created through `Timer::builder` and each of its methods
+/// configures one aspect of the resulting timer, which is then created with
+/// `build`.
+///
+/// # Examples
+///
+/// ```
+/// extern crate futures_timer;
+///
+/// use std::time::Duration;
+/// use futures_timer::{Timer, TestClock};
+///
+/// fn main() {
+///     let timer = Timer::builder()
+///         .clock(TestClock::new())
+///         .resolution(Duration::from_millis(10))
+///         .build();
+///     # drop(timer);
+/// }
+/// ```
+pub struct TimerBuilder {
+    clock: Box<dyn Clock>,
+    resolution: Option<Duration>,
+    coalescing: Option<Duration>,
+    slot_duration: Duration,
+    drain_budget: Option<usize>,
+    thread_name: Option<String>,
+    spin_threshold: Option<Duration>,
+}
+
+impl TimerBuilder {
+    /// Creates a new builder with the default configuration, which is the
+    /// same as that of `Timer::new`.
+    pub fn new() -> TimerBuilder {
+        TimerBuilder {
+            clock: Box::new(SystemClock),
+            resolution: None,
+            coalescing: None,
+            slot_duration: Duration::from_millis(1),
+            drain_budget: None,
+            thread_name: None,
+            spin_threshold: None,
+        }
+    }
+
+    /// Configures the clock the timer reads the current time from.
+    ///
+    /// See `Timer::with_clock` for more information.
+    pub fn clock<C: Clock + 'static>(mut self, clock: C) -> TimerBuilder {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Configures the granularity with which the timer fires timers.
+    ///
+    /// See `Timer::with_resolution` for more information. A zero resolution
+    /// means timers are fired at their exact deadlines, which is the default.
+    pub fn resolution(mut self, resolution: Duration) -> TimerBuilder {
+        self.resolution = nonzero(resolution);
+        self
+    }
+
+    /// Configures the window within which the timer coalesces deadlines so
+    /// that they fire together.
+    ///
+    /// See `Timer::with_coalescing` for more information. A zero tolerance
+    /// disables coalescing, which is the default.
+    pub fn coalescing(mut self, tolerance: Duration) -> TimerBuilder {
+        self.coalescing = nonzero(tolerance);
+        self
+    }
+
+    /// Configures the duration covered by each slot of the timer's wheel.
+    ///
+    /// Timers are bucketed into slots of this duration, but still fire at
+    /// their exact deadlines, so this only affects performance. The wheel has
+    /// a fixed number of slots, so memory use doesn't depend on this setting,
+    /// but the span of time the wheel covers does: finer slots mean less work
+    /// to pick out the timers which are due from a slot, while coarser slots
+    /// mean fewer timers have to be moved between the levels of the wheel as
+    /// time passes. Timers further out than the wheel covers, which is about
+    /// two years with the default of one millisecond, are kept in a list on
+    /// the side. A zero duration is treated as one nanosecond.
+    pub fn slot_duration(mut self, dur: Duration) -> TimerBuilder {
+        self.slot_duration = dur;
+        self
+    }
+
+    /// Configures the maximum number of timers fired by each call to
+    /// `advance`.
+    ///
+    /// Normally every expired timer is fired at once, which is what you'd
+    /// expect, but can cause a storm of wakeups when lots of timers expire
+    /// together. This happens for example after the system resumes from
+    /// suspend, as time jumps forward past the deadlines of every timer
+    /// which would have fired in the meantime. With a budget each call to
+    /// `advance` fires at most `budget` timers, in the order of their
+    /// deadlines, and leaves the rest for the following calls. Until all of
+    /// them are fired `next_event` reports an instant which has already
+    /// passed, so event loops come back to the timer right away, giving the
+    /// woken tasks a chance to run in between batches.
+    ///
+    /// A budget of zero means there's no limit, which is the default.
+    pub fn drain_budget(mut self, budget: usize) -> TimerBuilder {
+        self.drain_budget = if budget == 0 { None } else { Some(budget) };
+        self
+    }
+
+    /// Configures the remaining time below which delays spin until their
+    /// deadline instead of waiting for the timer to fire them.
+    ///
+    /// See `Timer::with_spin_threshold` for more information. A zero
+    /// threshold disables spinning, which is the default. Spinning waits for
+    /// the clock of the timer to pass the deadline, but for no longer than
+    /// the threshold in real time, so with a clock which doesn't advance on
+    /// its own, like `TestClock`, the delay is left for the timer to fire.
+    pub fn spin_threshold(mut self, threshold: Duration) -> TimerBuilder {
+        self.spin_threshold = nonzero(threshold);
+        self
+    }
+
+    /// Configures the name of the thread spawned by `spawn` to run the
+    /// timer on, which defaults to `futures-timer`.
+    pub fn thread_name<S: Into<String>>(mut self, name: S) -> TimerBuilder {
+        self.thread_name = Some(name.into());
+        self
+    }
+
+    /// Creates a timer with the configuration of this builder and spawns it
+    /// onto a helper thread of its own.
+    ///
+    /// Delays created from the handle of the returned `TimerThread` are fired
+    /// by that thread, just like the global timer does for `Delay::new`.
+    ///
+    /// The thread sleeps until the next deadline according to the configured
+    /// clock, taking the time left on that clock as the time to sleep for. A
+    /// clock which doesn't advance on its own, like `TestClock`, therefore
+    /// doesn't keep the thread busy, but moving it forward doesn't wake the
+    /// thread either: deadlines it passes fire once the thread wakes up to
+    /// check, either after sleeping or when a new delay is registered.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread couldn't be spawned.
+    pub fn spawn(mut self) -> io::Result<TimerThread> {
+        let name = self.thread_name.take()
+            .unwrap_or_else(|| DEFAULT_THREAD_NAME.to_string());
+        TimerThread::spawn(self.build(), name)
+    }
+
+    /// Creates a timer with the configuration of this builder.
+    pub fn build(self) -> Timer {
+        let origin = self.clock.now();
+        Timer {
+            inner: Arc::new(Inner {
+                list: ArcList::new(),
+                waker: AtomicWaker::new(),
+                clock: self.clock,
+                active: AtomicUsize::new(0),
+                next_seq: AtomicUsize::new(0),
+                error: Mutex::new(None),
+                wheel: Mutex::new(Wheel::new(origin, self.slot_duration)),
+                pool: (0..POOL_SHARDS).map(|_| Mutex::new(Vec::new())).collect(),
+                spin_threshold: self.spin_threshold,
+            }),
+            origin,
+            resolution: self.resolution,
+            coalescing: self.coalescing,
+            drain_budget: self.drain_budget,
+        }
+    }
+}
+
+fn nonzero(dur: Duration) -> Option<Duration> {
+    if dur == Duration::from_secs(0) {
+        None
+    } else {
+        Some(dur)
+    }
+}
+
+impl Default for TimerBuilder {
+    fn default() -> TimerBuilder {
+        TimerBuilder::new()
+    }
+}
+
+impl fmt::Debug for TimerBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TimerBuilder")
+            .field("resolution", &self.resolution)
+            .field("coalescing", &self.coalescing)
+            .field("slot_duration", &self.slot_duration)
+            .field("drain_budget", &self.drain_budget)
+            .field("thread_name", &self.thread_name)
+            .field("spin_threshold", &self.spin_threshold)
+            .finish()
+    }
+}
+
+/// Builder for a `Delay` with a non-default configuration.
+///
+/// A builder is created through `Delay::builder`. The deadline of the delay
+/// is configured with either `after` or `at`, and the coalescing tolerance of
+/// the delay with `slack`, before the delay is created with `build`.
+///
+/// # Examples
+///
+/// ```
+/// extern crate futures_timer;
+///
+/// use std::time::Duration;
+/// use futures_timer::Delay;
+///
+/// fn main() {
+///     // A bulk timeout which may fire up to a second late, so that it can
+///     // share a wakeup of the timer with others.
+///     let delay = Delay::builder()
+///         .after(Duration::from_secs(30))
+///         .slack(Duration::from_secs(1))
+///         .build();
+///     # drop(delay);
+/// }
+/// ```
+pub struct DelayBuilder {
+    when: When,
+    slack: Option<Duration>,
+    handle: Option<TimerHandle>,
+}
+
+enum When {
+    After(Duration),
+    At(Instant),
+}
+
+impl DelayBuilder {
+    /// Creates a new builder for a delay which fires right away, on the
+    /// default timer and with the timer's coalescing tolerance.
+    pub fn new() -> DelayBuilder {
+        DelayBuilder {
+            when: When::After(Duration::from_secs(0)),
+            slack: None,
+            handle: None,
+        }
+    }
+
+    /// Configures the delay to fire `dur` after it's built, according to
+    /// the clock of its timer.
+    pub fn after(mut self, dur: Duration) -> DelayBuilder {
+        self.when = When::After(dur);
+        self
+    }
+
+    /// Configures the delay to fire at the instant `at`.
+    pub fn at(mut self, at: Instant) -> DelayBuilder {
+        self.when = When::At(at);
+        self
+    }
+
+    /// Configures how late the delay may fire.
+    ///
+    /// The deadline of the delay is rounded up to the next multiple of
+    /// `slack`, just like `Timer::with_coalescing` does for all delays, so it
+    /// shares a wakeup of the timer with other delays set to fire within the
+    /// same window. This overrides the coalescing tolerance of the timer for
+    /// this delay alone: bulk timeouts can opt into coalescing on a timer
+    /// which doesn't otherwise coalesce, while a zero slack means exact
+    /// scheduling even on a timer which does.
+    pub fn slack(mut self, slack: Duration) -> DelayBuilder {
+        self.slack = Some(slack);
+        self
+    }
+
+    /// Configures the timer the delay is bound to, rather than the default
+    /// timer.
+    pub fn handle(mut self, handle: TimerHandle) -> DelayBuilder {
+        self.handle = Some(handle);
+        self
+    }
+
+    /// Creates a delay with the configuration of this builder.
+    pub fn build(self) -> Delay {
+        let handle = self.handle.unwrap_or_default();
+        let at = match self.when {
+            When::After(dur) => delay::after(handle.now(), dur),
+            When::At(at) => at,
+        };
+        Delay::new_slack(at, self.slack, handle)
+    }
+}
+
+impl Default for DelayBuilder {
+    fn default() -> DelayBuilder {
+        DelayBuilder::new()
+    }
+}
+
+impl fmt::Debug for DelayBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut f = f.debug_struct("DelayBuilder");
+        match self.when {
+            When::After(dur) => f.field("after", &dur),
+            When::At(at) => f.field("at", &at),
+        };
+        f.field("slack", &self.slack).finish()
+    }
+}
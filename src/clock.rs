@@ -0,0 +1,92 @@
+//! Sources of the current time used to drive a `Timer`.
+//!
+//! Every `Timer` consults a `Clock` to learn what time it is, which defaults
+//! to the `SystemClock` reading `Instant::now()`. Tests can instead use a
+//! `TestClock` which only moves forward when told to.
+
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A source of the current time for a `Timer`.
+///
+/// The instants returned by a clock are expected to never go backwards.
+pub trait Clock: Send + Sync {
+    /// Returns the current instant according to this clock.
+    fn now(&self) -> Instant;
+
+    /// Returns the current instant according to this clock, or the error
+    /// encountered reading the underlying time source.
+    ///
+    /// This is what a `Timer` consults whenever it fires the timers which
+    /// have expired, so clocks backed by a source which can fail, such as a
+    /// hardware counter or a platform call, should override it. Once it
+    /// returns an error the timer fails all of its pending delays with that
+    /// error, and keeps doing so for new delays until the clock recovers,
+    /// rather than leaving them to never fire. The default implementation
+    /// never fails.
+    fn try_now(&self) -> io::Result<Instant> {
+        Ok(self.now())
+    }
+}
+
+/// A shared `Clock`, so that a clock can be handed to a `Timer` while still
+/// being used elsewhere.
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+
+    fn try_now(&self) -> io::Result<Instant> {
+        (**self).try_now()
+    }
+}
+
+/// A `Clock` backed by `Instant::now()`.
+///
+/// This is the clock used by `Timer::new` and the global timer.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A `Clock` whose time only advances when explicitly told to.
+///
+/// A `TestClock` starts out at the instant it was created and is moved
+/// forward with the `advance` method. Clones of a `TestClock` share the same
+/// notion of time, so one clone can be handed to a `Timer` while another is
+/// kept around to drive it.
+#[derive(Clone, Debug)]
+pub struct TestClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl TestClock {
+    /// Creates a new clock frozen at the current instant.
+    pub fn new() -> TestClock {
+        TestClock {
+            now: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Moves the time of this clock, and all of its clones, forward by `dur`.
+    pub fn advance(&self, dur: Duration) {
+        *self.now.lock().unwrap() += dur;
+    }
+}
+
+impl Default for TestClock {
+    fn default() -> TestClock {
+        TestClock::new()
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+}
@@ -0,0 +1,115 @@
+//! Interoperability with `std::future::Future`, so that the timers of this
+//! crate can be used with `async`/`.await`.
+
+use std::future::Future as StdFuture;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{self as std_task, Poll as StdPoll};
+
+use futures::{Future, Async};
+use futures::task;
+
+use Delay;
+
+/// Adapter implementing `std::future::Future` for a future of this crate,
+/// created through `FutureExt::compat`.
+///
+/// The output of the adapter is the `Result` the adapted future resolves to.
+/// The adapted future is polled with a context which has no executor
+/// attached, so it mustn't try to spawn any tasks.
+///
+/// # Examples
+///
+/// ```edition2018
+/// use std::io;
+/// use std::time::Duration;
+/// use futures_timer::FutureExt;
+///
+/// async fn request() -> io::Result<u32> {
+///     let response = futures::future::ok::<u32, io::Error>(42);
+///     response.timeout(Duration::from_secs(1)).compat().await
+/// }
+/// # drop(request());
+/// ```
+#[derive(Debug)]
+pub struct Compat<F> {
+    future: F,
+}
+
+impl<F> Compat<F> {
+    pub(crate) fn new(future: F) -> Compat<F> {
+        Compat { future }
+    }
+
+    /// Returns a shared reference to the adapted future.
+    pub fn get_ref(&self) -> &F {
+        &self.future
+    }
+
+    /// Returns a mutable reference to the adapted future.
+    pub fn get_mut(&mut self) -> &mut F {
+        &mut self.future
+    }
+
+    /// Consumes this adapter, returning the adapted future.
+    pub fn into_inner(self) -> F {
+        self.future
+    }
+}
+
+impl<F: Future + Unpin> StdFuture for Compat<F> {
+    type Output = Result<F::Item, F::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut std_task::Context) -> StdPoll<Self::Output> {
+        poll_compat(&mut self.get_mut().future, cx)
+    }
+}
+
+/// Waits for the `Delay` to fire from within `async` code.
+///
+/// Should the timer associated with the `Delay` go away then it resolves
+/// right away, in the same way that the timeout combinators consider their
+/// timeout elapsed in that case. Use `FutureExt::compat` to observe the error
+/// instead.
+///
+/// # Examples
+///
+/// ```edition2018
+/// use std::time::Duration;
+/// use futures_timer::Delay;
+///
+/// async fn tick() {
+///     Delay::new(Duration::from_millis(10)).await;
+/// }
+/// # drop(tick());
+/// ```
+impl StdFuture for Delay {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut std_task::Context) -> StdPoll<()> {
+        poll_compat(self.get_mut(), cx).map(|_| ())
+    }
+}
+
+/// Forwards the wakeups of a future of this crate to the task of an `async`
+/// executor.
+struct StdWaker(std_task::Waker);
+
+impl task::Wake for StdWaker {
+    fn wake(arc_self: &Arc<Self>) {
+        arc_self.0.wake_by_ref()
+    }
+}
+
+fn poll_compat<F: Future>(future: &mut F, cx: &mut std_task::Context)
+    -> StdPoll<Result<F::Item, F::Error>>
+{
+    let waker = task::Waker::from(Arc::new(StdWaker(cx.waker().clone())));
+    let mut map = task::LocalMap::new();
+    let mut cx = task::Context::without_spawn(&mut map, &waker);
+    match future.poll(&mut cx) {
+        Ok(Async::Ready(item)) => StdPoll::Ready(Ok(item)),
+        Ok(Async::Pending) => StdPoll::Pending,
+        Err(e) => StdPoll::Ready(Err(e)),
+    }
+}
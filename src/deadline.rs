@@ -0,0 +1,233 @@
+//! Support for a deadline which is shared between many futures.
+
+use std::fmt;
+use std::io;
+use std::mem;
+use std::sync::{Arc, Mutex, Weak};
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
+use std::time::{Duration, Instant};
+
+use futures::{Future, Poll, Async};
+use futures::task::{self, Waker};
+
+use {Delay, TimerHandle};
+use delay;
+
+/// A deadline which can be shared between any number of futures, for example
+/// to abort all of them together once it has passed.
+///
+/// Clones of a `Deadline` all refer to the same deadline, which is backed by
+/// a single timer registration no matter how many clones there are or how
+/// many tasks are waiting on them. Once the deadline fires every task which
+/// polled any of the clones is woken.
+///
+/// A `Deadline` is itself a future which resolves once the deadline has
+/// passed, and the `poll_elapsed` method can be used to check on it from
+/// within the implementation of another future.
+///
+/// # Examples
+///
+/// ```
+/// extern crate futures;
+/// extern crate futures_timer;
+///
+/// use std::time::Duration;
+/// use futures::future;
+/// use futures::executor::block_on;
+/// use futures_timer::Deadline;
+///
+/// fn main() {
+///     let deadline = Deadline::new(Duration::from_millis(10));
+///     let waiters = (0..3).map(|_| deadline.clone());
+///     block_on(future::join_all(waiters)).unwrap();
+///     assert!(deadline.is_elapsed());
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Deadline {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    when: Instant,
+    fired: AtomicBool,
+
+    /// Tasks waiting for the deadline to fire.
+    wakers: Mutex<Wakers>,
+
+    /// The delay backing this deadline, polled with a waker which wakes all
+    /// of the tasks in `wakers`.
+    state: Mutex<State>,
+}
+
+/// A set of wakers which only allocates once it holds more than one, as
+/// usually just a single task waits on a deadline.
+#[derive(Default)]
+struct Wakers {
+    first: Option<Waker>,
+    rest: Vec<Waker>,
+}
+
+struct State {
+    delay: Delay,
+    fanout: Option<Waker>,
+}
+
+struct Fanout {
+    shared: Weak<Shared>,
+}
+
+impl Deadline {
+    /// Creates a new deadline which passes `dur` time into the future.
+    ///
+    /// The returned object will be bound to the default timer for this thread.
+    /// The default timer will be spun up in a helper thread on first use.
+    pub fn new(dur: Duration) -> Deadline {
+        let handle = TimerHandle::default();
+        Deadline::new_handle(delay::after(handle.now(), dur), handle)
+    }
+
+    /// Creates a new deadline which passes at the time specified by `at`.
+    ///
+    /// The returned object will be bound to the default timer for this thread.
+    /// The default timer will be spun up in a helper thread on first use.
+    pub fn new_at(at: Instant) -> Deadline {
+        Deadline::new_handle(at, TimerHandle::default())
+    }
+
+    /// Creates a new deadline which passes at the time specified by `at`,
+    /// bound to the timer specified by `handle`.
+    pub fn new_handle(at: Instant, handle: TimerHandle) -> Deadline {
+        Deadline::from(Delay::new_handle(at, handle))
+    }
+
+    /// Returns the instant at which this deadline passes.
+    pub fn deadline(&self) -> Instant {
+        self.shared.when
+    }
+
+    /// Returns whether this deadline has been observed to pass by any of its
+    /// clones.
+    pub fn is_elapsed(&self) -> bool {
+        self.shared.fired.load(SeqCst)
+    }
+
+    /// Polls whether this deadline has passed, arranging for the current task
+    /// to be woken once it does otherwise.
+    ///
+    /// This can be called by any number of tasks through any of the clones of
+    /// this deadline, and all of them are woken when the deadline passes.
+    ///
+    /// # Errors
+    ///
+    /// Like `Delay` this returns an error if the associated timer has gone
+    /// away.
+    pub fn poll_elapsed(&self, cx: &mut task::Context) -> Poll<(), io::Error> {
+        if self.is_elapsed() {
+            return Ok(Async::Ready(()))
+        }
+        self.shared.wakers.lock().unwrap().insert(cx.waker());
+
+        let mut state = self.shared.state.lock().unwrap();
+        let State { ref mut delay, ref mut fanout } = *state;
+        let fanout = fanout.get_or_insert_with(|| {
+            Waker::from(Arc::new(Fanout { shared: Arc::downgrade(&self.shared) }))
+        });
+        let res = delay.poll(&mut cx.with_waker(fanout));
+        drop(state);
+
+        match res {
+            Ok(Async::Pending) => return Ok(Async::Pending),
+            Ok(Async::Ready(())) => self.shared.fired.store(true, SeqCst),
+            Err(_) => {}
+        }
+        self.shared.wake_all();
+        res
+    }
+
+    /// Returns the current time according to the clock of the timer this
+    /// deadline is associated with.
+    pub(crate) fn now(&self) -> Instant {
+        delay::now(&self.shared.state.lock().unwrap().delay)
+    }
+
+    /// Returns the delay backing this deadline if this is its only clone.
+    pub(crate) fn try_into_delay(self) -> Result<Delay, Deadline> {
+        match Arc::try_unwrap(self.shared) {
+            Ok(shared) => Ok(shared.state.into_inner().unwrap().delay),
+            Err(shared) => Err(Deadline { shared }),
+        }
+    }
+}
+
+impl Shared {
+    fn wake_all(&self) {
+        let wakers = mem::take(&mut *self.wakers.lock().unwrap());
+        for waker in wakers.first.into_iter().chain(wakers.rest) {
+            waker.wake();
+        }
+    }
+}
+
+impl Wakers {
+    fn insert(&mut self, waker: &Waker) {
+        let first = match self.first {
+            Some(ref first) => first,
+            None => {
+                self.first = Some(waker.clone());
+                return
+            }
+        };
+        if !first.will_wake(waker) && !self.rest.iter().any(|w| w.will_wake(waker)) {
+            self.rest.push(waker.clone());
+        }
+    }
+}
+
+impl task::Wake for Fanout {
+    fn wake(arc_self: &Arc<Self>) {
+        if let Some(shared) = arc_self.shared.upgrade() {
+            shared.wake_all();
+        }
+    }
+}
+
+/// Shares an existing `Delay` as a `Deadline`, which passes once the delay
+/// fires.
+///
+/// This allows a delay to be waited on from any number of tasks at once,
+/// all of which are woken when it fires.
+impl From<Delay> for Deadline {
+    fn from(delay: Delay) -> Deadline {
+        Deadline {
+            shared: Arc::new(Shared {
+                when: delay.deadline(),
+                fired: AtomicBool::new(false),
+                wakers: Mutex::new(Wakers::default()),
+                state: Mutex::new(State {
+                    delay,
+                    fanout: None,
+                }),
+            }),
+        }
+    }
+}
+
+impl Future for Deadline {
+    type Item = ();
+    type Error = io::Error;
+
+    fn poll(&mut self, cx: &mut task::Context) -> Poll<(), io::Error> {
+        self.poll_elapsed(cx)
+    }
+}
+
+impl fmt::Debug for Deadline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Deadline")
+            .field("when", &self.shared.when)
+            .field("fired", &self.is_elapsed())
+            .finish()
+    }
+}
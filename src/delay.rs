@@ -3,17 +3,18 @@
 //! This module contains the `Delay` type which is a future that will resolve
 //! at a particular point in the future.
 
+use std::fmt;
 use std::io;
+use std::mem;
 use std::sync::{Arc, Mutex};
-use std::sync::atomic::AtomicUsize;
-use std::sync::atomic::Ordering::SeqCst;
+use std::thread;
 use std::time::{Duration, Instant};
 
-use futures::{Future, Poll, Async};
-use futures::task::{self, AtomicWaker};
+use futures::{Future, FutureExt, Poll, Stream};
+use futures::task;
 
-use arc_list::Node;
-use {TimerHandle, ScheduledTimer};
+use global::ThreadUnpark;
+use {Deadline, DelayBuilder, Registration, TimerHandle};
 
 /// A future representing the notification that an elapsed duration has
 /// occurred.
@@ -23,22 +24,59 @@ use {TimerHandle, ScheduledTimer};
 /// intended for high resolution timers, but rather they will likely fire some
 /// granularity after the exact instant that they're otherwise indicated to
 /// fire at.
+///
+/// Polling a `Delay` before it fires only updates which task is woken once it
+/// does, without involving the timer, so a task which is woken for unrelated
+/// reasons can poll it as often as it likes. Only the task which polled most
+/// recently is woken though, so a `Delay` which is waited on from several
+/// tasks at once should be cloned, or converted into a `Deadline`, first.
+///
+/// Cloning a `Delay` shares its registration with the timer between the
+/// clones, turning it into a `Deadline` under the hood, and every task which
+/// polled any of the clones is woken once it fires. Shared delays can't be
+/// moved to another deadline though, resetting one panics unless all of its
+/// other clones have been dropped.
+///
+/// Dropping a `Delay` before it fires cancels it right away. It stops
+/// counting towards `TimerHandle::active_timers` immediately, and the timer
+/// forgets about its deadline the next time it processes updates rather than
+/// once the deadline passes.
+///
+/// A `Delay` holds no self-referential state, so it is `Unpin` and can be
+/// moved freely, even after it has been polled.
+///
+/// A `Delay` is a thin wrapper implementing `Future` for a `Registration`,
+/// which can be used directly to build custom timer types.
 pub struct Delay {
-    state: Option<Arc<Node<ScheduledTimer>>>,
-    when: Instant,
+    // Only locked by the methods taking `&self`, polling goes through
+    // `get_mut`.
+    repr: Mutex<Repr>,
+}
+
+enum Repr {
+    Unique(Registration),
+    Shared(Deadline),
 }
 
 impl Delay {
     /// Creates a new future which will fire at `dur` time into the future.
     ///
+    /// If `dur` is so large that the deadline can't be represented then the
+    /// deadline is clamped to an instant far enough into the future that the
+    /// returned `Delay` will never fire in practice.
+    ///
     /// The returned object will be bound to the default timer for this thread.
     /// The default timer will be spun up in a helper thread on first use.
     pub fn new(dur: Duration) -> Delay {
-        Delay::new_at(Instant::now() + dur)
+        let handle = TimerHandle::default();
+        Delay::new_handle(after(handle.now(), dur), handle)
     }
 
     /// Creates a new future which will fire at the time specified by `at`.
     ///
+    /// If `at` has already passed then the returned `Delay` is ready the first
+    /// time it's polled, without registering with the timer at all.
+    ///
     /// The returned object will be bound to the default timer for this thread.
     /// The default timer will be spun up in a helper thread on first use.
     pub fn new_at(at: Instant) -> Delay {
@@ -50,38 +88,161 @@ impl Delay {
     /// The returned instance of `Delay` will be bound to the timer specified by
     /// the `handle` argument.
     pub fn new_handle(at: Instant, handle: TimerHandle) -> Delay {
-        let inner = match handle.inner.upgrade() {
-            Some(i) => i,
-            None => return Delay { state: None, when: at },
-        };
-        let state = Arc::new(Node::new(ScheduledTimer {
-            at: Mutex::new(Some(at)),
-            state: AtomicUsize::new(0),
-            waker: AtomicWaker::new(),
-            inner: handle.inner,
-            slot: Mutex::new(None),
-        }));
-
-        // If we fail to actually push our node then we've become an inert
-        // timer, meaning that we'll want to immediately return an error from
-        // `poll`.
-        if inner.list.push(&state).is_err() {
-            return Delay { state: None, when: at }
+        Delay::from_registration(Registration::new(at, handle))
+    }
+
+    /// Creates a builder for a `Delay` with a non-default configuration,
+    /// such as a coalescing tolerance of its own.
+    pub fn builder() -> DelayBuilder {
+        DelayBuilder::new()
+    }
+
+    /// Creates a new future which fires at `at`, coalesced with a tolerance
+    /// of `slack` rather than that of the timer if given.
+    pub(crate) fn new_slack(at: Instant,
+                            slack: Option<Duration>,
+                            handle: TimerHandle) -> Delay {
+        Delay::from_registration(Registration::new_slack(at, slack, handle))
+    }
+
+    /// Creates a new future which never fires.
+    ///
+    /// The returned `Delay` doesn't register with any timer, and polling it
+    /// always returns `Pending` without arranging for the task to be woken.
+    /// This is a cheap way to represent the absence of a timeout, for
+    /// example when a combinator is passed `None` for an optional duration.
+    /// Resetting the returned `Delay` binds it to the default timer.
+    pub fn never() -> Delay {
+        Delay::from_registration(Registration::never())
+    }
+
+    fn from_registration(registration: Registration) -> Delay {
+        Delay { repr: Mutex::new(Repr::Unique(registration)) }
+    }
+
+    /// Returns the instant at which this `Delay` is scheduled to fire.
+    pub fn deadline(&self) -> Instant {
+        match *self.repr.lock().unwrap() {
+            Repr::Unique(ref registration) => registration.deadline(),
+            Repr::Shared(ref deadline) => deadline.deadline(),
         }
+    }
+
+    /// Returns the amount of time left until the deadline of this `Delay`,
+    /// or a zero duration if the deadline has already passed.
+    pub fn remaining(&self) -> Duration {
+        self.deadline().saturating_duration_since(now(self))
+    }
 
-        inner.waker.wake();
-        Delay {
-            state: Some(state),
-            when: at,
+    /// Returns how long ago the deadline of this `Delay` passed, according
+    /// to the clock of the associated timer, or a zero duration if it hasn't
+    /// passed yet.
+    ///
+    /// Checked right after this `Delay` resolves, this reveals how late the
+    /// timer fired and the task got polled, which is handy to log scheduling
+    /// lag alongside `deadline`.
+    pub fn overrun(&self) -> Duration {
+        now(self).saturating_duration_since(self.deadline())
+    }
+
+    /// Returns whether the deadline of this `Delay` has passed.
+    ///
+    /// This is a cheap check of the current time, according to the clock of
+    /// the associated timer, against the deadline and doesn't require a task
+    /// context. Note that a `true` result doesn't imply
+    /// that the timer has fired and woken the task blocked on this `Delay`,
+    /// only that the deadline itself is in the past.
+    pub fn is_elapsed(&self) -> bool {
+        now(self) >= self.deadline()
+    }
+
+    /// Returns whether this `Delay` has fired, or its deadline has passed,
+    /// without registering the current task to be woken.
+    ///
+    /// This is meant for combinators which arrange for their own wakeups and
+    /// just need to know whether the deadline is behind them. It neither
+    /// touches the registration with the timer nor stores a waker, so it
+    /// doesn't need a task context either.
+    ///
+    /// Beware that as a result nothing wakes the current task once the
+    /// deadline passes. A task which only ever checks this `Delay` through
+    /// this method, and isn't woken for other reasons, is never polled again.
+    /// Polling the `Delay` as a future is what arranges for the wakeup.
+    pub fn poll_no_register(&mut self) -> bool {
+        match *self.repr.get_mut().unwrap() {
+            Repr::Unique(ref mut registration) => registration.poll_no_register(),
+            Repr::Shared(ref deadline) => {
+                deadline.is_elapsed() || deadline.now() >= deadline.deadline()
+            }
         }
     }
 
+    /// Converts this `Delay` into a stream which yields a single `()` once
+    /// the delay fires and ends after that.
+    ///
+    /// This allows a delay to take part in combinators over streams, such as
+    /// `select`, without hand-writing the adapter. An error from the delay is
+    /// yielded by the stream instead.
+    pub fn into_stream(self) -> impl Stream<Item = (), Error = io::Error> {
+        FutureExt::into_stream(self)
+    }
+
+    /// Blocks the current thread until this `Delay` fires.
+    ///
+    /// This is intended for synchronous code which needs to wait for a
+    /// deadline, and unlike `thread::sleep` it relies on the associated timer
+    /// to wake the thread back up, so the delay is subject to the timer's
+    /// configuration, such as its coalescing, just like any other `Delay`.
+    /// The thread is parked rather than spinning while it waits.
+    ///
+    /// This must not be called from within a future or on an executor
+    /// thread, as it blocks all other tasks on that thread as well. Nor may
+    /// it be called on the thread driving the associated timer, as that
+    /// thread would then never get to fire the delay.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the associated timer has gone away, just like
+    /// polling this `Delay` would.
+    pub fn wait(mut self) -> io::Result<()> {
+        let waker = task::Waker::from(Arc::new(ThreadUnpark::current()));
+        let mut map = task::LocalMap::new();
+        let mut cx = task::Context::without_spawn(&mut map, &waker);
+        while self.poll(&mut cx)?.is_pending() {
+            thread::park();
+        }
+        Ok(())
+    }
+
     /// Resets this timeout to an new timeout which will fire at the time
     /// specified by `dur`.
     ///
-    /// This is equivalent to calling `reset_at` with `Instant::now() + dur`
+    /// This is equivalent to calling `reset_at` with the current time of the
+    /// associated timer's clock plus `dur`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this `Delay` has been cloned and any of its clones is still
+    /// alive, see `reset_at`.
     pub fn reset(&mut self, dur: Duration) {
-        self.reset_at(Instant::now() + dur)
+        self.unique().reset(dur)
+    }
+
+    /// Resets this timeout like `reset`, returning whether the deadline it
+    /// was waiting for had already passed.
+    ///
+    /// Both the check and the new deadline are based on a single reading of
+    /// the associated timer's clock, so unlike calling `is_elapsed` followed
+    /// by `reset` no time can pass in between. This is handy in loops which
+    /// reuse a `Delay` per iteration and want to detect iterations which ran
+    /// over their time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this `Delay` has been cloned and any of its clones is still
+    /// alive, see `reset_at`.
+    pub fn reset_checked(&mut self, dur: Duration) -> bool {
+        self.unique().reset_checked(dur)
     }
 
     /// Resets this timeout to an new timeout which will fire at the time
@@ -94,47 +255,84 @@ impl Delay {
     /// If `at` is in the past then this future will immediately be resolved
     /// (when `poll` is called).
     ///
+    /// The existing registration with the timer is reused rather than
+    /// allocating a new one, and the timer is notified so that an earlier
+    /// deadline is picked up right away.
+    ///
     /// Note that if any task is currently blocked on this future then that task
     /// will be dropped. It is required to call `poll` again after this method
     /// has been called to ensure tha ta task is blocked on this future.
+    ///
+    /// # Panics
+    ///
+    /// Resetting is only allowed while this `Delay` is uniquely owned, as
+    /// moving a deadline which is shared with other clones would move it for
+    /// all of them behind their backs. This panics if this `Delay` has been
+    /// cloned and any of its clones is still alive. Once all other clones are
+    /// dropped the `Delay` can be reset again.
     pub fn reset_at(&mut self, at: Instant) {
-        self.when = at;
-        if self._reset(at).is_err() {
-            self.state = None
-        }
+        self.unique().reset_at(at)
     }
 
-    fn _reset(&mut self, at: Instant) -> Result<(), ()> {
-        let state = match self.state {
-            Some(ref state) => state,
-            None => return Err(()),
-        };
-        if let Some(timeouts) = state.inner.upgrade() {
-            let mut bits = state.state.load(SeqCst);
-            loop {
-                // If we've been invalidated, cancel this reset
-                if bits & 0b10 != 0 {
-                    return Err(())
-                }
-                let new = bits.wrapping_add(0b100) & !0b11;
-                match state.state.compare_exchange(bits, new, SeqCst, SeqCst) {
-                    Ok(_) => break,
-                    Err(s) => bits = s,
+    /// Returns the registration of this `Delay`, taking it back from the
+    /// deadline it's shared through if all other clones are gone.
+    fn unique(&mut self) -> &mut Registration {
+        let repr = self.repr.get_mut().unwrap();
+        if let Repr::Shared(_) = *repr {
+            let deadline = match mem::replace(repr, Repr::Unique(Registration::never())) {
+                Repr::Shared(deadline) => deadline,
+                Repr::Unique(_) => unreachable!(),
+            };
+            match deadline.try_into_delay() {
+                Ok(delay) => *repr = delay.repr.into_inner().unwrap(),
+                Err(deadline) => {
+                    *repr = Repr::Shared(deadline);
+                    panic!("cannot reset a `Delay` whose clones are still alive");
                 }
             }
-            *state.at.lock().unwrap() = Some(at);
-            // If we fail to push our node then we've become an inert timer, so
-            // we'll want to clear our `state` field accordingly
-            timeouts.list.push(state)?;
-            timeouts.waker.wake();
         }
+        match *repr {
+            Repr::Unique(ref mut registration) => registration,
+            Repr::Shared(_) => unreachable!(),
+        }
+    }
+}
 
-        Ok(())
+/// Creates a new future which will fire at `dur` time into the future.
+///
+/// This is an alias for `Delay::new`, which remains the canonical way to
+/// create a `Delay`, provided for those who expect a timer crate to have a
+/// `sleep` function.
+pub fn sleep(dur: Duration) -> Delay {
+    Delay::new(dur)
+}
+
+/// Creates a new future which will fire at the time specified by `at`.
+///
+/// This is an alias for `Delay::new_at`, see `sleep` for more information.
+pub fn sleep_until(at: Instant) -> Delay {
+    Delay::new_at(at)
+}
+
+/// Returns the current time according to the clock of the timer `delay` is
+/// associated with, or `Instant::now()` if there's no such timer.
+///
+/// For a `Delay::never` that's the default timer, which it's bound to once
+/// it's reset.
+pub fn now(delay: &Delay) -> Instant {
+    match *delay.repr.lock().unwrap() {
+        Repr::Unique(ref registration) => registration.now(),
+        Repr::Shared(ref deadline) => deadline.now(),
     }
 }
 
-pub fn fires_at(timeout: &Delay) -> Instant {
-    timeout.when
+/// Returns the instant `dur` after `now`, saturating to a far-future instant if
+/// the sum overflows.
+pub fn after(now: Instant, dur: Duration) -> Instant {
+    now.checked_add(dur).unwrap_or_else(|| {
+        // Roughly thirty years, which should be representable everywhere.
+        now + Duration::from_secs(86400 * 365 * 30)
+    })
 }
 
 impl Future for Delay {
@@ -142,40 +340,93 @@ impl Future for Delay {
     type Error = io::Error;
 
     fn poll(&mut self, cx: &mut task::Context) -> Poll<(), io::Error> {
-        let state = match self.state {
-            Some(ref state) => state,
-            None => return Err(io::Error::new(io::ErrorKind::Other,
-                                              "timer has gone away")),
-        };
-        if state.state.load(SeqCst) & 1 != 0 {
-            return Ok(Async::Ready(()))
+        match *self.repr.get_mut().unwrap() {
+            Repr::Unique(ref mut registration) => registration.poll(cx),
+            Repr::Shared(ref deadline) => deadline.poll_elapsed(cx),
         }
+    }
+}
 
-        state.waker.register(cx.waker());
-
-        // Now that we've registered, do the full check of our own internal
-        // state. If we've fired the first bit is set, and if we've been
-        // invalidated the second bit is set.
-        match state.state.load(SeqCst) {
-            n if n & 0b01 != 0 => Ok(Async::Ready(())),
-            n if n & 0b10 != 0 => Err(io::Error::new(io::ErrorKind::Other,
-                                                     "timer has gone away")),
-            _ => Ok(Async::Pending),
+/// Creates another `Delay` for the same deadline, sharing the registration
+/// with the timer.
+///
+/// The first clone turns this `Delay` into a shared one, after which both
+/// it and its clones are backed by a `Deadline`, and each of them wakes the
+/// task which polled it once the deadline passes. Shared delays can't be
+/// reset for as long as more than one of them is alive, see `reset_at`.
+impl Clone for Delay {
+    fn clone(&self) -> Delay {
+        let mut repr = self.repr.lock().unwrap();
+        if let Repr::Unique(_) = *repr {
+            let unique = mem::replace(&mut *repr, Repr::Unique(Registration::never()));
+            let delay = Delay { repr: Mutex::new(unique) };
+            *repr = Repr::Shared(Deadline::from(delay));
         }
+        let deadline = match *repr {
+            Repr::Shared(ref deadline) => deadline.clone(),
+            Repr::Unique(_) => unreachable!(),
+        };
+        Delay { repr: Mutex::new(Repr::Shared(deadline)) }
     }
 }
 
-impl Drop for Delay {
-    fn drop(&mut self) {
-        let state = match self.state {
-            Some(ref s) => s,
-            None => return,
-        };
-        if let Some(timeouts) = state.inner.upgrade() {
-            *state.at.lock().unwrap() = None;
-            if timeouts.list.push(state).is_ok() {
-                timeouts.waker.wake();
-            }
-        }
+/// Creates a `Delay` which fires `dur` time into the future, equivalent to
+/// `Delay::new`.
+///
+/// # Examples
+///
+/// ```
+/// extern crate futures_timer;
+///
+/// use std::time::Duration;
+/// use futures_timer::Delay;
+///
+/// fn main() {
+///     let delay: Delay = Duration::from_secs(1).into();
+///     # drop(delay);
+/// }
+/// ```
+impl From<Duration> for Delay {
+    fn from(dur: Duration) -> Delay {
+        Delay::new(dur)
+    }
+}
+
+/// Creates a `Delay` which fires at the instant `at`, equivalent to
+/// `Delay::new_at`.
+///
+/// Together with the conversion from `Duration` this allows functions to
+/// accept either a relative or an absolute time through `impl Into<Delay>`.
+///
+/// # Examples
+///
+/// ```
+/// extern crate futures_timer;
+///
+/// use std::time::{Duration, Instant};
+/// use futures_timer::Delay;
+///
+/// fn wait_until(delay: impl Into<Delay>) -> Delay {
+///     delay.into()
+/// }
+///
+/// fn main() {
+///     let relative = wait_until(Duration::from_secs(1));
+///     let absolute = wait_until(Instant::now() + Duration::from_secs(1));
+///     # drop((relative, absolute));
+/// }
+/// ```
+impl From<Instant> for Delay {
+    fn from(at: Instant) -> Delay {
+        Delay::new_at(at)
+    }
+}
+
+impl fmt::Debug for Delay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Delay")
+            .field("when", &self.deadline())
+            .field("elapsed", &self.is_elapsed())
+            .finish()
     }
 }
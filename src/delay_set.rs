@@ -0,0 +1,264 @@
+//! Support for tracking many keyed deadlines with a single timer registration.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::io;
+use std::time::{Duration, Instant};
+
+use futures::prelude::*;
+use futures::task::Waker;
+
+use heap::{Heap, Slot};
+use {Delay, TimerHandle};
+use delay;
+
+/// A set of deadlines, each identified by a key, which yields the keys of
+/// the deadlines as they pass.
+///
+/// Rather than registering a timer for every deadline, a `DelaySet` keeps its
+/// deadlines in a heap of its own and only registers the earliest of them
+/// with the timer. This makes it suitable for tracking a large number of
+/// deadlines which are frequently reset or removed before they pass, such as
+/// per-connection idle timeouts, within a single task.
+///
+/// Deadlines are added with `insert`, and can be moved with `reset` or
+/// dropped with `remove`. The keys of passed deadlines are then retrieved
+/// with `poll_expired`, or through the `Stream` implementation, in the order
+/// of their deadlines. Each deadline is yielded once, after which its key is
+/// no longer part of the set.
+///
+/// # Examples
+///
+/// ```
+/// extern crate futures;
+/// extern crate futures_timer;
+///
+/// use std::time::Duration;
+/// use futures::prelude::*;
+/// use futures::executor::block_on;
+/// use futures_timer::DelaySet;
+///
+/// fn main() {
+///     let mut set = DelaySet::new();
+///     set.insert("slow", Duration::from_millis(20));
+///     set.insert("fast", Duration::from_millis(10));
+///
+///     let keys: Vec<_> = block_on(set.collect()).unwrap();
+///     assert_eq!(keys, vec!["fast", "slow"]);
+/// }
+/// ```
+pub struct DelaySet<K> {
+    heap: Heap<Entry<K>>,
+    slots: HashMap<K, (Slot, Instant)>,
+    handle: TimerHandle,
+
+    // Registered for the earliest deadline in `heap`, once there's been one.
+    delay: Option<Delay>,
+
+    // The task which last saw no deadline pass, in case a deadline is added
+    // which has passed already and so won't be fired by the timer.
+    waker: Option<Waker>,
+    next_seq: u64,
+}
+
+struct Entry<K> {
+    at: Instant,
+    seq: u64,
+    key: K,
+}
+
+impl<K: Hash + Eq + Clone> DelaySet<K> {
+    /// Creates a new, empty set of deadlines.
+    ///
+    /// The returned object will be bound to the default timer for this thread.
+    /// The default timer will be spun up in a helper thread on first use.
+    pub fn new() -> DelaySet<K> {
+        DelaySet::new_handle(TimerHandle::default())
+    }
+
+    /// Creates a new, empty set of deadlines bound to the timer specified by
+    /// `handle`.
+    pub fn new_handle(handle: TimerHandle) -> DelaySet<K> {
+        DelaySet {
+            heap: Heap::new(),
+            slots: HashMap::new(),
+            handle,
+            delay: None,
+            waker: None,
+            next_seq: 0,
+        }
+    }
+
+    /// Adds a deadline for `key` which passes `dur` time into the future,
+    /// according to the clock of the associated timer.
+    ///
+    /// If `key` has a deadline already then it's replaced.
+    pub fn insert(&mut self, key: K, dur: Duration) {
+        let at = delay::after(self.handle.now(), dur);
+        self.insert_at(key, at)
+    }
+
+    /// Adds a deadline for `key` which passes at the instant `at`.
+    ///
+    /// If `key` has a deadline already then it's replaced.
+    pub fn insert_at(&mut self, key: K, at: Instant) {
+        if let Some((slot, _)) = self.slots.remove(&key) {
+            self.heap.remove(slot);
+        }
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let slot = self.heap.push(Entry { at, seq, key: key.clone() });
+        self.slots.insert(key, (slot, at));
+        self.update();
+    }
+
+    /// Moves the deadline of `key` to `dur` time into the future, returning
+    /// whether `key` had a deadline to move.
+    pub fn reset(&mut self, key: &K, dur: Duration) -> bool {
+        let at = delay::after(self.handle.now(), dur);
+        self.reset_at(key, at)
+    }
+
+    /// Moves the deadline of `key` to the instant `at`, returning whether
+    /// `key` had a deadline to move.
+    pub fn reset_at(&mut self, key: &K, at: Instant) -> bool {
+        if !self.slots.contains_key(key) {
+            return false
+        }
+        self.insert_at(key.clone(), at);
+        true
+    }
+
+    /// Removes the deadline of `key`, returning the instant at which it would
+    /// have passed if there was one.
+    pub fn remove(&mut self, key: &K) -> Option<Instant> {
+        let (slot, at) = self.slots.remove(key)?;
+        self.heap.remove(slot);
+        self.update();
+        Some(at)
+    }
+
+    /// Returns the instant at which the deadline of `key` passes, if it has
+    /// one.
+    pub fn deadline(&self, key: &K) -> Option<Instant> {
+        self.slots.get(key).map(|&(_, at)| at)
+    }
+
+    /// Returns whether `key` has a deadline in this set.
+    pub fn contains(&self, key: &K) -> bool {
+        self.slots.contains_key(key)
+    }
+
+    /// Returns the number of deadlines in this set.
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Returns whether this set has no deadlines.
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// Polls for the key of the earliest deadline which has passed, removing
+    /// it from the set.
+    ///
+    /// Returns `None` if the set is empty. Otherwise, if no deadline has
+    /// passed yet, the current task is woken once the earliest one does, or
+    /// an earlier one is added which has passed already.
+    ///
+    /// # Errors
+    ///
+    /// Like `Delay` this returns an error if the associated timer has gone
+    /// away.
+    pub fn poll_expired(&mut self, cx: &mut task::Context) -> Poll<Option<K>, io::Error> {
+        let at = match self.heap.peek() {
+            Some(entry) => entry.at,
+            None => return Ok(Async::Ready(None)),
+        };
+        if at > self.handle.now() {
+            let handle = &self.handle;
+            let delay = self.delay.get_or_insert_with(|| {
+                Delay::new_handle(at, handle.clone())
+            });
+            if delay.poll(cx)?.is_pending() {
+                self.waker = Some(cx.waker().clone());
+                return Ok(Async::Pending)
+            }
+        }
+        let entry = self.heap.pop().expect("peeked an entry");
+        self.slots.remove(&entry.key);
+        self.update();
+        Ok(Async::Ready(Some(entry.key)))
+    }
+
+    /// Registers the earliest deadline with the timer after the set of
+    /// deadlines has changed.
+    fn update(&mut self) {
+        let at = match self.heap.peek() {
+            Some(entry) => entry.at,
+            None => return,
+        };
+        if let Some(ref mut delay) = self.delay {
+            if delay.deadline() != at {
+                delay.reset_at(at);
+            }
+        }
+        if at <= self.handle.now() {
+            if let Some(waker) = self.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone> Default for DelaySet<K> {
+    fn default() -> DelaySet<K> {
+        DelaySet::new()
+    }
+}
+
+/// Yields the keys of deadlines as they pass, see `poll_expired`.
+///
+/// The stream ends whenever the set is empty, but may be polled again after
+/// new deadlines have been inserted.
+impl<K: Hash + Eq + Clone> Stream for DelaySet<K> {
+    type Item = K;
+    type Error = io::Error;
+
+    fn poll_next(&mut self, cx: &mut task::Context) -> Poll<Option<K>, io::Error> {
+        self.poll_expired(cx)
+    }
+}
+
+impl<K> fmt::Debug for DelaySet<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DelaySet")
+            .field("len", &self.slots.len())
+            .field("next", &self.heap.peek().map(|e| e.at))
+            .finish()
+    }
+}
+
+impl<K> PartialEq for Entry<K> {
+    fn eq(&self, other: &Entry<K>) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<K> Eq for Entry<K> {}
+
+impl<K> PartialOrd for Entry<K> {
+    fn partial_cmp(&self, other: &Entry<K>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Orders entries by deadline, and those with the same deadline by the order
+/// they were inserted in.
+impl<K> Ord for Entry<K> {
+    fn cmp(&self, other: &Entry<K>) -> Ordering {
+        (self.at, self.seq).cmp(&(other.at, other.seq))
+    }
+}
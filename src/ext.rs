@@ -1,11 +1,21 @@
-//! Extension traits for the standard `Stream` and `Future` traits.
+//! Extension traits for the standard `Stream`, `Future` and `Sink` traits.
 
+use std::cmp;
+use std::fmt;
 use std::time::{Duration, Instant};
 use std::io;
+use std::mem;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
 
 use futures::prelude::*;
+use futures::future::Either;
 
-use Delay;
+use {Cancelled, Delay, Elapsed, Interval, MissedTickBehavior, TimeoutKind, TimerHandle};
+#[cfg(feature = "std-future")]
+use Compat;
+use delay;
 
 /// An extension trait for futures which provides convenient accessors for
 /// timing out execution and such.
@@ -20,7 +30,9 @@ pub trait FutureExt: Future + Sized {
     ///
     /// If the future completes before `dur` elapses then the future will
     /// resolve with that item. Otherwise the future will resolve to an error
-    /// once `dur` has elapsed.
+    /// once `dur` has elapsed. This error is created from an `Elapsed` through
+    /// its conversion to `io::Error`, so it has the `TimedOut` kind and the
+    /// `Elapsed` can be recovered with `get_ref` and `downcast_ref`.
     ///
     /// # Examples
     ///
@@ -50,9 +62,13 @@ pub trait FutureExt: Future + Sized {
     fn timeout(self, dur: Duration) -> Timeout<Self>
         where Self::Error: From<io::Error>,
     {
+        let (timeout, start) = arm(dur);
         Timeout {
-            timeout: Delay::new(dur),
+            timeout,
+            start,
             future: self,
+            graceful: false,
+            eager: false,
         }
     }
 
@@ -65,98 +81,2513 @@ pub trait FutureExt: Future + Sized {
     fn timeout_at(self, at: Instant) -> Timeout<Self>
         where Self::Error: From<io::Error>,
     {
+        let (timeout, start) = arm_at(at);
         Timeout {
-            timeout: Delay::new_at(at),
+            timeout,
+            start,
             future: self,
+            graceful: false,
+            eager: false,
         }
     }
-}
 
-impl<F: Future> FutureExt for F {}
+    /// Creates a new future which will take at most `dur` time to resolve,
+    /// giving the receiving future a last chance to complete once `dur` has
+    /// elapsed.
+    ///
+    /// This behaves like `timeout`, except that after the timeout has fired
+    /// the receiving future is polled one final time, and its result is
+    /// returned if it's ready by then. Only if it's still pending does the
+    /// returned future resolve to a timeout error. This avoids spurious
+    /// timeouts for futures which complete right on the boundary, or which
+    /// do their final bit of work only when polled.
+    fn timeout_graceful(self, dur: Duration) -> Timeout<Self>
+        where Self::Error: From<io::Error>,
+    {
+        let mut timeout = self.timeout(dur);
+        timeout.graceful = true;
+        timeout
+    }
 
-/// Future returned by the `FutureExt::timeout` method.
-pub struct Timeout<F> {
-    timeout: Delay,
-    future: F,
-}
+    /// Creates a new future which will resolve no later than the deadline
+    /// `parent_deadline` of the operation it's a part of.
+    ///
+    /// This is meant for calls made on behalf of a request which has a
+    /// deadline of its own, so that the call gets whatever time is left of
+    /// that deadline rather than a fresh timeout, see `remaining_timeout`.
+    /// Otherwise this behaves like `timeout_at`, except that if
+    /// `parent_deadline` has already passed by the time the returned future
+    /// is first polled then it fails with an `Elapsed` error right away,
+    /// without driving the receiving future at all.
+    fn timeout_within(self, parent_deadline: Instant) -> Timeout<Self>
+        where Self::Error: From<io::Error>,
+    {
+        let mut timeout = self.timeout_at(parent_deadline);
+        timeout.eager = true;
+        timeout
+    }
 
-impl<F> Future for Timeout<F>
-    where F: Future,
-          F::Error: From<io::Error>,
-{
-    type Item = F::Item;
-    type Error = F::Error;
+    /// Creates a new future which will take at most `dur` time to resolve,
+    /// and which can also be cancelled early by setting the `cancel` flag.
+    ///
+    /// This combines a timeout and cooperative cancellation into a single
+    /// future. Every time it's polled the returned future first checks the
+    /// flag, then the receiving future and then the timeout. If the flag is
+    /// set the returned future resolves to an error created from `Cancelled`,
+    /// without polling the receiving future again, and if the timeout fires
+    /// first to an error created from `Elapsed` just like `timeout`.
+    ///
+    /// Setting the flag doesn't wake the task waiting on the returned future,
+    /// so it's only noticed the next time the task is polled, at the latest
+    /// once the timeout fires. Whoever sets the flag should wake the task if
+    /// it needs to be noticed right away.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate futures;
+    /// extern crate futures_timer;
+    ///
+    /// use std::io;
+    /// use std::sync::Arc;
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    /// use std::time::Duration;
+    /// use futures::executor::block_on;
+    /// use futures_timer::FutureExt;
+    ///
+    /// fn main() {
+    ///     let cancel = Arc::new(AtomicBool::new(false));
+    ///     let work = futures::future::empty::<(), io::Error>()
+    ///         .timeout_cancellable(Duration::from_secs(10), cancel.clone());
+    ///
+    ///     cancel.store(true, Ordering::SeqCst);
+    ///     let err = block_on(work).unwrap_err();
+    ///     assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+    /// }
+    /// ```
+    fn timeout_cancellable(self, dur: Duration, cancel: Arc<AtomicBool>)
+        -> TimeoutCancellable<Self>
+        where Self::Error: From<io::Error>,
+    {
+        let (timeout, start) = arm(dur);
+        TimeoutCancellable {
+            timeout,
+            start,
+            future: self,
+            cancel,
+        }
+    }
 
-    fn poll(&mut self, cx: &mut task::Context) -> Poll<F::Item, F::Error> {
-        match self.future.poll(cx)? {
-            Async::Pending => {}
-            other => return Ok(other)
+    /// Creates a new future which will take at most `dur` time to resolve,
+    /// reporting a timeout through its item rather than its error.
+    ///
+    /// Unlike `timeout` this places no requirements on the error type of the
+    /// receiving future. The returned future resolves to `Ok(item)` if the
+    /// receiving future completes before `dur` elapses and to `Err(Elapsed)`
+    /// if the timeout fires first. Errors from the receiving future are
+    /// propagated unchanged.
+    ///
+    /// If the timer powering the timeout has gone away then the timeout is
+    /// considered to have elapsed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate futures;
+    /// extern crate futures_timer;
+    ///
+    /// use std::time::Duration;
+    /// use futures::prelude::*;
+    /// use futures::executor::block_on;
+    /// use futures_timer::FutureExt;
+    ///
+    /// fn main() {
+    ///     let future = futures::future::empty::<(), ()>();
+    ///     let timed_out = future.timeout_result(Duration::from_millis(10));
+    ///
+    ///     match block_on(timed_out) {
+    ///         Ok(Ok(item)) => println!("got {:?} within enough time!", item),
+    ///         Ok(Err(_)) => println!("took too long to produce the item"),
+    ///         Err(()) => println!("the future itself failed"),
+    ///     }
+    /// }
+    /// ```
+    fn timeout_result(self, dur: Duration) -> TimeoutResult<Self> {
+        let (timeout, start) = arm(dur);
+        TimeoutResult {
+            timeout,
+            start,
+            future: self,
         }
+    }
 
-        if self.timeout.poll(cx)?.is_ready() {
-            Err(io::Error::new(io::ErrorKind::TimedOut, "future timed out").into())
-        } else {
-            Ok(Async::Pending)
+    /// Creates a new future which will take at most `dur` time to resolve,
+    /// producing the error returned by `on_timeout` if it doesn't.
+    ///
+    /// This behaves like `timeout` except that the error produced when `dur`
+    /// elapses is created by calling `on_timeout` and converting its result
+    /// into the error type of the receiving future. The closure is called at
+    /// most once, and only if the timeout fires before the receiving future
+    /// completes. If the timer powering the timeout has gone away then the
+    /// timeout is considered to have elapsed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate futures;
+    /// extern crate futures_timer;
+    ///
+    /// use std::time::Duration;
+    /// use futures::executor::block_on;
+    /// use futures_timer::FutureExt;
+    ///
+    /// #[derive(Debug, PartialEq)]
+    /// enum MyError {
+    ///     RequestTimeout { peer: u32 },
+    /// }
+    ///
+    /// fn main() {
+    ///     let peer = 7;
+    ///     let future = futures::future::empty::<(), MyError>();
+    ///     let timed_out = future.timeout_with(Duration::from_millis(10), || {
+    ///         MyError::RequestTimeout { peer }
+    ///     });
+    ///
+    ///     assert_eq!(block_on(timed_out), Err(MyError::RequestTimeout { peer }));
+    /// }
+    /// ```
+    fn timeout_with<E, G>(self, dur: Duration, on_timeout: G) -> TimeoutWith<Self, G>
+        where G: FnOnce() -> E,
+              Self::Error: From<E>,
+    {
+        TimeoutWith {
+            timeout: Delay::new(dur),
+            future: self,
+            on_timeout: Some(on_timeout),
         }
     }
-}
 
-/// An extension trait for streams which provides convenient accessors for
-/// timing out execution and such.
-pub trait StreamExt: Stream + Sized {
+    /// Creates a new future which will take at most `dur` time to resolve,
+    /// reporting which of the receiving future and the timeout finished
+    /// first.
+    ///
+    /// The returned future resolves to `Either::Left(item)` if the receiving
+    /// future completes before `dur` elapses, and to `Either::Right(())` if
+    /// the timeout fires first. This is handy when running out of time isn't
+    /// an error but simply another branch to continue with. Errors from the
+    /// receiving future are propagated unchanged.
+    ///
+    /// If the timer powering the timeout has gone away then the timeout is
+    /// considered to have elapsed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate futures;
+    /// extern crate futures_timer;
+    ///
+    /// use std::time::Duration;
+    /// use futures::executor::block_on;
+    /// use futures::future::Either;
+    /// use futures_timer::FutureExt;
+    ///
+    /// fn main() {
+    ///     let fresh = futures::future::empty::<u32, ()>();
+    ///     let value = match block_on(fresh.timeout_either(Duration::from_millis(10))) {
+    ///         Ok(Either::Left(value)) => value,
+    ///         Ok(Either::Right(())) => 0, // fall back to a default
+    ///         Err(()) => panic!("failed to compute the value"),
+    ///     };
+    ///     assert_eq!(value, 0);
+    /// }
+    /// ```
+    fn timeout_either(self, dur: Duration) -> TimeoutEither<Self> {
+        TimeoutEither {
+            timeout: Delay::new(dur),
+            future: self,
+        }
+    }
 
-    /// Creates a new stream which will take at most `dur` time to yield each
-    /// item of the stream.
+    /// Creates a stream which reports on the progress of the receiving future
+    /// every `heartbeat`, giving up on it once `total` time has passed.
     ///
-    /// This combinator creates a new stream which wraps the receiving stream
-    /// in a timeout-per-item. The stream returned will resolve in at most
-    /// `dur` time for each item yielded from the stream. The first item's timer
-    /// starts when this method is called.
+    /// While the receiving future is running the returned stream yields a
+    /// `Heartbeat::Progress` every `heartbeat`, and once it completes the
+    /// stream yields its item as `Heartbeat::Done` and ends. Should `total`
+    /// elapse first then the stream instead fails with a timeout error, which
+    /// is created from an `Elapsed` just like the error of `timeout`.
     ///
-    /// If a stream's item completes before `dur` elapses then the timer will be
-    /// reset for the next item. If the timeout elapses, however, then an error
-    /// will be yielded on the stream and the timer will be reset.
-    fn timeout(self, dur: Duration) -> TimeoutStream<Self>
+    /// Heartbeats which are due while the consumer of the stream is busy are
+    /// skipped rather than yielded in a burst afterwards. A zero `heartbeat`
+    /// disables them, leaving just the item or the timeout error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate futures;
+    /// extern crate futures_timer;
+    ///
+    /// use std::time::Duration;
+    /// use futures::prelude::*;
+    /// use futures::executor::block_on;
+    /// use futures_timer::{Delay, FutureExt};
+    /// use futures_timer::ext::Heartbeat;
+    ///
+    /// fn main() {
+    ///     let work = Delay::new(Duration::from_millis(50));
+    ///     let events = work.with_heartbeat(Duration::from_millis(20),
+    ///                                      Duration::from_secs(1));
+    ///     let events = events.for_each(|event| {
+    ///         match event {
+    ///             Heartbeat::Progress => print!("."),
+    ///             Heartbeat::Done(()) => println!(" done"),
+    ///         }
+    ///         Ok(())
+    ///     });
+    ///     block_on(events).unwrap();
+    /// }
+    /// ```
+    fn with_heartbeat(self, heartbeat: Duration, total: Duration) -> WithHeartbeat<Self>
         where Self::Error: From<io::Error>,
     {
-        TimeoutStream {
-            timeout: Delay::new(dur),
-            dur,
-            stream: self,
+        let (timeout, start) = arm(total);
+        let interval = if heartbeat > Duration::from_secs(0) {
+            let mut interval = Interval::new_handle(delay::after(start, heartbeat),
+                                                    heartbeat,
+                                                    TimerHandle::default());
+            interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+            Some(interval)
+        } else {
+            None
+        };
+        WithHeartbeat {
+            future: Some(self),
+            interval,
+            timeout,
+            start,
+        }
+    }
+
+    /// Creates a new future which measures how long this future takes to
+    /// complete.
+    ///
+    /// The returned future resolves to the item of this future along with the
+    /// time which passed between calling this method and this future
+    /// completing. This isn't a timeout, the returned future waits for as
+    /// long as this future does, and errors are passed through unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate futures;
+    /// extern crate futures_timer;
+    ///
+    /// use std::time::Duration;
+    /// use futures::executor::block_on;
+    /// use futures_timer::{Delay, FutureExt};
+    ///
+    /// fn main() {
+    ///     let request = Delay::new(Duration::from_millis(10));
+    ///     let ((), latency) = block_on(request.timed()).unwrap();
+    ///     println!("request took {:?}", latency);
+    /// }
+    /// ```
+    fn timed(self) -> Timed<Self> {
+        Timed {
+            future: self,
+            start: Instant::now(),
+        }
+    }
+
+    /// Creates a new future which waits for `dur` to pass before it starts
+    /// polling this future.
+    ///
+    /// Unlike `timeout` this gates the start of the receiving future rather
+    /// than bounding how long it may take: the receiving future isn't polled
+    /// at all until `dur` has elapsed, which matters for futures doing work
+    /// on their first poll, and is then driven to completion for as long as
+    /// it takes. This is useful to stagger the startup of many tasks. If the
+    /// timer powering the delay has gone away then the delay is considered
+    /// to have elapsed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate futures;
+    /// extern crate futures_timer;
+    ///
+    /// use std::time::Duration;
+    /// use futures::executor::block_on;
+    /// use futures_timer::FutureExt;
+    ///
+    /// fn main() {
+    ///     let workers = (0..3u64).map(|i| {
+    ///         let start = futures::future::lazy(move |_| Ok::<_, ()>(i));
+    ///         start.delay(Duration::from_millis(10 * i))
+    ///     });
+    ///     let ids = block_on(futures::future::join_all(workers)).unwrap();
+    ///     assert_eq!(ids, vec![0, 1, 2]);
+    /// }
+    /// ```
+    fn delay(self, dur: Duration) -> Delayed<Self> {
+        Delayed {
+            delay: Some(Delay::new(dur)),
+            future: self,
         }
     }
+
+    /// Adapts this future into a `std::future::Future`, so that it can be
+    /// used with `.await`.
+    ///
+    /// This allows the timeout combinators to be used from `async` code, the
+    /// output of the returned future is the `Result` this future resolves
+    /// to. Only available with the `std-future` feature.
+    #[cfg(feature = "std-future")]
+    fn compat(self) -> Compat<Self>
+        where Self: Unpin,
+    {
+        Compat::new(self)
+    }
 }
 
-impl<S: Stream> StreamExt for S {}
+impl<F: Future> FutureExt for F {}
 
-/// Stream returned by the `StreamExt::timeout` method.
-pub struct TimeoutStream<S> {
+/// Creates a new future which will take at most `dur` time to resolve the
+/// future `f`.
+///
+/// This is the same as `f.timeout(dur)`, which can read better when `f` is a
+/// long expression. See `FutureExt::timeout` for more information.
+///
+/// # Examples
+///
+/// ```
+/// extern crate futures;
+/// extern crate futures_timer;
+///
+/// use std::time::Duration;
+/// use futures::executor::block_on;
+/// use futures_timer::timeout;
+///
+/// fn main() {
+///     let work = futures::future::ok::<u32, std::io::Error>(3);
+///     assert_eq!(block_on(timeout(Duration::from_secs(1), work)).unwrap(), 3);
+/// }
+/// ```
+pub fn timeout<F>(dur: Duration, f: F) -> Timeout<F>
+    where F: Future,
+          F::Error: From<io::Error>,
+{
+    f.timeout(dur)
+}
+
+/// Creates a new future which will resolve the future `f` no later than `at`.
+///
+/// This is the same as `f.timeout_at(at)`, see `timeout` for more
+/// information.
+pub fn timeout_at<F>(at: Instant, f: F) -> Timeout<F>
+    where F: Future,
+          F::Error: From<io::Error>,
+{
+    f.timeout_at(at)
+}
+
+/// Returns the amount of time left until `parent_deadline`, according to the
+/// clock of the default timer, or a zero duration if it has already passed.
+///
+/// Only reading the time, this doesn't spawn the global timer's helper
+/// thread: the clock of the fallback configured through
+/// `TimerHandle::set_as_global_fallback` is used if there is one, and the
+/// system clock which the global timer runs on otherwise.
+///
+/// This derives the timeout of a call made on behalf of an operation with a
+/// deadline of its own, such as a request in a pipeline, so that the call
+/// doesn't outlive its parent.
+///
+/// # Examples
+///
+/// ```
+/// extern crate futures_timer;
+///
+/// use std::time::{Duration, Instant};
+/// use futures_timer::remaining_timeout;
+///
+/// fn main() {
+///     let parent_deadline = Instant::now() + Duration::from_secs(1);
+///     assert!(remaining_timeout(parent_deadline) <= Duration::from_secs(1));
+///     assert_eq!(remaining_timeout(Instant::now()), Duration::from_secs(0));
+/// }
+/// ```
+pub fn remaining_timeout(parent_deadline: Instant) -> Duration {
+    parent_deadline.saturating_duration_since(TimerHandle::default_now())
+}
+
+/// Creates a new future which races all of `futures` against each other,
+/// taking at most `dur` time to do so.
+///
+/// The returned future drives all of `futures` concurrently and resolves
+/// with whatever the first of them to complete resolves to, along with the
+/// index of that future for a success. The futures which are still running
+/// at that point are dropped. If none of the futures completes before `dur`
+/// elapses then the returned future resolves to an error created from an
+/// `Elapsed`, just like the error of `FutureExt::timeout`. With no futures
+/// to race at all this simply waits for that timeout.
+///
+/// # Examples
+///
+/// ```
+/// extern crate futures;
+/// extern crate futures_timer;
+///
+/// use std::time::Duration;
+/// use futures::prelude::*;
+/// use futures::executor::block_on;
+/// use futures_timer::{Delay, select_timeout};
+///
+/// fn main() {
+///     let replicas = [30, 10, 20].iter().map(|&ms| {
+///         Delay::new(Duration::from_millis(ms)).map(move |()| ms)
+///     });
+///     let (fastest, index) = block_on(select_timeout(replicas, Duration::from_secs(1))).unwrap();
+///     assert_eq!((fastest, index), (10, 1));
+/// }
+/// ```
+pub fn select_timeout<I>(futures: I, dur: Duration) -> SelectTimeout<I::Item>
+    where I: IntoIterator,
+          I::Item: Future,
+          <I::Item as Future>::Error: From<io::Error>,
+{
+    let (timeout, start) = arm(dur);
+    SelectTimeout {
+        timeout,
+        start,
+        futures: futures.into_iter().collect(),
+    }
+}
+
+/// Future returned by the `select_timeout` function.
+pub struct SelectTimeout<F> {
     timeout: Delay,
-    dur: Duration,
-    stream: S,
+    start: Instant,
+    futures: Vec<F>,
 }
 
-impl<S> Stream for TimeoutStream<S>
-    where S: Stream,
-          S::Error: From<io::Error>,
+impl<F> SelectTimeout<F> {
+    /// Returns the futures being raced.
+    pub fn get_ref(&self) -> &[F] {
+        &self.futures
+    }
+
+    /// Consumes this future, returning the futures being raced.
+    pub fn into_inner(self) -> Vec<F> {
+        self.futures
+    }
+}
+
+impl<F: fmt::Debug> fmt::Debug for SelectTimeout<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SelectTimeout")
+            .field("timeout", &self.timeout)
+            .field("futures", &self.futures)
+            .finish()
+    }
+}
+
+impl<F> Future for SelectTimeout<F>
+    where F: Future,
+          F::Error: From<io::Error>,
 {
-    type Item = S::Item;
-    type Error = S::Error;
+    type Item = (F::Item, usize);
+    type Error = F::Error;
 
-    fn poll_next(&mut self, cx: &mut task::Context) -> Poll<Option<S::Item>, S::Error> {
-        match self.stream.poll_next(cx) {
-            Ok(Async::Pending) => {}
-            other => {
-                self.timeout.reset(self.dur);
-                return other
+    fn poll(&mut self, cx: &mut task::Context) -> Poll<(F::Item, usize), F::Error> {
+        for (i, future) in self.futures.iter_mut().enumerate() {
+            if let Async::Ready(item) = future.poll(cx)? {
+                self.futures.clear();
+                return Ok(Async::Ready((item, i)))
             }
         }
 
         if self.timeout.poll(cx)?.is_ready() {
-            self.timeout.reset(self.dur);
-            Err(io::Error::new(io::ErrorKind::TimedOut, "stream item timed out").into())
+            Err(io::Error::from(elapsed(TimeoutKind::Future, &self.timeout, self.start)).into())
         } else {
             Ok(Async::Pending)
         }
     }
 }
+
+/// Future returned by the `FutureExt::timeout`, `FutureExt::timeout_at`,
+/// `FutureExt::timeout_graceful` and `FutureExt::timeout_within` methods.
+///
+/// This is `Unpin` whenever the future being timed out is. Dropping a
+/// `Timeout`, for example because another branch of a `select` won, drops
+/// the future being timed out and cancels the timeout right away.
+pub struct Timeout<F> {
+    timeout: Delay,
+    start: Instant,
+    future: F,
+    graceful: bool,
+
+    // Whether to fail without polling `future` if the deadline has passed by
+    // the first poll, cleared once it has happened.
+    eager: bool,
+}
+
+impl<F> Timeout<F> {
+    /// Returns a shared reference to the future being timed out.
+    pub fn get_ref(&self) -> &F {
+        &self.future
+    }
+
+    /// Returns a mutable reference to the future being timed out.
+    pub fn get_mut(&mut self) -> &mut F {
+        &mut self.future
+    }
+
+    /// Consumes this timeout, returning the future being timed out.
+    ///
+    /// This can be used to recover the future after the timeout has elapsed,
+    /// for example to try driving it again under a fresh timeout.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate futures;
+    /// extern crate futures_timer;
+    ///
+    /// use std::time::Duration;
+    /// use futures::prelude::*;
+    /// use futures::future::poll_fn;
+    /// use futures::executor::block_on;
+    /// use futures_timer::FutureExt;
+    ///
+    /// # fn connect() -> futures::future::Empty<(), std::io::Error> {
+    /// #   futures::future::empty()
+    /// # }
+    /// #
+    /// fn main() {
+    ///     let mut attempt = connect().timeout(Duration::from_millis(10));
+    ///     if block_on(poll_fn(|cx| attempt.poll(cx))).is_err() {
+    ///         // Keep the same connection future for the next attempt.
+    ///         let retry = attempt.into_inner().timeout(Duration::from_millis(10));
+    ///         # drop(retry);
+    ///     }
+    /// }
+    /// ```
+    pub fn into_inner(self) -> F {
+        self.future
+    }
+
+    /// Resets the deadline of this timeout to `dur` from now, leaving the
+    /// future being timed out untouched.
+    ///
+    /// Calling this whenever the future makes progress turns this timeout
+    /// into an idle timeout, which only elapses once the future has made no
+    /// progress for `dur`. See `Delay::reset` for more information.
+    pub fn reset(&mut self, dur: Duration) {
+        let now = delay::now(&self.timeout);
+        self.start = now;
+        self.timeout.reset_at(delay::after(now, dur))
+    }
+
+    /// Resets the deadline of this timeout to `at`, leaving the future being
+    /// timed out untouched.
+    ///
+    /// See `Delay::reset_at` for more information.
+    pub fn reset_at(&mut self, at: Instant) {
+        self.start = delay::now(&self.timeout);
+        self.timeout.reset_at(at)
+    }
+
+    /// Returns the instant at which this timeout elapses.
+    pub fn deadline(&self) -> Instant {
+        self.timeout.deadline()
+    }
+
+    /// Returns the amount of time left until this timeout elapses, or a zero
+    /// duration if its deadline has already passed.
+    pub fn remaining(&self) -> Duration {
+        self.timeout.remaining()
+    }
+
+    /// Returns whether the deadline of this timeout has passed.
+    ///
+    /// This only compares the deadline against the current time, so a `true`
+    /// result doesn't mean that this future has been polled since then and
+    /// has resolved to an error. The future being timed out may well still
+    /// complete successfully if it's ready by the time this is next polled.
+    pub fn is_elapsed(&self) -> bool {
+        self.timeout.is_elapsed()
+    }
+}
+
+impl<F: fmt::Debug> fmt::Debug for Timeout<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Timeout")
+            .field("timeout", &self.timeout)
+            .field("future", &self.future)
+            .finish()
+    }
+}
+
+impl<F> Future for Timeout<F>
+    where F: Future,
+          F::Error: From<io::Error>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self, cx: &mut task::Context) -> Poll<F::Item, F::Error> {
+        if mem::replace(&mut self.eager, false) && self.timeout.is_elapsed() {
+            return Err(io::Error::from(elapsed(TimeoutKind::Future, &self.timeout, self.start)).into())
+        }
+        match self.future.poll(cx)? {
+            Async::Pending => {}
+            other => return Ok(other)
+        }
+
+        if self.timeout.poll(cx)?.is_pending() {
+            return Ok(Async::Pending)
+        }
+        if self.graceful {
+            if let Async::Ready(item) = self.future.poll(cx)? {
+                return Ok(Async::Ready(item))
+            }
+        }
+        Err(io::Error::from(elapsed(TimeoutKind::Future, &self.timeout, self.start)).into())
+    }
+}
+
+/// Future returned by the `FutureExt::timeout_cancellable` method.
+pub struct TimeoutCancellable<F> {
+    timeout: Delay,
+    start: Instant,
+    future: F,
+    cancel: Arc<AtomicBool>,
+}
+
+impl<F> TimeoutCancellable<F> {
+    /// Returns a shared reference to the future being timed out.
+    pub fn get_ref(&self) -> &F {
+        &self.future
+    }
+
+    /// Returns a mutable reference to the future being timed out.
+    pub fn get_mut(&mut self) -> &mut F {
+        &mut self.future
+    }
+
+    /// Consumes this timeout, returning the future being timed out.
+    pub fn into_inner(self) -> F {
+        self.future
+    }
+
+    /// Returns whether the cancellation flag of this future has been set.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(SeqCst)
+    }
+}
+
+impl<F: fmt::Debug> fmt::Debug for TimeoutCancellable<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TimeoutCancellable")
+            .field("timeout", &self.timeout)
+            .field("future", &self.future)
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+impl<F> Future for TimeoutCancellable<F>
+    where F: Future,
+          F::Error: From<io::Error>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self, cx: &mut task::Context) -> Poll<F::Item, F::Error> {
+        if self.is_cancelled() {
+            return Err(io::Error::from(Cancelled::new()).into())
+        }
+
+        match self.future.poll(cx)? {
+            Async::Pending => {}
+            other => return Ok(other)
+        }
+
+        if self.timeout.poll(cx)?.is_pending() {
+            return Ok(Async::Pending)
+        }
+        Err(io::Error::from(elapsed(TimeoutKind::Future, &self.timeout, self.start)).into())
+    }
+}
+
+/// Future returned by the `FutureExt::timeout_result` method.
+pub struct TimeoutResult<F> {
+    timeout: Delay,
+    start: Instant,
+    future: F,
+}
+
+impl<F> TimeoutResult<F> {
+    /// Flattens the result of this future into a single error type, created
+    /// by calling `on_elapsed` if the timeout fires and converted from the
+    /// error of the future being timed out otherwise.
+    ///
+    /// This makes it easy to use timeouts with an error enum of your own
+    /// alongside the `?` operator, as long as the enum can be created from
+    /// the errors of the future being timed out. The closure is called at
+    /// most once, and only if the timeout fires first. Should you want to
+    /// keep the details of the timeout then the `Elapsed` error is available
+    /// from the item of this future itself instead, and can be converted
+    /// into your error type through a `From` implementation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate futures;
+    /// extern crate futures_timer;
+    ///
+    /// use std::io;
+    /// use std::time::Duration;
+    /// use futures::executor::block_on;
+    /// use futures_timer::FutureExt;
+    ///
+    /// #[derive(Debug)]
+    /// enum FetchError {
+    ///     Timeout,
+    ///     Io(io::Error),
+    /// }
+    ///
+    /// impl From<io::Error> for FetchError {
+    ///     fn from(err: io::Error) -> FetchError {
+    ///         FetchError::Io(err)
+    ///     }
+    /// }
+    ///
+    /// fn fetch() -> Result<Vec<u8>, FetchError> {
+    ///     let request = futures::future::empty::<Vec<u8>, io::Error>();
+    ///     let response = block_on(request.timeout_result(Duration::from_millis(10))
+    ///         .on_elapsed_map(|| FetchError::Timeout))?;
+    ///     Ok(response)
+    /// }
+    ///
+    /// fn main() {
+    ///     match fetch() {
+    ///         Err(FetchError::Timeout) => {}
+    ///         other => panic!("unexpected result: {:?}", other),
+    ///     }
+    /// }
+    /// ```
+    pub fn on_elapsed_map<E, G>(self, on_elapsed: G) -> MapElapsed<F, G>
+        where G: FnOnce() -> E,
+    {
+        MapElapsed {
+            inner: self,
+            on_elapsed: Some(on_elapsed),
+        }
+    }
+}
+
+impl<F: fmt::Debug> fmt::Debug for TimeoutResult<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TimeoutResult")
+            .field("timeout", &self.timeout)
+            .field("future", &self.future)
+            .finish()
+    }
+}
+
+impl<F> Future for TimeoutResult<F>
+    where F: Future,
+{
+    type Item = Result<F::Item, Elapsed>;
+    type Error = F::Error;
+
+    fn poll(&mut self, cx: &mut task::Context)
+        -> Poll<Result<F::Item, Elapsed>, F::Error>
+    {
+        match self.future.poll(cx)? {
+            Async::Ready(item) => return Ok(Async::Ready(Ok(item))),
+            Async::Pending => {}
+        }
+
+        match self.timeout.poll(cx) {
+            Ok(Async::Pending) => Ok(Async::Pending),
+            Ok(Async::Ready(())) | Err(_) => {
+                Ok(Async::Ready(Err(elapsed(TimeoutKind::Future, &self.timeout, self.start))))
+            }
+        }
+    }
+}
+
+/// Future returned by the `TimeoutResult::on_elapsed_map` method.
+pub struct MapElapsed<F, G> {
+    inner: TimeoutResult<F>,
+    on_elapsed: Option<G>,
+}
+
+impl<F: fmt::Debug, G> fmt::Debug for MapElapsed<F, G> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MapElapsed")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<F, G, E> Future for MapElapsed<F, G>
+    where F: Future,
+          G: FnOnce() -> E,
+          E: From<F::Error>,
+{
+    type Item = F::Item;
+    type Error = E;
+
+    fn poll(&mut self, cx: &mut task::Context) -> Poll<F::Item, E> {
+        match self.inner.poll(cx)? {
+            Async::Ready(Ok(item)) => Ok(Async::Ready(item)),
+            Async::Ready(Err(_)) => {
+                let on_elapsed = self.on_elapsed.take()
+                    .expect("cannot poll MapElapsed after it has timed out");
+                Err(on_elapsed())
+            }
+            Async::Pending => Ok(Async::Pending),
+        }
+    }
+}
+
+/// Future returned by the `FutureExt::timeout_either` method.
+pub struct TimeoutEither<F> {
+    timeout: Delay,
+    future: F,
+}
+
+impl<F> TimeoutEither<F> {
+    /// Returns a shared reference to the future being timed out.
+    pub fn get_ref(&self) -> &F {
+        &self.future
+    }
+
+    /// Returns a mutable reference to the future being timed out.
+    pub fn get_mut(&mut self) -> &mut F {
+        &mut self.future
+    }
+
+    /// Consumes this timeout, returning the future being timed out.
+    pub fn into_inner(self) -> F {
+        self.future
+    }
+}
+
+impl<F: fmt::Debug> fmt::Debug for TimeoutEither<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TimeoutEither")
+            .field("timeout", &self.timeout)
+            .field("future", &self.future)
+            .finish()
+    }
+}
+
+impl<F> Future for TimeoutEither<F>
+    where F: Future,
+{
+    type Item = Either<F::Item, ()>;
+    type Error = F::Error;
+
+    fn poll(&mut self, cx: &mut task::Context) -> Poll<Either<F::Item, ()>, F::Error> {
+        match self.future.poll(cx)? {
+            Async::Ready(item) => return Ok(Async::Ready(Either::Left(item))),
+            Async::Pending => {}
+        }
+
+        match self.timeout.poll(cx) {
+            Ok(Async::Pending) => Ok(Async::Pending),
+            Ok(Async::Ready(())) | Err(_) => Ok(Async::Ready(Either::Right(()))),
+        }
+    }
+}
+
+/// An event yielded by the stream returned from `FutureExt::with_heartbeat`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Heartbeat<T> {
+    /// The future is still running.
+    Progress,
+    /// The future completed with the contained item.
+    Done(T),
+}
+
+/// Stream returned by the `FutureExt::with_heartbeat` method.
+pub struct WithHeartbeat<F> {
+    future: Option<F>,
+
+    // Ticks the heartbeats, unless they're disabled.
+    interval: Option<Interval>,
+    timeout: Delay,
+    start: Instant,
+}
+
+impl<F> WithHeartbeat<F> {
+    /// Returns a shared reference to the future being reported on, or `None`
+    /// if it has already completed.
+    pub fn get_ref(&self) -> Option<&F> {
+        self.future.as_ref()
+    }
+
+    /// Returns a mutable reference to the future being reported on, or
+    /// `None` if it has already completed.
+    pub fn get_mut(&mut self) -> Option<&mut F> {
+        self.future.as_mut()
+    }
+}
+
+impl<F: fmt::Debug> fmt::Debug for WithHeartbeat<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WithHeartbeat")
+            .field("future", &self.future)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl<F> Stream for WithHeartbeat<F>
+    where F: Future,
+          F::Error: From<io::Error>,
+{
+    type Item = Heartbeat<F::Item>;
+    type Error = F::Error;
+
+    fn poll_next(&mut self, cx: &mut task::Context)
+        -> Poll<Option<Heartbeat<F::Item>>, F::Error>
+    {
+        let item = match self.future {
+            Some(ref mut future) => future.poll(cx)?,
+            None => return Ok(Async::Ready(None)),
+        };
+        if let Async::Ready(item) = item {
+            self.future = None;
+            return Ok(Async::Ready(Some(Heartbeat::Done(item))))
+        }
+
+        if self.timeout.poll(cx)?.is_ready() {
+            self.future = None;
+            return Err(io::Error::from(elapsed(TimeoutKind::Future, &self.timeout, self.start)).into())
+        }
+
+        let interval = match self.interval {
+            Some(ref mut interval) => interval,
+            None => return Ok(Async::Pending),
+        };
+        match interval.poll_tick(cx)? {
+            Async::Ready(()) => Ok(Async::Ready(Some(Heartbeat::Progress))),
+            Async::Pending => Ok(Async::Pending),
+        }
+    }
+}
+
+/// Future returned by the `FutureExt::delay` method.
+pub struct Delayed<F> {
+    // The delay gating the start of `future`, until it has fired.
+    delay: Option<Delay>,
+    future: F,
+}
+
+impl<F> Delayed<F> {
+    /// Returns a shared reference to the future being delayed.
+    pub fn get_ref(&self) -> &F {
+        &self.future
+    }
+
+    /// Returns a mutable reference to the future being delayed.
+    pub fn get_mut(&mut self) -> &mut F {
+        &mut self.future
+    }
+
+    /// Consumes this future, returning the future being delayed.
+    pub fn into_inner(self) -> F {
+        self.future
+    }
+
+    /// Returns whether the delay has fired, so that the future being delayed
+    /// has started to be polled.
+    pub fn is_started(&self) -> bool {
+        self.delay.is_none()
+    }
+}
+
+impl<F: fmt::Debug> fmt::Debug for Delayed<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Delayed")
+            .field("delay", &self.delay)
+            .field("future", &self.future)
+            .finish()
+    }
+}
+
+impl<F: Future> Future for Delayed<F> {
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self, cx: &mut task::Context) -> Poll<F::Item, F::Error> {
+        if let Some(ref mut delay) = self.delay {
+            if let Ok(Async::Pending) = delay.poll(cx) {
+                return Ok(Async::Pending)
+            }
+        }
+        self.delay = None;
+        self.future.poll(cx)
+    }
+}
+
+/// Future returned by the `FutureExt::timed` method.
+pub struct Timed<F> {
+    future: F,
+    start: Instant,
+}
+
+impl<F> Timed<F> {
+    /// Returns a shared reference to the future being measured.
+    pub fn get_ref(&self) -> &F {
+        &self.future
+    }
+
+    /// Returns a mutable reference to the future being measured.
+    pub fn get_mut(&mut self) -> &mut F {
+        &mut self.future
+    }
+
+    /// Consumes this future, returning the future being measured.
+    pub fn into_inner(self) -> F {
+        self.future
+    }
+
+    /// Returns the amount of time which has passed since the measurement
+    /// started.
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+impl<F: fmt::Debug> fmt::Debug for Timed<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Timed")
+            .field("future", &self.future)
+            .field("start", &self.start)
+            .finish()
+    }
+}
+
+impl<F: Future> Future for Timed<F> {
+    type Item = (F::Item, Duration);
+    type Error = F::Error;
+
+    fn poll(&mut self, cx: &mut task::Context) -> Poll<(F::Item, Duration), F::Error> {
+        match self.future.poll(cx)? {
+            Async::Ready(item) => Ok(Async::Ready((item, self.start.elapsed()))),
+            Async::Pending => Ok(Async::Pending),
+        }
+    }
+}
+
+/// Future returned by the `FutureExt::timeout_with` method.
+pub struct TimeoutWith<F, G> {
+    timeout: Delay,
+    future: F,
+    on_timeout: Option<G>,
+}
+
+impl<F: fmt::Debug, G> fmt::Debug for TimeoutWith<F, G> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TimeoutWith")
+            .field("timeout", &self.timeout)
+            .field("future", &self.future)
+            .finish()
+    }
+}
+
+impl<F, G, E> Future for TimeoutWith<F, G>
+    where F: Future,
+          G: FnOnce() -> E,
+          F::Error: From<E>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self, cx: &mut task::Context) -> Poll<F::Item, F::Error> {
+        match self.future.poll(cx)? {
+            Async::Pending => {}
+            other => return Ok(other)
+        }
+
+        match self.timeout.poll(cx) {
+            Ok(Async::Pending) => Ok(Async::Pending),
+            Ok(Async::Ready(())) | Err(_) => {
+                let on_timeout = self.on_timeout.take()
+                    .expect("cannot poll TimeoutWith after it has timed out");
+                Err(on_timeout().into())
+            }
+        }
+    }
+}
+
+/// An extension trait for streams which provides convenient accessors for
+/// timing out execution and such.
+pub trait StreamExt: Stream + Sized {
+
+    /// Creates a new stream which will take at most `dur` time to yield each
+    /// item of the stream.
+    ///
+    /// This combinator creates a new stream which wraps the receiving stream
+    /// in a timeout-per-item. The stream returned will resolve in at most
+    /// `dur` time for each item yielded from the stream. The first item's timer
+    /// starts when this method is called.
+    ///
+    /// If a stream's item completes before `dur` elapses then the timer will be
+    /// reset for the next item. If the timeout elapses, however, then an error
+    /// will be yielded on the stream and the timer will be reset. Once the
+    /// stream has finished the timer is released rather than reset.
+    ///
+    /// What the timer measures can be changed with
+    /// `TimeoutStream::inter_item_clock`, see `InterItemClock` for the
+    /// available modes.
+    fn timeout(self, dur: Duration) -> TimeoutStream<Self>
+        where Self::Error: From<io::Error>,
+    {
+        let (timeout, start) = arm(dur);
+        TimeoutStream {
+            timeout,
+            start,
+            dur,
+            stream: self,
+            clock: InterItemClock::SinceLastItem,
+            ended: false,
+        }
+    }
+
+    /// Creates a new stream which will take at most `first` time to yield its
+    /// first item and at most `each` time to yield every item after that.
+    ///
+    /// This is useful for protocols where producing the first item, such as
+    /// completing a handshake, legitimately takes longer than producing the
+    /// ones that follow. Once the first timer has fired or the first item has
+    /// been yielded the timer is always reset with `each`, so a stream whose
+    /// first item timed out recovers with the steady-state timeout.
+    fn timeout_first_then(self, first: Duration, each: Duration) -> TimeoutStream<Self>
+        where Self::Error: From<io::Error>,
+    {
+        let (timeout, start) = arm(first);
+        TimeoutStream {
+            timeout,
+            start,
+            dur: each,
+            stream: self,
+            clock: InterItemClock::SinceLastItem,
+            ended: false,
+        }
+    }
+
+    /// Creates a new stream which takes as long as it likes to yield its first
+    /// item, and at most `dur` time to yield every item after that.
+    ///
+    /// This suits streaming RPCs and the like, where producing the first item
+    /// may involve arbitrarily slow work, such as running a query, but items
+    /// should flow steadily once they've started. The timer isn't armed at
+    /// all until the first item has been yielded, so a stream which ends
+    /// without yielding any item never times out.
+    fn timeout_after_first(self, dur: Duration) -> TimeoutStream<Self>
+        where Self::Error: From<io::Error>,
+    {
+        TimeoutStream {
+            timeout: Delay::never(),
+            start: Instant::now(),
+            dur,
+            stream: self,
+            clock: InterItemClock::SinceLastItem,
+            ended: false,
+        }
+    }
+
+    /// Creates a new stream which must yield its first item by the instant
+    /// `first_at` and every item after that within `dur` time of the previous
+    /// one.
+    ///
+    /// This behaves like `timeout_first_then`, except that the deadline of
+    /// the first item is absolute. This is useful to line the first item up
+    /// with a deadline dictated by a protocol, while still bounding the time
+    /// between the items which follow.
+    fn timeout_at(self, first_at: Instant, dur: Duration) -> TimeoutStream<Self>
+        where Self::Error: From<io::Error>,
+    {
+        let (timeout, start) = arm_at(first_at);
+        TimeoutStream {
+            timeout,
+            start,
+            dur,
+            stream: self,
+            clock: InterItemClock::SinceLastItem,
+            ended: false,
+        }
+    }
+
+    /// Creates a new stream which will take at most `dur` time to yield each
+    /// item, producing the error for each item which times out with
+    /// `on_timeout`.
+    ///
+    /// This behaves like `timeout`, except that rather than an `Elapsed`
+    /// error the error yielded on the stream is whatever `on_timeout`
+    /// returns, which allows attaching context such as the connection the
+    /// stream belongs to. Unlike the closure of `FutureExt::timeout_with`,
+    /// `on_timeout` is called again for every item which times out, as the
+    /// stream carries on after each timeout.
+    ///
+    /// If the timer powering the timeout has gone away then the timeout is
+    /// considered to have elapsed.
+    fn timeout_map<G>(self, dur: Duration, on_timeout: G) -> TimeoutMap<Self, G>
+        where G: FnMut() -> Self::Error,
+    {
+        TimeoutMap {
+            timeout: Delay::new(dur),
+            dur,
+            stream: self,
+            on_timeout,
+        }
+    }
+
+    /// Creates a new stream which must finish within `dur` time of when this
+    /// method is called.
+    ///
+    /// Unlike `timeout` the timer of the returned stream is never reset, so it
+    /// bounds the lifetime of the whole stream regardless of how often items
+    /// are produced. Once `dur` has elapsed an error is yielded on the stream,
+    /// after which the stream is terminated and yields `None`. The deadline
+    /// is checked before the stream is polled, so even a stream which always
+    /// has an item ready is cut off once it passes.
+    ///
+    /// This can be combined with the per-item `timeout` to bound both the
+    /// time between items and the stream as a whole:
+    ///
+    /// ```
+    /// extern crate futures;
+    /// extern crate futures_timer;
+    ///
+    /// use std::time::Duration;
+    /// use futures_timer::StreamExt;
+    ///
+    /// fn main() {
+    ///     let items = futures::stream::empty::<(), std::io::Error>();
+    ///     let bounded = items
+    ///         .timeout(Duration::from_secs(1))
+    ///         .timeout_total(Duration::from_secs(30));
+    ///     # drop(bounded);
+    /// }
+    /// ```
+    fn timeout_total(self, dur: Duration) -> DeadlineStream<Self>
+        where Self::Error: From<io::Error>,
+    {
+        let (deadline, start) = arm(dur);
+        DeadlineStream {
+            deadline,
+            start,
+            stream: self,
+            ended: false,
+        }
+    }
+
+    /// Creates a new stream which must make progress within a window of
+    /// `window` time, which is only ever extended explicitly.
+    ///
+    /// The deadline of the returned stream starts out `window` from when this
+    /// method is called. Unlike with `timeout` it isn't moved by the items of
+    /// the stream, instead it's moved to `window` from the current time by
+    /// calling `DeadlineWindow::reset_deadline`. This lets protocol logic
+    /// decide what counts as progress, for example only extending the
+    /// deadline on acknowledgements from a peer or on events from outside of
+    /// the stream altogether.
+    ///
+    /// Once the deadline has elapsed an error is yielded on the stream, after
+    /// which it yields `None` unless the deadline is reset again. Just like
+    /// for `timeout_total` the deadline is checked before the stream is
+    /// polled, so a stream which is always ready is cut off as well.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate futures;
+    /// extern crate futures_timer;
+    ///
+    /// use std::time::Duration;
+    /// use futures::prelude::*;
+    /// use futures::stream;
+    /// use futures::executor::block_on;
+    /// use futures_timer::StreamExt as TimerStreamExt;
+    ///
+    /// fn main() {
+    ///     let messages = stream::iter_ok::<_, std::io::Error>(vec!["ping", "ack"]);
+    ///     let mut messages = messages.deadline_per_window(Duration::from_secs(5));
+    ///     while let (Some(message), rest) = block_on(messages.next()).map_err(|e| e.0).unwrap() {
+    ///         messages = rest;
+    ///         if message == "ack" {
+    ///             messages.reset_deadline();
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    fn deadline_per_window(self, window: Duration) -> DeadlineWindow<Self>
+        where Self::Error: From<io::Error>,
+    {
+        let (deadline, start) = arm(window);
+        DeadlineWindow {
+            deadline,
+            start,
+            window,
+            stream: self,
+            elapsed: false,
+            ended: false,
+        }
+    }
+
+    /// Creates a new stream which yields the items of this stream until the
+    /// instant `at`, after which it finishes.
+    ///
+    /// Unlike `timeout_total` passing the deadline isn't an error, the
+    /// returned stream simply ends once it's passed, yielding `None` from
+    /// then on. This is useful to collect whatever items arrive within a
+    /// window of time. The deadline is checked before each item, so even a
+    /// stream which always has an item ready is cut off promptly. If the
+    /// timer powering the deadline has gone away then the deadline is
+    /// considered to have passed.
+    fn take_until_deadline(self, at: Instant) -> TakeUntil<Self> {
+        TakeUntil {
+            deadline: arm_at(at).0,
+            stream: self,
+            done: false,
+        }
+    }
+
+    /// Creates a new stream which yields the items of this stream for `dur`
+    /// time from when this method is called, after which it finishes.
+    ///
+    /// This is the relative version of `take_until_deadline`, see there for
+    /// more information.
+    fn take_for(self, dur: Duration) -> TakeUntil<Self> {
+        TakeUntil {
+            deadline: arm(dur).0,
+            stream: self,
+            done: false,
+        }
+    }
+
+    /// Creates a new stream which only yields an item once this stream has
+    /// been quiet for `dur`.
+    ///
+    /// Every item produced by this stream restarts the timer and replaces the
+    /// item waiting to be yielded, so out of each burst of items arriving less
+    /// than `dur` apart only the last one is yielded, `dur` after it arrived.
+    /// If this stream finishes while an item is waiting then that item is
+    /// yielded right away before the returned stream finishes as well.
+    ///
+    /// Errors from this stream are passed through immediately and don't
+    /// affect the item waiting to be yielded.
+    fn debounce(self, dur: Duration) -> Debounce<Self>
+        where Self::Error: From<io::Error>,
+    {
+        Debounce {
+            delay: Delay::never(),
+            dur,
+            stream: self,
+            pending: None,
+            done: false,
+        }
+    }
+
+    /// Creates a new stream which yields the most recent item of this stream
+    /// once every `dur`.
+    ///
+    /// This stream is drained eagerly and only its latest item is kept, which
+    /// is then yielded on the next tick of an internal `Interval`. Ticks at
+    /// which no new item has arrived since the previous tick yield nothing,
+    /// and ticks missed because the returned stream wasn't polled in time are
+    /// skipped rather than made up for. The first tick happens `dur` after
+    /// this method is called.
+    ///
+    /// Once this stream finishes, an item which is still waiting is yielded
+    /// on the next tick, after which the returned stream finishes as well.
+    /// Errors from this stream are passed through immediately.
+    ///
+    /// A zero `dur` is treated as the shortest period an interval can tick
+    /// at, a nanosecond.
+    fn sample(self, dur: Duration) -> Sample<Self>
+        where Self::Error: From<io::Error>,
+    {
+        let mut interval = Interval::new(min_period(dur));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        Sample {
+            interval,
+            stream: self,
+            latest: None,
+            done: false,
+        }
+    }
+
+    /// Creates a new stream which yields every item of this stream, but
+    /// spaces consecutive items at least `dur` apart.
+    ///
+    /// An item arriving less than `dur` after the previous one was yielded is
+    /// held back until `dur` has passed. No items are dropped, and this stream
+    /// isn't polled for its next item while one is held back, so a fast
+    /// producer is slowed down rather than buffered. The first item is
+    /// yielded as soon as it's available.
+    fn min_interval(self, dur: Duration) -> MinInterval<Self>
+        where Self::Error: From<io::Error>,
+    {
+        MinInterval {
+            delay: Delay::never(),
+            dur,
+            stream: self,
+            held: None,
+            last: None,
+        }
+    }
+
+    /// Creates a new stream which collects the items of this stream into
+    /// batches of up to `n` items, yielding partial batches which have been
+    /// waiting for `dur`.
+    ///
+    /// A batch is yielded as soon as it holds `n` items, or once `dur` has
+    /// passed since its first item arrived, whichever happens first. No
+    /// batch is ever yielded empty, so the returned stream is idle for as
+    /// long as this stream is. Once this stream finishes the items which are
+    /// still buffered are yielded right away as a final batch.
+    ///
+    /// Errors from this stream are passed through immediately and the items
+    /// buffered so far are kept for the next batch.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate futures;
+    /// extern crate futures_timer;
+    ///
+    /// use std::time::Duration;
+    /// use futures::prelude::*;
+    /// use futures::stream;
+    /// use futures::executor::block_on;
+    /// use futures_timer::StreamExt as TimerStreamExt;
+    ///
+    /// fn main() {
+    ///     let items = stream::iter_ok::<_, std::io::Error>(1..6);
+    ///     let batches = items.chunks_timeout(2, Duration::from_millis(10));
+    ///     let batches: Vec<_> = block_on(batches.collect()).unwrap();
+    ///     assert_eq!(batches, vec![vec![1, 2], vec![3, 4], vec![5]]);
+    /// }
+    /// ```
+    fn chunks_timeout(self, n: usize, dur: Duration) -> ChunksTimeout<Self>
+        where Self::Error: From<io::Error>,
+    {
+        assert!(n > 0, "chunks_timeout requires a batch size of at least one");
+        ChunksTimeout {
+            delay: Delay::never(),
+            dur,
+            cap: n,
+            stream: self,
+            items: Vec::with_capacity(n),
+            done: false,
+        }
+    }
+
+    /// Creates a new stream which yields the items of this stream along with
+    /// a tick every `dur`.
+    ///
+    /// The items of this stream are yielded as `Event::Item`, interleaved
+    /// with an `Event::Tick` once every `dur`, whether or not this stream is
+    /// busy. This allows a single loop to handle both incoming data and
+    /// periodic maintenance, like flushing buffers or sending heartbeats.
+    /// When an item and a tick are ready at the same time the item is
+    /// yielded first. Ticks which are due while the consumer of the stream
+    /// is busy are skipped rather than yielded in a burst afterwards.
+    ///
+    /// The returned stream finishes along with this stream, and errors from
+    /// this stream are passed through immediately. A zero `dur` is treated
+    /// as a period of a nanosecond, like for `sample`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate futures;
+    /// extern crate futures_timer;
+    ///
+    /// use std::time::Duration;
+    /// use futures::prelude::*;
+    /// use futures::executor::block_on;
+    /// use futures_timer::StreamExt as TimerStreamExt;
+    /// use futures_timer::ext::Event;
+    ///
+    /// fn main() {
+    ///     let data = futures::stream::iter_ok::<_, std::io::Error>(vec![1, 2, 3]);
+    ///     let mut buffered = Vec::new();
+    ///     let events = data.with_ticks(Duration::from_millis(100)).for_each(|event| {
+    ///         match event {
+    ///             Event::Item(item) => buffered.push(item),
+    ///             Event::Tick => buffered.clear(), // flush
+    ///         }
+    ///         Ok(())
+    ///     });
+    ///     block_on(events).unwrap();
+    /// }
+    /// ```
+    fn with_ticks(self, dur: Duration) -> WithTicks<Self>
+        where Self::Error: From<io::Error>,
+    {
+        let mut interval = Interval::new(min_period(dur));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        WithTicks {
+            interval,
+            stream: self,
+        }
+    }
+}
+
+impl<S: Stream> StreamExt for S {}
+
+/// Stream returned by the `StreamExt::timeout` method.
+///
+/// This is `Unpin` whenever the stream being timed out is.
+pub struct TimeoutStream<S> {
+    timeout: Delay,
+    start: Instant,
+    dur: Duration,
+    stream: S,
+    clock: InterItemClock,
+    ended: bool,
+}
+
+/// What the timer of a `StreamExt::timeout` stream measures, configured
+/// through `TimeoutStream::inter_item_clock`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InterItemClock {
+    /// Measure the time since the last item was yielded, or since the stream
+    /// was created for the first item.
+    ///
+    /// The timer is reset whenever the underlying stream yields an item, as
+    /// well as after it elapsed, so an error is yielded for every stretch of
+    /// the timeout's duration without an item and the stream carries on
+    /// afterwards. Polls which find the underlying stream pending don't
+    /// affect the timer.
+    #[default]
+    SinceLastItem,
+
+    /// Measure the time since the stream was created.
+    ///
+    /// The timer is never reset, so it acts as a deadline for the stream as
+    /// a whole, like `StreamExt::timeout_total`. Once it has elapsed a single
+    /// error is yielded, after which the stream is terminated and yields
+    /// `None`.
+    SinceSubscribe,
+}
+
+impl<S> TimeoutStream<S> {
+    /// Returns a shared reference to the stream being timed out.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Returns a mutable reference to the stream being timed out.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Consumes this timeout, returning the stream being timed out.
+    ///
+    /// This can be used to recover the underlying stream after an item has
+    /// timed out, for example to shut it down gracefully.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Returns the amount of time each item of the stream is given before it
+    /// times out.
+    pub fn timeout_duration(&self) -> Duration {
+        self.dur
+    }
+
+    /// Configures what the timer of this stream measures, which defaults to
+    /// `InterItemClock::SinceLastItem`.
+    ///
+    /// The timer keeps running from when this stream was created, so this
+    /// is meant to be called right away.
+    pub fn inter_item_clock(mut self, clock: InterItemClock) -> TimeoutStream<S> {
+        self.clock = clock;
+        self
+    }
+
+    /// Restarts the timer for the next item.
+    fn rearm(&mut self) {
+        let now = delay::now(&self.timeout);
+        self.start = now;
+        self.timeout.reset_at(delay::after(now, self.dur));
+    }
+
+    /// Returns the error for the timer having elapsed, and either restarts
+    /// the timer or terminates the stream depending on what it measures.
+    fn elapse(&mut self) -> io::Error {
+        let err = elapsed(TimeoutKind::Stream, &self.timeout, self.start);
+        match self.clock {
+            InterItemClock::SinceLastItem => self.rearm(),
+            InterItemClock::SinceSubscribe => {
+                self.timeout = Delay::never();
+                self.ended = true;
+            }
+        }
+        io::Error::from(err)
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for TimeoutStream<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TimeoutStream")
+            .field("timeout", &self.timeout)
+            .field("dur", &self.dur)
+            .field("clock", &self.clock)
+            .field("stream", &self.stream)
+            .finish()
+    }
+}
+
+impl<S> Stream for TimeoutStream<S>
+    where S: Stream,
+          S::Error: From<io::Error>,
+{
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll_next(&mut self, cx: &mut task::Context) -> Poll<Option<S::Item>, S::Error> {
+        if self.ended {
+            return Ok(Async::Ready(None))
+        }
+
+        // A deadline for the stream as a whole is checked first, like that of
+        // `timeout_total`, so that a stream which is always ready is cut off
+        // too. Between items a ready item wins over the timer instead.
+        if self.clock == InterItemClock::SinceSubscribe && self.timeout.poll(cx)?.is_ready() {
+            return Err(self.elapse().into())
+        }
+
+        // Only a stream making progress resets the timer, errors from the
+        // underlying stream are passed through without touching it. Once the
+        // stream is done there's nothing left to time out, so the timer is
+        // released rather than being reset.
+        match self.stream.poll_next(cx)? {
+            Async::Pending => {}
+            Async::Ready(None) => {
+                self.timeout = Delay::never();
+                return Ok(Async::Ready(None))
+            }
+            other => {
+                if self.clock == InterItemClock::SinceLastItem {
+                    self.rearm();
+                }
+                return Ok(other)
+            }
+        }
+
+        if self.clock == InterItemClock::SinceLastItem && self.timeout.poll(cx)?.is_ready() {
+            Err(self.elapse().into())
+        } else {
+            Ok(Async::Pending)
+        }
+    }
+}
+
+/// Stream returned by the `StreamExt::timeout_map` method.
+pub struct TimeoutMap<S, G> {
+    timeout: Delay,
+    dur: Duration,
+    stream: S,
+    on_timeout: G,
+}
+
+impl<S, G> TimeoutMap<S, G> {
+    /// Returns a shared reference to the stream being timed out.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Returns a mutable reference to the stream being timed out.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Consumes this timeout, returning the stream being timed out.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S: fmt::Debug, G> fmt::Debug for TimeoutMap<S, G> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TimeoutMap")
+            .field("timeout", &self.timeout)
+            .field("dur", &self.dur)
+            .field("stream", &self.stream)
+            .finish()
+    }
+}
+
+impl<S, G> Stream for TimeoutMap<S, G>
+    where S: Stream,
+          G: FnMut() -> S::Error,
+{
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll_next(&mut self, cx: &mut task::Context) -> Poll<Option<S::Item>, S::Error> {
+        match self.stream.poll_next(cx)? {
+            Async::Pending => {}
+            Async::Ready(None) => {
+                self.timeout = Delay::never();
+                return Ok(Async::Ready(None))
+            }
+            other => {
+                self.timeout.reset(self.dur);
+                return Ok(other)
+            }
+        }
+
+        match self.timeout.poll(cx) {
+            Ok(Async::Pending) => Ok(Async::Pending),
+            Ok(Async::Ready(())) | Err(_) => {
+                self.timeout.reset(self.dur);
+                Err((self.on_timeout)())
+            }
+        }
+    }
+}
+
+/// Stream returned by the `StreamExt::timeout_total` method.
+pub struct DeadlineStream<S> {
+    deadline: Delay,
+    start: Instant,
+    stream: S,
+
+    // Whether the deadline has elapsed or the stream has finished.
+    ended: bool,
+}
+
+impl<S> DeadlineStream<S> {
+    /// Returns a shared reference to the stream being timed out.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Returns a mutable reference to the stream being timed out.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Consumes this timeout, returning the stream being timed out.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for DeadlineStream<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DeadlineStream")
+            .field("deadline", &self.deadline)
+            .field("stream", &self.stream)
+            .finish()
+    }
+}
+
+impl<S> Stream for DeadlineStream<S>
+    where S: Stream,
+          S::Error: From<io::Error>,
+{
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll_next(&mut self, cx: &mut task::Context) -> Poll<Option<S::Item>, S::Error> {
+        if self.ended {
+            return Ok(Async::Ready(None))
+        }
+
+        // The deadline is checked first so that a stream which is always
+        // ready is cut off as well.
+        if self.deadline.poll(cx)?.is_ready() {
+            let err = elapsed(TimeoutKind::StreamDeadline, &self.deadline, self.start);
+            self.deadline = Delay::never();
+            self.ended = true;
+            return Err(io::Error::from(err).into())
+        }
+
+        // Once the stream is done there's nothing left to time out, so the
+        // timer is released.
+        let item = self.stream.poll_next(cx)?;
+        if let Async::Ready(None) = item {
+            self.deadline = Delay::never();
+            self.ended = true;
+        }
+        Ok(item)
+    }
+}
+
+/// Stream returned by the `StreamExt::deadline_per_window` method.
+pub struct DeadlineWindow<S> {
+    deadline: Delay,
+    start: Instant,
+    window: Duration,
+    stream: S,
+    elapsed: bool,
+    ended: bool,
+}
+
+impl<S> DeadlineWindow<S> {
+    /// Returns a shared reference to the stream being timed out.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Returns a mutable reference to the stream being timed out.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Consumes this timeout, returning the stream being timed out.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Returns the instant by which the stream must make progress.
+    pub fn deadline(&self) -> Instant {
+        self.deadline.deadline()
+    }
+
+    /// Returns the amount of time each reset of the deadline allows for.
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Moves the deadline to `window` time from now, even if it has elapsed
+    /// already.
+    ///
+    /// The task polling this stream, if any, needs to poll it again to be
+    /// woken for the new deadline. Once the underlying stream has finished
+    /// there's nothing left to time out and this does nothing.
+    pub fn reset_deadline(&mut self) {
+        if self.ended {
+            return
+        }
+        let now = delay::now(&self.deadline);
+        self.start = now;
+        self.elapsed = false;
+        self.deadline.reset_at(delay::after(now, self.window));
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for DeadlineWindow<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DeadlineWindow")
+            .field("deadline", &self.deadline)
+            .field("window", &self.window)
+            .field("stream", &self.stream)
+            .finish()
+    }
+}
+
+impl<S> Stream for DeadlineWindow<S>
+    where S: Stream,
+          S::Error: From<io::Error>,
+{
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll_next(&mut self, cx: &mut task::Context) -> Poll<Option<S::Item>, S::Error> {
+        if self.elapsed || self.ended {
+            return Ok(Async::Ready(None))
+        }
+
+        // Like `DeadlineStream` the deadline is checked first, so that a
+        // stream which is always ready is cut off as well.
+        if self.deadline.poll(cx)?.is_ready() {
+            self.elapsed = true;
+            let err = elapsed(TimeoutKind::StreamDeadline, &self.deadline, self.start);
+            return Err(io::Error::from(err).into())
+        }
+
+        let item = self.stream.poll_next(cx)?;
+        if let Async::Ready(None) = item {
+            self.deadline = Delay::never();
+            self.ended = true;
+        }
+        Ok(item)
+    }
+}
+
+/// Stream returned by the `StreamExt::take_until_deadline` and
+/// `StreamExt::take_for` methods.
+pub struct TakeUntil<S> {
+    deadline: Delay,
+    stream: S,
+    done: bool,
+}
+
+impl<S> TakeUntil<S> {
+    /// Returns a shared reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Returns a mutable reference to the underlying stream.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Consumes this combinator, returning the underlying stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Returns the instant at which this stream finishes.
+    pub fn deadline(&self) -> Instant {
+        self.deadline.deadline()
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for TakeUntil<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TakeUntil")
+            .field("deadline", &self.deadline)
+            .field("stream", &self.stream)
+            .field("done", &self.done)
+            .finish()
+    }
+}
+
+impl<S: Stream> Stream for TakeUntil<S> {
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll_next(&mut self, cx: &mut task::Context) -> Poll<Option<S::Item>, S::Error> {
+        if self.done {
+            return Ok(Async::Ready(None))
+        }
+        match self.deadline.poll(cx) {
+            Ok(Async::Pending) => {}
+            Ok(Async::Ready(())) | Err(_) => {
+                self.done = true;
+                return Ok(Async::Ready(None))
+            }
+        }
+
+        let item = self.stream.poll_next(cx)?;
+        if let Async::Ready(None) = item {
+            self.done = true;
+        }
+        Ok(item)
+    }
+}
+
+/// Stream returned by the `StreamExt::debounce` method.
+pub struct Debounce<S: Stream> {
+    delay: Delay,
+    dur: Duration,
+    stream: S,
+    pending: Option<S::Item>,
+    done: bool,
+}
+
+impl<S: Stream> Debounce<S> {
+    /// Returns a shared reference to the stream being debounced.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Returns a mutable reference to the stream being debounced.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Consumes this stream, returning the stream being debounced.
+    ///
+    /// Any item waiting to be yielded is dropped.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S: Stream + fmt::Debug> fmt::Debug for Debounce<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Debounce")
+            .field("delay", &self.delay)
+            .field("dur", &self.dur)
+            .field("stream", &self.stream)
+            .field("pending", &self.pending.is_some())
+            .finish()
+    }
+}
+
+impl<S> Stream for Debounce<S>
+    where S: Stream,
+          S::Error: From<io::Error>,
+{
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll_next(&mut self, cx: &mut task::Context) -> Poll<Option<S::Item>, S::Error> {
+        while !self.done {
+            match self.stream.poll_next(cx)? {
+                Async::Ready(Some(item)) => {
+                    self.pending = Some(item);
+                    self.delay.reset(self.dur);
+                }
+                Async::Ready(None) => self.done = true,
+                Async::Pending => break,
+            }
+        }
+
+        if self.pending.is_none() {
+            return Ok(if self.done { Async::Ready(None) } else { Async::Pending })
+        }
+        if self.done || self.delay.poll(cx)?.is_ready() {
+            return Ok(Async::Ready(self.pending.take()))
+        }
+        Ok(Async::Pending)
+    }
+}
+
+/// Stream returned by the `StreamExt::chunks_timeout` method.
+pub struct ChunksTimeout<S: Stream> {
+    delay: Delay,
+    dur: Duration,
+    cap: usize,
+    stream: S,
+    items: Vec<S::Item>,
+    done: bool,
+}
+
+impl<S: Stream> ChunksTimeout<S> {
+    /// Returns a shared reference to the stream being batched.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Returns a mutable reference to the stream being batched.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Consumes this stream, returning the stream being batched.
+    ///
+    /// Any items which are still buffered are dropped.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    fn flush(&mut self) -> Vec<S::Item> {
+        mem::replace(&mut self.items, Vec::with_capacity(self.cap))
+    }
+}
+
+impl<S: Stream + fmt::Debug> fmt::Debug for ChunksTimeout<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ChunksTimeout")
+            .field("delay", &self.delay)
+            .field("dur", &self.dur)
+            .field("cap", &self.cap)
+            .field("stream", &self.stream)
+            .field("buffered", &self.items.len())
+            .finish()
+    }
+}
+
+impl<S> Stream for ChunksTimeout<S>
+    where S: Stream,
+          S::Error: From<io::Error>,
+{
+    type Item = Vec<S::Item>;
+    type Error = S::Error;
+
+    fn poll_next(&mut self, cx: &mut task::Context) -> Poll<Option<Vec<S::Item>>, S::Error> {
+        while !self.done && self.items.len() < self.cap {
+            match self.stream.poll_next(cx)? {
+                Async::Ready(Some(item)) => {
+                    if self.items.is_empty() {
+                        self.delay.reset(self.dur);
+                    }
+                    self.items.push(item);
+                }
+                Async::Ready(None) => self.done = true,
+                Async::Pending => break,
+            }
+        }
+
+        if self.items.is_empty() {
+            return Ok(if self.done { Async::Ready(None) } else { Async::Pending })
+        }
+        if self.done || self.items.len() >= self.cap || self.delay.poll(cx)?.is_ready() {
+            return Ok(Async::Ready(Some(self.flush())))
+        }
+        Ok(Async::Pending)
+    }
+}
+
+/// An event yielded by the stream returned from `StreamExt::with_ticks`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event<T> {
+    /// An item of the underlying stream.
+    Item(T),
+    /// A periodic tick.
+    Tick,
+}
+
+/// Stream returned by the `StreamExt::with_ticks` method.
+pub struct WithTicks<S> {
+    interval: Interval,
+    stream: S,
+}
+
+impl<S> WithTicks<S> {
+    /// Returns a shared reference to the stream being ticked alongside.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Returns a mutable reference to the stream being ticked alongside.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Consumes this stream, returning the stream being ticked alongside.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for WithTicks<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WithTicks")
+            .field("period", &self.interval.period())
+            .field("stream", &self.stream)
+            .finish()
+    }
+}
+
+impl<S> Stream for WithTicks<S>
+    where S: Stream,
+          S::Error: From<io::Error>,
+{
+    type Item = Event<S::Item>;
+    type Error = S::Error;
+
+    fn poll_next(&mut self, cx: &mut task::Context) -> Poll<Option<Event<S::Item>>, S::Error> {
+        match self.stream.poll_next(cx)? {
+            Async::Ready(item) => return Ok(Async::Ready(item.map(Event::Item))),
+            Async::Pending => {}
+        }
+        match self.interval.poll_tick(cx)? {
+            Async::Ready(()) => Ok(Async::Ready(Some(Event::Tick))),
+            Async::Pending => Ok(Async::Pending),
+        }
+    }
+}
+
+/// Stream returned by the `StreamExt::sample` method.
+pub struct Sample<S: Stream> {
+    interval: Interval,
+    stream: S,
+    latest: Option<S::Item>,
+    done: bool,
+}
+
+impl<S: Stream> Sample<S> {
+    /// Returns a shared reference to the stream being sampled.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Returns a mutable reference to the stream being sampled.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Consumes this stream, returning the stream being sampled.
+    ///
+    /// Any item waiting to be yielded is dropped.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S: Stream + fmt::Debug> fmt::Debug for Sample<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Sample")
+            .field("stream", &self.stream)
+            .field("latest", &self.latest.is_some())
+            .finish()
+    }
+}
+
+impl<S> Stream for Sample<S>
+    where S: Stream,
+          S::Error: From<io::Error>,
+{
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll_next(&mut self, cx: &mut task::Context) -> Poll<Option<S::Item>, S::Error> {
+        while !self.done {
+            match self.stream.poll_next(cx)? {
+                Async::Ready(Some(item)) => self.latest = Some(item),
+                Async::Ready(None) => self.done = true,
+                Async::Pending => break,
+            }
+        }
+
+        let mut empty = false;
+        loop {
+            if self.done && self.latest.is_none() {
+                return Ok(Async::Ready(None))
+            }
+            match self.interval.poll_next(cx)? {
+                Async::Ready(_) => {
+                    if let Some(item) = self.latest.take() {
+                        return Ok(Async::Ready(Some(item)))
+                    }
+                    // Two ticks in a row without an item mean that a very
+                    // short period keeps the interval due, so the task is
+                    // woken to check for new items rather than spinning.
+                    if empty {
+                        cx.waker().wake();
+                        return Ok(Async::Pending)
+                    }
+                    empty = true;
+                }
+                Async::Pending => return Ok(Async::Pending),
+            }
+        }
+    }
+}
+
+/// Stream returned by the `StreamExt::min_interval` method.
+pub struct MinInterval<S: Stream> {
+    delay: Delay,
+    dur: Duration,
+    stream: S,
+    held: Option<S::Item>,
+    last: Option<Instant>,
+}
+
+impl<S: Stream> MinInterval<S> {
+    /// Returns a shared reference to the stream being spaced out.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Returns a mutable reference to the stream being spaced out.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Consumes this stream, returning the stream being spaced out.
+    ///
+    /// Any item being held back is dropped.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S: Stream + fmt::Debug> fmt::Debug for MinInterval<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MinInterval")
+            .field("dur", &self.dur)
+            .field("stream", &self.stream)
+            .field("held", &self.held.is_some())
+            .finish()
+    }
+}
+
+impl<S> Stream for MinInterval<S>
+    where S: Stream,
+          S::Error: From<io::Error>,
+{
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll_next(&mut self, cx: &mut task::Context) -> Poll<Option<S::Item>, S::Error> {
+        if self.held.is_none() {
+            let item = match self.stream.poll_next(cx)? {
+                Async::Ready(Some(item)) => item,
+                other => return Ok(other),
+            };
+            let now = delay::now(&self.delay);
+            let next = self.last.map(|last| delay::after(last, self.dur));
+            match next {
+                Some(next) if now < next => {
+                    self.held = Some(item);
+                    self.delay.reset_at(next);
+                }
+                _ => {
+                    self.last = Some(now);
+                    return Ok(Async::Ready(Some(item)))
+                }
+            }
+        }
+
+        if self.delay.poll(cx)?.is_ready() {
+            self.last = Some(delay::now(&self.delay));
+            Ok(Async::Ready(self.held.take()))
+        } else {
+            Ok(Async::Pending)
+        }
+    }
+}
+
+/// An extension trait for sinks which provides convenient accessors for timing
+/// out execution and such.
+pub trait SinkExt: Sink + Sized {
+
+    /// Creates a new future which sends `item` into this sink and flushes it,
+    /// taking at most `dur` time from the point at which this method is
+    /// called.
+    ///
+    /// The returned future resolves to this sink once the item has been
+    /// accepted and the sink has been flushed. If that takes longer than
+    /// `dur` then the future resolves to an error instead, created the same
+    /// way as for `FutureExt::timeout`.
+    ///
+    /// The item is only handed to the sink once the sink reports that it's
+    /// ready for it, so a timeout leaves the sink in a consistent state:
+    /// either the item was never given to the sink, and can be recovered with
+    /// `SendTimeout::into_inner`, or it was accepted and only flushing it
+    /// timed out. To keep using a sink after a timeout, send through a
+    /// mutable reference to it with `(&mut sink).send_timeout(item, dur)`.
+    fn send_timeout(self, item: Self::SinkItem, dur: Duration) -> SendTimeout<Self>
+        where Self::SinkError: From<io::Error>,
+    {
+        let (timeout, start) = arm(dur);
+        SendTimeout {
+            timeout,
+            start,
+            sink: Some(self),
+            item: Some(item),
+        }
+    }
+}
+
+impl<S: Sink> SinkExt for S {}
+
+/// Future returned by the `SinkExt::send_timeout` method.
+pub struct SendTimeout<S: Sink> {
+    timeout: Delay,
+    start: Instant,
+    sink: Option<S>,
+    item: Option<S::SinkItem>,
+}
+
+impl<S: Sink> SendTimeout<S> {
+    /// Returns a shared reference to the sink being sent to.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this future has already resolved.
+    pub fn get_ref(&self) -> &S {
+        self.sink.as_ref().expect("SendTimeout polled after completion")
+    }
+
+    /// Returns a mutable reference to the sink being sent to.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this future has already resolved.
+    pub fn get_mut(&mut self) -> &mut S {
+        self.sink.as_mut().expect("SendTimeout polled after completion")
+    }
+
+    /// Consumes this future, returning the sink being sent to along with the
+    /// item if the sink hasn't accepted it yet.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this future has already resolved.
+    pub fn into_inner(self) -> (S, Option<S::SinkItem>) {
+        let sink = self.sink.expect("SendTimeout polled after completion");
+        (sink, self.item)
+    }
+
+    fn poll_send(&mut self, cx: &mut task::Context) -> Poll<(), S::SinkError> {
+        let sink = self.sink.as_mut().expect("SendTimeout polled after completion");
+        if let Some(item) = self.item.take() {
+            match sink.poll_ready(cx)? {
+                Async::Ready(()) => sink.start_send(item)?,
+                Async::Pending => {
+                    self.item = Some(item);
+                    return Ok(Async::Pending)
+                }
+            }
+        }
+        sink.poll_flush(cx)
+    }
+}
+
+impl<S: Sink + fmt::Debug> fmt::Debug for SendTimeout<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SendTimeout")
+            .field("timeout", &self.timeout)
+            .field("sink", &self.sink)
+            .field("sent", &self.item.is_none())
+            .finish()
+    }
+}
+
+impl<S> Future for SendTimeout<S>
+    where S: Sink,
+          S::SinkError: From<io::Error>,
+{
+    type Item = S;
+    type Error = S::SinkError;
+
+    fn poll(&mut self, cx: &mut task::Context) -> Poll<S, S::SinkError> {
+        if self.poll_send(cx)?.is_ready() {
+            return Ok(Async::Ready(self.sink.take().unwrap()))
+        }
+
+        if self.timeout.poll(cx)?.is_ready() {
+            Err(io::Error::from(elapsed(TimeoutKind::Sink, &self.timeout, self.start)).into())
+        } else {
+            Ok(Async::Pending)
+        }
+    }
+}
+
+/// Creates a delay on the default timer firing `dur` from now, along with the
+/// instant it was started at.
+fn arm(dur: Duration) -> (Delay, Instant) {
+    let handle = TimerHandle::default();
+    let now = handle.now();
+    (Delay::new_handle(delay::after(now, dur), handle), now)
+}
+
+/// Returns `dur`, or the shortest period an `Interval` can tick at if `dur`
+/// is zero.
+fn min_period(dur: Duration) -> Duration {
+    cmp::max(dur, Duration::new(0, 1))
+}
+
+/// Creates a delay on the default timer firing at `at`, along with the instant
+/// it was started at.
+fn arm_at(at: Instant) -> (Delay, Instant) {
+    let handle = TimerHandle::default();
+    let now = handle.now();
+    (Delay::new_handle(at, handle), now)
+}
+
+/// Returns the error for the timeout `delay` of a combinator of the given
+/// kind, started at `start`, elapsing, and reports it to the timeout hook.
+fn elapsed(kind: TimeoutKind, delay: &Delay, start: Instant) -> Elapsed {
+    let err = Elapsed::new(delay.deadline().saturating_duration_since(start),
+                           delay::now(delay).saturating_duration_since(start));
+    ::timeout::fired(kind, &err);
+    err
+}
@@ -1,73 +1,205 @@
+use std::cmp;
+use std::fmt;
 use std::io;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, Weak};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use futures::prelude::*;
 use futures::executor::{SpawnError, Executor};
 
-use {TimerHandle, Timer};
+use {delay, TimerHandle, Timer};
 
-pub struct HelperThread {
+/// The helper thread powering the global timer, spawned lazily on first use.
+static HELPER: Mutex<Option<TimerThread>> = Mutex::new(None);
+
+/// Returns a handle to the global timer, spawning its helper thread if it
+/// isn't already running.
+///
+/// If the helper thread can't be spawned then a "defunkt" handle is returned
+/// which will return errors when timer objects are attempted to be
+/// associated.
+///
+/// Should the helper thread have died, for example because it panicked, then
+/// a fresh one is spawned in its place. The delays which were registered with
+/// the dead thread have been invalidated along with its timer, so they report
+/// an error rather than never firing.
+pub fn handle() -> TimerHandle {
+    let mut helper = HELPER.lock().unwrap_or_else(|e| e.into_inner());
+    let dead = if helper.as_ref().is_some_and(|h| !h.is_running()) {
+        helper.take()
+    } else {
+        None
+    };
+    if helper.is_none() {
+        if let Ok(thread) = TimerThread::spawn(Timer::new(), DEFAULT_THREAD_NAME.to_string()) {
+            *helper = Some(thread);
+        }
+    }
+    let handle = match *helper {
+        Some(ref thread) => thread.handle(),
+        None => TimerHandle { inner: Weak::new(), scope: None },
+    };
+
+    // Dropping the dead helper joins its thread, which may still be unwinding,
+    // so do so only once other callers can get at the fresh one.
+    drop(helper);
+    drop(dead);
+    handle
+}
+
+/// Shuts down the global helper thread, if it's running, once all of its
+/// pending timers have fired or been dropped.
+pub fn shutdown() {
+    // Take the helper out while holding the lock but wait for it outside of
+    // the lock so new timers can spin up a fresh helper in the meantime.
+    let helper = HELPER.lock().unwrap_or_else(|e| e.into_inner()).take();
+    drop(helper);
+}
+
+/// Shuts down the global helper thread like `shutdown`, but waits at most
+/// `timeout` for it to exit, returning whether it did.
+pub fn shutdown_timeout(timeout: Duration) -> bool {
+    let helper = HELPER.lock().unwrap_or_else(|e| e.into_inner()).take();
+    match helper {
+        Some(helper) => helper.join_timeout(timeout),
+        None => true,
+    }
+}
+
+/// Name of the threads timers are run on, unless configured otherwise.
+pub const DEFAULT_THREAD_NAME: &str = "futures-timer";
+
+/// A `Timer` running on a helper thread of its own.
+///
+/// This is created through `TimerBuilder::spawn`, and the global timer used
+/// by constructors like `Delay::new` is run this way, too. The thread is
+/// named `futures-timer` unless configured otherwise through
+/// `TimerBuilder::thread_name`, which makes it easy to recognize in
+/// debuggers and profilers.
+///
+/// Dropping a `TimerThread` blocks until all the timers registered with it
+/// have either fired or been dropped, after which its thread exits.
+pub struct TimerThread {
     thread: Option<thread::JoinHandle<()>>,
     timer: TimerHandle,
     done: Arc<AtomicBool>,
 }
 
-impl HelperThread {
-    pub fn new() -> io::Result<HelperThread> {
-        let timer = Timer::new();
+impl TimerThread {
+    pub(crate) fn spawn(timer: Timer, name: String) -> io::Result<TimerThread> {
         let timer_handle = timer.handle();
         let done = Arc::new(AtomicBool::new(false));
         let done2 = done.clone();
-        let thread = thread::Builder::new().spawn(move || run(timer, done2))?;
+        let thread = thread::Builder::new()
+            .name(name)
+            .spawn(move || run(timer, done2))?;
 
-        Ok(HelperThread {
+        Ok(TimerThread {
             thread: Some(thread),
             done,
             timer: timer_handle,
         })
     }
 
+    /// Returns a handle to the timer running on this thread, used to create
+    /// new delays.
     pub fn handle(&self) -> TimerHandle {
         self.timer.clone()
     }
 
-    pub fn forget(mut self) {
-        self.thread.take();
+    /// Returns whether the timer is still running on this thread.
+    ///
+    /// This is only `false` if the thread has died, which happens if it
+    /// panics. All the delays registered with the timer are invalidated when
+    /// this happens, so polling them returns an error, as does polling any
+    /// delay created from its handle afterwards.
+    pub fn is_running(&self) -> bool {
+        let finished = self.thread.as_ref().is_none_or(|t| t.is_finished());
+        !finished && self.timer.inner.upgrade().is_some()
     }
-}
 
-impl Drop for HelperThread {
-    fn drop(&mut self) {
-        let thread = match self.thread.take() {
-            Some(thread) => thread,
-            None => return,
-        };
+    /// Asks the thread to exit once its timers are done, returning it for
+    /// the caller to wait on.
+    fn stop(&mut self) -> Option<thread::JoinHandle<()>> {
+        let thread = self.thread.take()?;
         self.done.store(true, Ordering::SeqCst);
         thread.thread().unpark();
+        Some(thread)
+    }
+
+    /// Waits at most `timeout` for the thread to exit, as dropping it does
+    /// without a limit, returning whether it did.
+    ///
+    /// If it didn't then the thread is detached, and exits on its own once
+    /// its remaining timers have fired or been dropped.
+    fn join_timeout(mut self, timeout: Duration) -> bool {
+        let thread = match self.stop() {
+            Some(thread) => thread,
+            None => return true,
+        };
+        let deadline = delay::after(Instant::now(), timeout);
+        while !thread.is_finished() {
+            let now = Instant::now();
+            if now >= deadline {
+                return false
+            }
+            thread::sleep(cmp::min(deadline - now, Duration::from_millis(1)));
+        }
         drop(thread.join());
+        true
+    }
+}
+
+impl fmt::Debug for TimerThread {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = self.thread.as_ref().and_then(|t| t.thread().name());
+        f.debug_struct("TimerThread")
+            .field("name", &name)
+            .finish()
+    }
+}
+
+impl Drop for TimerThread {
+    fn drop(&mut self) {
+        if let Some(thread) = self.stop() {
+            drop(thread.join());
+        }
     }
 }
 
 fn run(mut timer: Timer, done: Arc<AtomicBool>) {
-	let me = Arc::new(ThreadUnpark {
-		thread: thread::current(),
-	});
+	let me = Arc::new(ThreadUnpark::current());
     let mut local_map = task::LocalMap::new();
     let waker = task::Waker::from(me);
     let mut exec = NonFunctionalExecutor;
     let mut cx = task::Context::new(&mut local_map, &waker, &mut exec);
-    while !done.load(Ordering::SeqCst) {
+    loop {
         drop(timer.poll(&mut cx));
         timer.advance();
-        match timer.next_event() {
+        let next = timer.next_event();
+
+        // Once we've been asked to shut down we keep running only for as long
+        // as there are timers left to fire. Timers registered right before the
+        // request may not have been processed yet though, so take one more
+        // look at the incoming updates before deciding to exit.
+        if done.load(Ordering::SeqCst) && next.is_none() {
+            let _ = timer.poll(&mut cx);
+            if timer.next_event().is_none() {
+                break
+            }
+            continue
+        }
+        match next {
             // Ok, block for the specified time
             Some(when) => {
-                let now = Instant::now();
-                if now < when {
-                    thread::park_timeout(when - now)
+                // The deadline is measured by the timer's clock, which isn't
+                // necessarily the system clock, so neither is the wait.
+                let now = timer.inner.clock.try_now().unwrap_or(when);
+                let wait = when.saturating_duration_since(now);
+                if wait > Duration::from_secs(0) {
+                    thread::park_timeout(wait)
                 } else {
                     // .. continue...
                 }
@@ -79,10 +211,20 @@ fn run(mut timer: Timer, done: Arc<AtomicBool>) {
     }
 }
 
-struct ThreadUnpark {
+/// Wakes a task by unparking the thread it's running on.
+pub struct ThreadUnpark {
     thread: thread::Thread,
 }
 
+impl ThreadUnpark {
+    /// Creates a waker for the current thread.
+    pub fn current() -> ThreadUnpark {
+        ThreadUnpark {
+            thread: thread::current(),
+        }
+    }
+}
+
 impl task::Wake for ThreadUnpark {
     fn wake(arc_self: &Arc<Self>) {
         arc_self.thread.unpark()
@@ -1,7 +1,8 @@
 //! A simple binary heap with support for removal of arbitrary elements
 //!
-//! This heap is used to manage timer state in the event loop. All timeouts go
-//! into this heap and we also cancel timeouts from this heap. The crucial
+//! This heap is used to manage the deadlines of a `DelaySet`, and as a model
+//! of the timer wheel in its tests. All deadlines go into this heap and we
+//! also cancel deadlines from this heap. The crucial
 //! feature of this heap over the standard library's `BinaryHeap` is the ability
 //! to remove arbitrary elements. (e.g. when a timer is canceled)
 //!
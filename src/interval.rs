@@ -1,3 +1,6 @@
+use std::cmp;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
 use std::time::{Duration, Instant};
 use std::io;
 
@@ -12,22 +15,78 @@ use delay;
 /// `Interval::new_at` methods indicating when a first notification
 /// should be triggered and when it will be repeated.
 ///
+/// Note that `Interval::new` waits a full period before its first tick, so
+/// an interval of a second ticks at one, two, three seconds and so on. Use
+/// `Interval::new_immediate` instead for an interval whose first tick is
+/// yielded right away, at zero, one, two seconds and so on, which suits
+/// polling loops that want to do their work once before waiting.
+///
 /// Note that intervals are not intended for high resolution timers, but rather
 /// they will likely fire some granularity after the exact instant that they're
 /// otherwise indicated to fire at.
+///
+/// Ticks are scheduled at a fixed rate: each one is due a period after the
+/// instant the previous one was scheduled for, not after the instant it was
+/// observed at. The lateness of individual ticks, whether because of the
+/// granularity of the timer or a busy consumer, therefore doesn't accumulate
+/// into drift. Only once a whole tick has been missed does the configured
+/// `MissedTickBehavior` come into play.
 pub struct Interval {
     delay: Delay,
     interval: Duration,
+    missed_tick_behavior: MissedTickBehavior,
+    jitter: f64,
+    rng: Option<u64>,
+    missed: u64,
+}
+
+/// Policy describing how an `Interval` schedules its next tick after it has
+/// been polled too late to observe one or more ticks on time.
+///
+/// The policy is configured through `Interval::set_missed_tick_behavior` and
+/// defaults to `MissedTickBehavior::Burst`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MissedTickBehavior {
+    /// Fire each missed tick immediately, one after another, until the
+    /// interval has caught up with its original schedule.
+    #[default]
+    Burst,
+
+    /// Schedule the next tick one full period after the late tick was
+    /// observed, shifting the schedule of all subsequent ticks.
+    Delay,
+
+    /// Skip any missed ticks and schedule the next tick at the next period
+    /// boundary of the original schedule which is still in the future.
+    Skip,
 }
 
+
 impl Interval {
     /// Creates a new interval which will fire at `dur` time into the future,
     /// and will repeat every `dur` interval after
     ///
+    /// The first tick is a full period away, see `new_immediate` for an
+    /// interval which ticks right away.
+    ///
     /// The returned object will be bound to the default timer for this thread.
     /// The default timer will be spun up in a helper thread on first use.
     pub fn new(dur: Duration) -> Interval {
-        Interval::new_at(Instant::now() + dur, dur)
+        let handle = TimerHandle::default();
+        Interval::new_handle(delay::after(handle.now(), dur), dur, handle)
+    }
+
+    /// Creates a new interval which fires as soon as it's first polled, and
+    /// then repeats every `dur` interval after
+    ///
+    /// The ticks after the first one are scheduled from the moment the
+    /// interval was created, just like those of `new` are.
+    ///
+    /// The returned object will be bound to the default timer for this thread.
+    /// The default timer will be spun up in a helper thread on first use.
+    pub fn new_immediate(dur: Duration) -> Interval {
+        let handle = TimerHandle::default();
+        Interval::new_handle(handle.now(), dur, handle)
     }
 
     /// Creates a new interval which will fire at the time specified by `at`,
@@ -39,6 +98,10 @@ impl Interval {
         Interval {
             delay: Delay::new_at(at),
             interval: dur,
+            missed_tick_behavior: MissedTickBehavior::default(),
+            jitter: 0.0,
+            rng: None,
+            missed: 0,
         }
     }
 
@@ -50,8 +113,213 @@ impl Interval {
         Interval {
             delay: Delay::new_handle(at, handle),
             interval: dur,
+            missed_tick_behavior: MissedTickBehavior::default(),
+            jitter: 0.0,
+            rng: None,
+            missed: 0,
         }
     }
+
+    /// Creates a new interval whose ticks are spaced by a random duration
+    /// between `dur * (1 - jitter)` and `dur * (1 + jitter)`.
+    ///
+    /// When many clients use an interval with the same period, their ticks
+    /// tend to line up and all hit a server at the same moment. Spreading
+    /// out the ticks a little avoids that, at the price of breaking the
+    /// exact periodicity of the interval by design: the spacing of every
+    /// tick, including the first one, is drawn anew, so the ticks drift
+    /// away from any fixed schedule over time. See `set_jitter` for more
+    /// information.
+    ///
+    /// The returned object will be bound to the default timer for this thread.
+    /// The default timer will be spun up in a helper thread on first use.
+    pub fn with_jitter(dur: Duration, jitter: f64) -> Interval {
+        let handle = TimerHandle::default();
+        let now = handle.now();
+        let mut interval = Interval::new_handle(now, dur, handle);
+        interval.set_jitter(jitter);
+        let first = interval.next_period();
+        interval.reset_at(delay::after(now, first));
+        interval
+    }
+
+    /// Restarts this interval so that its next tick is a full period from
+    /// now.
+    ///
+    /// This is equivalent to calling `reset_at` with the current time of the
+    /// associated timer's clock plus the period of this interval.
+    pub fn reset(&mut self) {
+        let at = delay::after(delay::now(&self.delay), self.interval);
+        self.reset_at(at)
+    }
+
+    /// Schedules the next tick of this interval at the instant `at`.
+    ///
+    /// Any tick which is currently pending is cancelled, and the ticks after
+    /// the next one continue to be spaced by the period of this interval. If
+    /// `at` is in the past then the next tick is considered missed and fires
+    /// immediately, with the tick after it scheduled according to the
+    /// configured `MissedTickBehavior`.
+    pub fn reset_at(&mut self, at: Instant) {
+        self.delay.reset_at(at);
+    }
+
+    /// Returns the period this interval repeats at.
+    pub fn period(&self) -> Duration {
+        self.interval
+    }
+
+    /// Changes the period this interval repeats at, starting with the tick
+    /// after the pending one.
+    ///
+    /// The tick which is currently pending still fires when it was scheduled
+    /// to, and the tick after it is then scheduled `dur` after it rather than
+    /// one old period after it. Use `set_period_immediate` for the change to
+    /// affect the pending tick as well.
+    pub fn set_period(&mut self, dur: Duration) {
+        self.interval = dur;
+    }
+
+    /// Changes the period this interval repeats at, rescheduling the
+    /// pending tick to be `dur` from now.
+    ///
+    /// This is equivalent to calling `set_period` followed by `reset`.
+    pub fn set_period_immediate(&mut self, dur: Duration) {
+        self.set_period(dur);
+        self.reset();
+    }
+
+    /// Returns the policy used to schedule the next tick when this interval
+    /// is polled late.
+    pub fn missed_tick_behavior(&self) -> MissedTickBehavior {
+        self.missed_tick_behavior
+    }
+
+    /// Configures how the next tick is scheduled when this interval is polled
+    /// after one or more ticks were due.
+    ///
+    /// See `MissedTickBehavior` for the available policies.
+    pub fn set_missed_tick_behavior(&mut self, behavior: MissedTickBehavior) {
+        self.missed_tick_behavior = behavior;
+    }
+
+    /// Returns the total number of ticks this interval has dropped because
+    /// it was polled too late to observe them.
+    ///
+    /// Ticks are only ever dropped under the `Skip` and `Delay` missed tick
+    /// behaviors, as `Burst` fires every missed tick eventually. The count
+    /// accumulates over the lifetime of the interval, and is never reset.
+    pub fn missed_ticks(&self) -> u64 {
+        self.missed
+    }
+
+    /// Returns the fraction of the period by which the spacing of ticks is
+    /// randomized, which is zero unless configured otherwise.
+    pub fn jitter(&self) -> f64 {
+        self.jitter
+    }
+
+    /// Configures the fraction of the period by which the spacing of ticks
+    /// is randomized.
+    ///
+    /// Every tick after the next one is scheduled a random duration between
+    /// `period * (1 - jitter)` and `period * (1 + jitter)` after the previous
+    /// one, rather than exactly one period after it. The fraction is clamped
+    /// to between zero and one, and a fraction of zero disables jitter. Ticks
+    /// are always at least a nanosecond apart, even with a full jitter.
+    ///
+    /// Unless seeded through `set_jitter_seed`, the offsets are drawn from a
+    /// pseudo-random generator seeded differently for every interval.
+    pub fn set_jitter(&mut self, jitter: f64) {
+        self.jitter = if jitter > 0.0 { jitter.min(1.0) } else { 0.0 };
+    }
+
+    /// Seeds the generator the random offsets of ticks are drawn from, which
+    /// makes them reproducible, for example in tests.
+    pub fn set_jitter_seed(&mut self, seed: u64) {
+        self.rng = Some(seed);
+    }
+
+    /// Polls for the next tick of this interval, returning `Ready` once it's
+    /// due and scheduling the tick after it.
+    ///
+    /// This is what the `Stream` implementation of `Interval` uses under the
+    /// hood, without wrapping ticks in an `Option`, which is convenient when
+    /// driving an interval by hand as part of a larger state machine. The
+    /// next tick is scheduled according to the configured
+    /// `MissedTickBehavior` and jitter, just like it is for `poll_next`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the associated timer has gone away.
+    pub fn poll_tick(&mut self, cx: &mut task::Context) -> Poll<(), io::Error> {
+        Ok(self.poll_tick_at(cx)?.map(|_| ()))
+    }
+
+    /// Polls for the next tick of this interval like `poll_tick`, returning
+    /// the instant the tick was scheduled for once it's due.
+    ///
+    /// The scheduled instant is the nominal time of the tick rather than the
+    /// time it was observed at, which may be later if the timer fired late
+    /// or this interval was polled late.
+    pub fn poll_tick_at(&mut self, cx: &mut task::Context) -> Poll<Instant, io::Error> {
+        if self.delay.poll(cx)?.is_pending() {
+            return Ok(Async::Pending)
+        }
+        let period = self.next_period();
+        let prev = self.delay.deadline();
+        let now = delay::now(&self.delay);
+        if self.missed_tick_behavior != MissedTickBehavior::Burst {
+            self.missed += count_missed(prev, now, period);
+        }
+        let next = next_tick(self.missed_tick_behavior, prev, now, period);
+        self.delay.reset_at(next);
+        Ok(Async::Ready(prev))
+    }
+
+    /// Converts this interval into a stream which yields the instant each
+    /// tick was scheduled for, rather than `()`.
+    pub fn into_scheduled(self) -> ScheduledInterval {
+        ScheduledInterval { interval: self }
+    }
+
+    /// Returns the spacing of the next tick, with jitter applied.
+    fn next_period(&mut self) -> Duration {
+        if self.jitter == 0.0 {
+            return self.interval
+        }
+        let rng = self.rng.get_or_insert_with(random_seed);
+        jittered(self.interval, self.jitter, next_unit(rng))
+    }
+}
+
+/// Returns `interval` scaled by a factor between `1 - jitter` and
+/// `1 + jitter`, picked by `unit` from `[0, 1)`.
+///
+/// The result is at least a nanosecond, as a full jitter can draw a factor
+/// of zero, and a zero period would have the interval fire continuously.
+fn jittered(interval: Duration, jitter: f64, unit: f64) -> Duration {
+    let factor = 1.0 + jitter * (2.0 * unit - 1.0);
+    let period = Duration::try_from_secs_f64(interval.as_secs_f64() * factor)
+        .unwrap_or(interval);
+    cmp::max(period, Duration::new(0, 1))
+}
+
+/// Returns a seed for the generator of jittered intervals which is different
+/// each time.
+fn random_seed() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// Advances the splitmix64 generator `state`, returning a value uniformly
+/// distributed in `[0, 1)`.
+fn next_unit(state: &mut u64) -> f64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
 }
 
 impl Stream for Interval {
@@ -59,52 +327,161 @@ impl Stream for Interval {
     type Error = io::Error;
 
     fn poll_next(&mut self, cx: &mut task::Context) -> Poll<Option<()>, io::Error> {
-        if self.delay.poll(cx)?.is_pending() {
-            return Ok(Async::Pending)
-        }
-        let next = next_interval(delay::fires_at(&self.delay),
-                                 Instant::now(),
-                                 self.interval);
-        self.delay.reset_at(next);
-        Ok(Async::Ready(Some(())))
+        Ok(self.poll_tick(cx)?.map(Some))
     }
 }
 
-/// Converts Duration object to raw nanoseconds if possible
+/// A stream of the ticks of an `Interval` which yields the instant each tick
+/// was scheduled for.
 ///
-/// This is useful to divide intervals.
+/// This is created through `ScheduledInterval::new` or
+/// `Interval::into_scheduled`, and ticks exactly like the underlying
+/// `Interval` does. Each item is the nominal time of its tick rather than
+/// the time it was observed at, so consecutive items of an interval with the
+/// default `MissedTickBehavior` are exactly one period apart no matter how
+/// late the ticks were serviced, which keeps time arithmetic downstream from
+/// picking up scheduling lag.
+pub struct ScheduledInterval {
+    interval: Interval,
+}
+
+impl ScheduledInterval {
+    /// Creates a new interval which will fire at `dur` time into the future,
+    /// and will repeat every `dur` interval after, yielding the scheduled
+    /// instant of every tick.
+    ///
+    /// The returned object will be bound to the default timer for this thread.
+    /// The default timer will be spun up in a helper thread on first use.
+    pub fn new(dur: Duration) -> ScheduledInterval {
+        Interval::new(dur).into_scheduled()
+    }
+
+    /// Returns a shared reference to the underlying interval.
+    pub fn get_ref(&self) -> &Interval {
+        &self.interval
+    }
+
+    /// Returns a mutable reference to the underlying interval, for example
+    /// to configure its `MissedTickBehavior`.
+    pub fn get_mut(&mut self) -> &mut Interval {
+        &mut self.interval
+    }
+
+    /// Consumes this stream, returning the underlying interval.
+    pub fn into_inner(self) -> Interval {
+        self.interval
+    }
+}
+
+impl Stream for ScheduledInterval {
+    type Item = Instant;
+    type Error = io::Error;
+
+    fn poll_next(&mut self, cx: &mut task::Context) -> Poll<Option<Instant>, io::Error> {
+        Ok(self.interval.poll_tick_at(cx)?.map(Some))
+    }
+}
+
+/// Creates a new stream which yields `n`, `n - 1`, ..., `1` spaced by
+/// `period`, and finishes after the last tick.
+///
+/// The first item is yielded `period` after this function is called, so the
+/// whole countdown takes `n * period`. Ticks are scheduled like those of an
+/// `Interval`, so one which is observed late doesn't delay the ones after it.
 ///
-/// While technically for large duration it's impossible to represent any
-/// duration as nanoseconds, the largest duration we can represent is about
-/// 427_000 years. Large enough for any interval we would use or calculate in
-/// tokio.
-fn duration_to_nanos(dur: Duration) -> Option<u64> {
-    dur.as_secs()
-        .checked_mul(1_000_000_000)
-        .and_then(|v| v.checked_add(dur.subsec_nanos() as u64))
+/// The returned stream will be bound to the default timer for this thread.
+/// The default timer will be spun up in a helper thread on first use.
+pub fn countdown(n: u32, period: Duration) -> Countdown {
+    Countdown {
+        interval: Interval::new(period),
+        remaining: n,
+    }
+}
+
+/// Stream returned by the `countdown` function.
+pub struct Countdown {
+    interval: Interval,
+    remaining: u32,
+}
+
+impl Countdown {
+    /// Returns the number of ticks left in this countdown.
+    pub fn remaining(&self) -> u32 {
+        self.remaining
+    }
+}
+
+impl Stream for Countdown {
+    type Item = u32;
+    type Error = io::Error;
+
+    fn poll_next(&mut self, cx: &mut task::Context) -> Poll<Option<u32>, io::Error> {
+        if self.remaining == 0 {
+            return Ok(Async::Ready(None))
+        }
+        if self.interval.poll_next(cx)?.is_pending() {
+            return Ok(Async::Pending)
+        }
+        let n = self.remaining;
+        self.remaining -= 1;
+        Ok(Async::Ready(Some(n)))
+    }
+}
+
+fn next_tick(behavior: MissedTickBehavior,
+             prev: Instant,
+             now: Instant,
+             interval: Duration) -> Instant {
+    let new = delay::after(prev, interval);
+    if new > now {
+        return new
+    }
+    match behavior {
+        MissedTickBehavior::Burst => new,
+        MissedTickBehavior::Delay => delay::after(now, interval),
+        MissedTickBehavior::Skip => next_interval(prev, now, interval),
+    }
+}
+
+/// Returns how many ticks spaced by `interval` after the one due at `prev`
+/// were due by `now` as well.
+fn count_missed(prev: Instant, now: Instant, interval: Duration) -> u64 {
+    let interval = interval.as_nanos();
+    if now <= prev || interval == 0 {
+        return 0
+    }
+    let missed = (now - prev).as_nanos() / interval;
+    if missed > u128::from(u64::MAX) {
+        u64::MAX
+    } else {
+        missed as u64
+    }
 }
 
 fn next_interval(prev: Instant, now: Instant, interval: Duration) -> Instant {
-    let new = prev + interval;
+    let new = delay::after(prev, interval);
     if new > now {
         return new;
-    } else {
-        let spent_ns = duration_to_nanos(now.duration_since(prev))
-            .expect("interval should be expired");
-        let interval_ns = duration_to_nanos(interval)
-            .expect("interval is less that 427 thousand years");
-        let mult = spent_ns/interval_ns + 1;
-        assert!(mult < (1 << 32),
-            "can't skip more than 4 billion intervals of {:?} \
-             (trying to skip {})", interval, mult);
-        return prev + interval * (mult as u32);
     }
+    let interval_ns = interval.as_nanos();
+    if interval_ns == 0 {
+        return now;
+    }
+
+    // Nanoseconds are counted in a `u128`, which holds the sum of any two
+    // durations, so skipping however many intervals can't overflow.
+    let mult = now.duration_since(prev).as_nanos() / interval_ns + 1;
+    let skip_ns = mult * interval_ns;
+    let secs = cmp::min(skip_ns / 1_000_000_000, u128::from(u64::MAX)) as u64;
+    let skip = Duration::new(secs, (skip_ns % 1_000_000_000) as u32);
+    delay::after(prev, skip)
 }
 
 #[cfg(test)]
 mod test {
     use std::time::{Instant, Duration};
-    use super::next_interval;
+    use super::{count_missed, jittered, next_interval, next_tick, next_unit};
+    use super::MissedTickBehavior;
 
     struct Timeline(Instant);
 
@@ -147,6 +524,14 @@ mod test {
                                         tm.at(2101)));
     }
 
+    #[test]
+    fn zero_interval_next() {
+        let tm = Timeline::new();
+        assert_eq!(next_interval(tm.at(1), tm.at(1000), dur(0)), tm.at(1000));
+        assert_eq!(next_tick(MissedTickBehavior::Skip, tm.at(1), tm.at(5), dur(0)),
+                   tm.at(5));
+    }
+
     #[test]
     fn fast_forward() {
         let tm = Timeline::new();
@@ -158,15 +543,58 @@ mod test {
                                         tm.at(10501)));
     }
 
-    /// TODO: this test actually should be successful, but since we can't
-    ///       multiply Duration on anything larger than u32 easily we decided
-    ///       to allow it to fail for now
     #[test]
-    #[should_panic(expected = "can't skip more than 4 billion intervals")]
     fn large_skip() {
         let tm = Timeline::new();
         assert_eq!(next_interval(
             tm.at_ns(0, 1), tm.at_ns(25, 0), Duration::new(0, 2)),
             tm.at_ns(25, 1));
+        assert_eq!(next_interval(
+            tm.at_ns(0, 0), tm.at_ns(5, 0), Duration::new(0, 1)),
+            tm.at_ns(5, 1));
+    }
+
+    #[test]
+    fn missed_count() {
+        let tm = Timeline::new();
+        assert_eq!(count_missed(tm.at(100), tm.at(150), dur(100)), 0);
+        assert_eq!(count_missed(tm.at(100), tm.at(200), dur(100)), 1);
+        assert_eq!(count_missed(tm.at(100), tm.at(350), dur(100)), 2);
+        assert_eq!(count_missed(tm.at(100), tm.at(50), dur(100)), 0);
+        assert_eq!(count_missed(tm.at(100), tm.at(350), dur(0)), 0);
+    }
+
+    #[test]
+    fn unit_range() {
+        let mut state = 42;
+        let samples = (0..10_000).map(|_| next_unit(&mut state)).collect::<Vec<_>>();
+        assert!(samples.iter().all(|u| (0.0..1.0).contains(u)));
+        assert!(samples.iter().any(|&u| u < 0.1));
+        assert!(samples.iter().any(|&u| u > 0.9));
+
+        let mut again = 42;
+        assert_eq!(next_unit(&mut again), samples[0]);
+    }
+
+    #[test]
+    fn jittered_range() {
+        assert_eq!(jittered(dur(100), 0.5, 0.5), dur(100));
+        assert_eq!(jittered(dur(100), 0.5, 0.0), dur(50));
+        assert_eq!(jittered(dur(100), 1.0, 0.0), Duration::new(0, 1));
+        assert_eq!(jittered(Duration::new(0, 10), 1.0, 0.01), Duration::new(0, 1));
+    }
+
+    #[test]
+    fn missed_ticks() {
+        let tm = Timeline::new();
+        let next = |b| next_tick(b, tm.at(100), tm.at(350), dur(100));
+        assert!(almost_eq(next(MissedTickBehavior::Burst), tm.at(200)));
+        assert!(almost_eq(next(MissedTickBehavior::Delay), tm.at(450)));
+        assert!(almost_eq(next(MissedTickBehavior::Skip), tm.at(400)));
+
+        let next = |b| next_tick(b, tm.at(100), tm.at(150), dur(100));
+        assert!(almost_eq(next(MissedTickBehavior::Burst), tm.at(200)));
+        assert!(almost_eq(next(MissedTickBehavior::Delay), tm.at(200)));
+        assert!(almost_eq(next(MissedTickBehavior::Skip), tm.at(200)));
     }
 }
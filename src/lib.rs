@@ -48,8 +48,9 @@
 //! The `Delay` and `Interval` types are powered by an associated `Timer`. By
 //! default constructors like `Delay::new` and `Interval::new` use a global
 //! instance of `Timer` to power their usage. This global `Timer` is spawned
-//! onto a helper thread which continuously runs in the background sending out
-//! timer notifications.
+//! onto a helper thread on first use which then runs in the background
+//! sending out timer notifications, until it's shut down with the `shutdown`
+//! function.
 //!
 //! If needed, however, a `Timer` can be constructed manually and the
 //! `Delay::new_handle`-style methods can be used to create delays/intervals
@@ -60,37 +61,61 @@
 //! globally configure the fallback timer handle as well if you'd like to run
 //! your own timer.
 //!
-//! Finally, the implementation of `Timer` itself is currently a binary heap.
-//! Timer insertion is O(log n) where n is the number of active timers, and so
-//! is firing a timer (which invovles removing from the heap).
+//! Finally, the implementation of `Timer` itself is a hierarchical timing
+//! wheel. Inserting, resetting and cancelling a timer are all O(1) regardless
+//! of the number of active timers, and firing a timer is amortized O(1) as
+//! well.
 
 #![deny(missing_docs)]
 
 extern crate futures;
 
-use std::cmp::Ordering;
+use std::io;
 use std::mem;
 use std::sync::atomic::Ordering::SeqCst;
 use std::sync::atomic::{AtomicUsize, ATOMIC_USIZE_INIT};
 use std::sync::{Arc, Weak, Mutex};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use futures::task::AtomicWaker;
 use futures::{Future, Async, Poll, task};
 
 use arc_list::{ArcList, Node};
-use heap::{Heap, Slot};
+use wheel::{Wheel, Slot};
 
 mod arc_list;
+mod builder;
+mod clock;
+#[cfg(feature = "std-future")]
+mod compat;
 mod global;
 mod heap;
+mod local;
+mod manual;
+mod registration;
+mod retry;
+mod scope;
+mod timeout;
+mod wheel;
 pub mod ext;
-pub use ext::{FutureExt, StreamExt};
-
-/// A "timer heap" used to power separately owned instances of `Delay` and
+pub use builder::{DelayBuilder, TimerBuilder};
+pub use clock::{Clock, SystemClock, TestClock};
+#[cfg(feature = "std-future")]
+pub use compat::Compat;
+pub use ext::{FutureExt, SinkExt, StreamExt, select_timeout, timeout, timeout_at};
+pub use ext::remaining_timeout;
+pub use global::TimerThread;
+pub use local::TimerTask;
+pub use manual::ManualTimer;
+pub use registration::Registration;
+pub use retry::{retry, Retry, RetryPolicy};
+pub use scope::TimerScope;
+pub use timeout::{Cancelled, Elapsed, SetHookError, TimeoutKind, on_timeout_fired};
+
+/// A "timer wheel" used to power separately owned instances of `Delay` and
 /// `Interval`.
 ///
-/// This timer is implemented as a priority queued-based heap. Each `Timer`
+/// This timer is implemented as a hierarchical timing wheel. Each `Timer`
 /// contains a few primary methods which which to drive it:
 ///
 /// * `next_wake` indicates how long the ambient system needs to sleep until it
@@ -110,19 +135,39 @@ pub use ext::{FutureExt, StreamExt};
 /// `TimerHandle::set_fallback` method can be used instead!
 pub struct Timer {
     inner: Arc<Inner>,
-    timer_heap: Heap<HeapTimer>,
+
+    /// The instant this timer was created at, which tick boundaries are
+    /// measured from.
+    origin: Instant,
+
+    /// Granularity which deadlines are rounded up to, if any.
+    resolution: Option<Duration>,
+
+    /// Window within which deadlines are rounded up to a shared boundary, if
+    /// any.
+    coalescing: Option<Duration>,
+
+    /// Maximum number of timers fired by each call to `advance_to`, if any.
+    drain_budget: Option<usize>,
 }
 
 /// A handle to a `Timer` which is used to create instances of a `Delay`.
 #[derive(Clone)]
 pub struct TimerHandle {
     inner: Weak<Inner>,
+    scope: Option<Arc<scope::Registry>>,
 }
 
+mod backoff;
+mod deadline;
 mod delay;
+mod delay_set;
 mod interval;
-pub use self::delay::Delay;
-pub use self::interval::Interval;
+pub use self::backoff::BackoffInterval;
+pub use self::deadline::Deadline;
+pub use self::delay::{Delay, sleep, sleep_until};
+pub use self::delay_set::DelaySet;
+pub use self::interval::{Countdown, Interval, MissedTickBehavior, ScheduledInterval, countdown};
 
 struct Inner {
     /// List of updates the `Timer` needs to process
@@ -130,6 +175,114 @@ struct Inner {
 
     /// The blocked `Timer` task to receive notifications to the `list` above.
     waker: AtomicWaker,
+
+    /// Source of the current time for this timer.
+    clock: Box<dyn Clock>,
+
+    /// Number of registrations which are neither fired nor dropped.
+    active: AtomicUsize,
+
+    /// Incremented for every new registration, so that registrations with
+    /// equal deadlines fire in the order they were created.
+    next_seq: AtomicUsize,
+
+    /// Kind and description of the last error returned by the clock, which
+    /// delays failed because of it report instead of firing.
+    error: Mutex<Option<(io::ErrorKind, String)>>,
+
+    /// Timers which have been processed by the `Timer`, keyed by deadline.
+    ///
+    /// This is only ever locked briefly, by the `Timer` itself and by handles
+    /// peeking at it or firing expired timers. When both a wheel and a slot
+    /// are locked the wheel is locked first.
+    wheel: Mutex<Wheel<WheelTimer>>,
+
+    /// Registrations no longer referenced by any `Delay`, kept around so new
+    /// delays can reuse them instead of allocating.
+    ///
+    /// The pool is split into shards, each used by a subset of the threads
+    /// creating delays, so that threads don't all contend on one lock.
+    pool: Vec<Mutex<Vec<Arc<Node<ScheduledTimer>>>>>,
+
+    /// Remaining time below which delays spin until their deadline rather
+    /// than waiting to be fired, if any.
+    spin_threshold: Option<Duration>,
+}
+
+/// Number of shards the pool of unused registrations is split into.
+const POOL_SHARDS: usize = 8;
+
+/// Maximum number of unused registrations each shard of the pool keeps.
+const POOL_CAPACITY: usize = 256;
+
+/// Returns the pool shard used by the current thread.
+fn current_shard() -> usize {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    thread_local!(static SHARD: usize = NEXT.fetch_add(1, SeqCst) % POOL_SHARDS);
+    SHARD.with(|shard| *shard)
+}
+
+impl Inner {
+    /// Returns a registration for `timer`, reusing one from the pool shard
+    /// of `timer` if possible.
+    fn alloc(&self, timer: ScheduledTimer) -> Arc<Node<ScheduledTimer>> {
+        let pooled = self.pool[timer.shard].lock().unwrap().pop();
+        match pooled {
+            Some(mut node) => {
+                **Arc::get_mut(&mut node).unwrap() = timer;
+                node
+            }
+            None => Arc::new(Node::new(timer)),
+        }
+    }
+
+    /// Fires at most `budget` timers in the wheel which have expired by `now`,
+    /// waking their tasks.
+    fn fire_expired(&self, now: Instant, mut budget: usize) {
+        // Flag each expired timer as fired, and then notify its task, if any,
+        // that's blocked once the wheel is unlocked again.
+        let mut fired = Vec::new();
+        let mut wheel = self.wheel.lock().unwrap();
+        while budget > 0 {
+            let wheel_timer = match wheel.poll(now) {
+                Some(wheel_timer) => wheel_timer,
+                None => break,
+            };
+            *wheel_timer.node.slot.lock().unwrap() = None;
+            let bits = wheel_timer.gen << 2;
+            match wheel_timer.node.state.compare_exchange(bits, bits | 0b01, SeqCst, SeqCst) {
+                Ok(_) => {
+                    self.active.fetch_sub(1, SeqCst);
+                    fired.push(wheel_timer.node);
+                    budget -= 1;
+                }
+                Err(_b) => {}
+            }
+        }
+        drop(wheel);
+        for node in fired {
+            node.waker.wake();
+        }
+    }
+
+    /// Returns the error delays fail with if the clock has failed.
+    fn error(&self) -> Option<io::Error> {
+        let error = self.error.lock().unwrap();
+        error.as_ref().map(|&(kind, ref msg)| {
+            io::Error::new(kind, format!("timer clock failed: {}", msg))
+        })
+    }
+
+    /// Returns `node` to its pool shard if nothing else refers to it anymore.
+    fn recycle(&self, mut node: Arc<Node<ScheduledTimer>>) {
+        if Arc::get_mut(&mut node).is_none() {
+            return
+        }
+        let mut pool = self.pool[node.shard].lock().unwrap();
+        if pool.len() < POOL_CAPACITY {
+            pool.push(node);
+        }
+    }
 }
 
 /// Shared state between the `Timer` and a `Delay`.
@@ -148,31 +301,116 @@ struct ScheduledTimer {
     // TODO: this is only accessed by the timer thread, should have a more
     // lightweight protection than a `Mutex`
     slot: Mutex<Option<Slot>>,
+
+    // Shard of the pool this registration is returned to once it's unused,
+    // which is that of the thread which created it.
+    shard: usize,
+
+    // Coalescing tolerance of this registration in particular, overriding
+    // that of the timer. A zero tolerance means exact scheduling.
+    slack: Option<Duration>,
+
+    // Order in which this registration was created among those of its timer,
+    // which breaks ties between equal deadlines.
+    seq: usize,
 }
 
-/// Entries in the timer heap, sorted by the instant they're firing at and then
-/// also containing some payload data.
-struct HeapTimer {
-    at: Instant,
+/// Entries in the timer wheel, which keeps track of the instant they're firing
+/// at alongside this payload data.
+struct WheelTimer {
     gen: usize,
     node: Arc<Node<ScheduledTimer>>,
 }
 
 impl Timer {
-    /// Creates a new timer heap ready to create new timers.
+    /// Creates a new timer wheel ready to create new timers.
     pub fn new() -> Timer {
-        Timer {
-            inner: Arc::new(Inner {
-                list: ArcList::new(),
-                waker: AtomicWaker::new(),
-            }),
-            timer_heap: Heap::new(),
-        }
+        Timer::with_clock(SystemClock)
+    }
+
+    /// Creates a new timer wheel which reads the current time from `clock`.
+    ///
+    /// The clock is used by `advance` to determine which timers need to fire,
+    /// and by delays associated with this timer to compute relative deadlines
+    /// and determine whether they've elapsed.
+    pub fn with_clock<C: Clock + 'static>(clock: C) -> Timer {
+        Timer::builder().clock(clock).build()
+    }
+
+    /// Creates a new timer wheel which fires timers with a granularity of
+    /// `resolution`.
+    ///
+    /// The deadline of every timer is rounded up to the next multiple of
+    /// `resolution` since the creation of this timer, so timers never fire
+    /// early but may fire up to `resolution` late. In exchange `next_event`
+    /// only ever reports tick boundaries, so an event loop sleeping until
+    /// `next_event` wakes up at most once per `resolution` no matter how many
+    /// timers are active. A coarse resolution therefore trades precision for
+    /// fewer wakeups, and a fine one the other way around.
+    pub fn with_resolution(resolution: Duration) -> Timer {
+        Timer::builder().resolution(resolution).build()
+    }
+
+    /// Creates a new timer wheel which coalesces deadlines falling within the
+    /// same window of `tolerance`.
+    ///
+    /// The deadline of every timer is rounded up to the next multiple of
+    /// `tolerance` since the creation of this timer, so all timers within one
+    /// window share a deadline and are fired together in a single call to
+    /// `advance`. This trades a little lateness, at most `tolerance`, for far
+    /// fewer wakeups when many timers are set with similar deadlines, such as
+    /// the timeouts of many connections created around the same time.
+    ///
+    /// If a resolution is configured as well then coalescing is applied on
+    /// top of the rounding to the resolution.
+    pub fn with_coalescing(tolerance: Duration) -> Timer {
+        Timer::builder().coalescing(tolerance).build()
+    }
+
+    /// Creates a new timer wheel whose delays spin until their deadline when
+    /// they're polled with less than `threshold` time remaining.
+    ///
+    /// Waking up a thread at a precise instant is at the mercy of the
+    /// operating system's scheduler, which commonly overshoots by tens of
+    /// microseconds or more. For very short delays that can be way longer
+    /// than the delay itself. With a spin threshold configured, polling a
+    /// `Delay` whose deadline is less than `threshold` away doesn't return
+    /// until the deadline has passed, repeatedly yielding the thread in the
+    /// meantime, so the delay resolves as close to its deadline as possible.
+    ///
+    /// This comes at a real cost: the polling thread is kept busy for up to
+    /// `threshold` for every such poll, and can't run any other task in the
+    /// meantime. Only use this for latency-sensitive code with thresholds
+    /// in the order of microseconds.
+    pub fn with_spin_threshold(threshold: Duration) -> Timer {
+        Timer::builder().spin_threshold(threshold).build()
+    }
+
+    /// Returns a builder which can be used to create a timer with a custom
+    /// configuration.
+    pub fn builder() -> TimerBuilder {
+        TimerBuilder::new()
     }
 
-    /// Returns a handle to this timer heap, used to create new timeouts.
+    /// Creates a new timer wheel which runs as a task, along with a handle to
+    /// it.
+    ///
+    /// The returned `TimerTask` needs to be polled for delays associated
+    /// with the handle to fire, see `TimerTask` for more information.
+    pub fn task() -> (TimerTask, TimerHandle) {
+        let timer = Timer::new();
+        let handle = timer.handle();
+        (timer.into_task(), handle)
+    }
+
+    /// Converts this timer into a task which drives it whenever it's polled.
+    pub fn into_task(self) -> TimerTask {
+        TimerTask::new(self)
+    }
+
+    /// Returns a handle to this timer wheel, used to create new timeouts.
     pub fn handle(&self) -> TimerHandle {
-        TimerHandle { inner: Arc::downgrade(&self.inner) }
+        TimerHandle { inner: Arc::downgrade(&self.inner), scope: None }
     }
 
     /// Returns the time at which this timer next needs to be invoked with
@@ -181,39 +419,39 @@ impl Timer {
     /// Event loops or threads typically want to sleep until the specified
     /// instant.
     pub fn next_event(&self) -> Option<Instant> {
-        self.timer_heap.peek().map(|t| t.at)
+        self.inner.wheel.lock().unwrap().peek()
     }
 
     /// Proces any timers which are supposed to fire at or before the current
     /// instant.
     ///
-    /// This method is equivalent to `self.advance_to(Instant::now())`.
+    /// This method is equivalent to `self.advance_to(now)` where `now` is the
+    /// current time according to this timer's clock.
+    ///
+    /// If reading the clock fails then every pending timer is failed with the
+    /// clock's error instead, which polling them returns, see
+    /// `Clock::try_now`.
     pub fn advance(&mut self) {
-        self.advance_to(Instant::now())
+        match self.inner.clock.try_now() {
+            Ok(now) => {
+                *self.inner.error.lock().unwrap() = None;
+                self.advance_to(now)
+            }
+            Err(err) => self.fail(err),
+        }
     }
 
     /// Proces any timers which are supposed to fire before `now` specified.
     ///
     /// This method should be called on `Timer` periodically to advance the
     /// internal state and process any pending timers which need to fire.
+    ///
+    /// If the timer was configured with a drain budget through
+    /// `TimerBuilder::drain_budget` then at most that many timers are fired,
+    /// and the remaining ones are left for the next call.
     pub fn advance_to(&mut self, now: Instant) {
-        loop {
-            match self.timer_heap.peek() {
-                Some(head) if head.at <= now => {}
-                Some(_) => break,
-                None => break,
-            };
-
-            // Flag the timer as fired and then notify its task, if any, that's
-            // blocked.
-            let heap_timer = self.timer_heap.pop().unwrap();
-            *heap_timer.node.slot.lock().unwrap() = None;
-            let bits = heap_timer.gen << 2;
-            match heap_timer.node.state.compare_exchange(bits, bits | 0b01, SeqCst, SeqCst) {
-                Ok(_) => heap_timer.node.waker.wake(),
-                Err(_b) => {}
-            }
-        }
+        let budget = self.drain_budget.unwrap_or(usize::MAX);
+        self.inner.fire_expired(now, budget)
     }
 
     /// Either updates the timer at slot `idx` to fire at `at`, or adds a new
@@ -221,30 +459,74 @@ impl Timer {
     fn update_or_add(&mut self,
                      at: Instant,
                      node: Arc<Node<ScheduledTimer>>) {
-        // TODO: avoid remove + push and instead just do one sift of the heap?
-        // In theory we could update it in place and then do the percolation
-        // as necessary
+        let at = match self.resolution {
+            Some(resolution) => round_up(at, self.origin, resolution),
+            None => at,
+        };
+        let tolerance = match node.slack {
+            Some(slack) if slack > Duration::from_secs(0) => Some(slack),
+            Some(_) => None,
+            None => self.coalescing,
+        };
+        let at = match tolerance {
+            Some(tolerance) => round_up(at, self.origin, tolerance),
+            None => at,
+        };
         let gen = node.state.load(SeqCst) >> 2;
+        let mut wheel = self.inner.wheel.lock().unwrap();
         let mut slot = node.slot.lock().unwrap();
-        if let Some(heap_slot) = slot.take() {
-            self.timer_heap.remove(heap_slot);
+        if let Some(wheel_slot) = slot.take() {
+            wheel.remove(wheel_slot);
         }
-        *slot = Some(self.timer_heap.push(HeapTimer {
-            at: at,
+        *slot = Some(wheel.insert(at, node.seq as u64, WheelTimer {
             gen: gen,
             node: node.clone(),
         }));
     }
 
-    fn remove(&mut self, node: Arc<Node<ScheduledTimer>>) {
+    /// Drains the list of incoming updates, adding, moving or removing timers
+    /// in the wheel as requested.
+    fn process_updates(&mut self) {
+        let mut list = self.inner.list.take();
+        while let Some(node) = list.pop() {
+            let at = *node.at.lock().unwrap();
+            match at {
+                Some(at) => self.update_or_add(at, node),
+                None => {
+                    self.remove(&node);
+                    self.inner.recycle(node);
+                }
+            }
+        }
+    }
+
+    fn remove(&mut self, node: &Node<ScheduledTimer>) {
         // If this `idx` is still around and it's still got a registered timer,
-        // then we jettison it form the timer heap.
+        // then we jettison it form the timer wheel.
+        let mut wheel = self.inner.wheel.lock().unwrap();
         let mut slot = node.slot.lock().unwrap();
-        let heap_slot = match slot.take() {
+        let wheel_slot = match slot.take() {
             Some(slot) => slot,
             None => return,
         };
-        self.timer_heap.remove(heap_slot);
+        wheel.remove(wheel_slot);
+    }
+
+    /// Records `err` reading the clock and invalidates all timers in the
+    /// wheel, so that their tasks see the error rather than waiting forever.
+    fn fail(&mut self, err: io::Error) {
+        *self.inner.error.lock().unwrap() = Some((err.kind(), err.to_string()));
+        loop {
+            let t = match self.inner.wheel.lock().unwrap().pop() {
+                Some(t) => t,
+                None => break,
+            };
+            *t.node.slot.lock().unwrap() = None;
+            if t.node.state.fetch_or(0b10, SeqCst) & 0b11 == 0 {
+                self.inner.active.fetch_sub(1, SeqCst);
+            }
+            t.node.waker.wake();
+        }
     }
 
     fn invalidate(&mut self, node: Arc<Node<ScheduledTimer>>) {
@@ -259,14 +541,7 @@ impl Future for Timer {
 
     fn poll(&mut self, cx: &mut task::Context) -> Poll<(), ()> {
         self.inner.waker.register(cx.waker());
-        let mut list = self.inner.list.take();
-        while let Some(node) = list.pop() {
-            let at = *node.at.lock().unwrap();
-            match at {
-                Some(at) => self.update_or_add(at, node),
-                None => self.remove(node),
-            }
-        }
+        self.process_updates();
         Ok(Async::Pending)
     }
 }
@@ -279,35 +554,81 @@ impl Drop for Timer {
         let mut list = self.inner.list.take_and_seal();
 
         // Now that we'll never receive another timer, drain the list of all
-        // updates and also drain our heap of all active timers, invalidating
+        // updates and also drain our wheel of all active timers, invalidating
         // everything.
         while let Some(t) = list.pop() {
             self.invalidate(t);
         }
-        while let Some(t) = self.timer_heap.pop() {
+        loop {
+            let t = match self.inner.wheel.lock().unwrap().pop() {
+                Some(t) => t,
+                None => break,
+            };
             self.invalidate(t.node);
         }
     }
 }
 
-impl PartialEq for HeapTimer {
-    fn eq(&self, other: &HeapTimer) -> bool {
-        self.at == other.at
+/// Rounds `at` up to the next multiple of `granularity` after `origin`.
+///
+/// Instants before `origin`, or which can't be rounded without overflowing,
+/// are returned unchanged.
+fn round_up(at: Instant, origin: Instant, granularity: Duration) -> Instant {
+    if at <= origin {
+        return at
+    }
+    let since = (at - origin).as_nanos();
+    let granularity = granularity.as_nanos();
+    let rounded = since.div_ceil(granularity) * granularity;
+    if rounded > u128::from(u64::MAX) {
+        return at
     }
+    origin.checked_add(Duration::from_nanos(rounded as u64)).unwrap_or(at)
 }
 
-impl Eq for HeapTimer {}
+/// Shuts down the global timer's helper thread.
+///
+/// The helper thread is spawned on demand by `TimerHandle::default`, which is
+/// used by constructors like `Delay::new`. Calling this function detaches the
+/// current helper thread from being the default and blocks until it has
+/// exited, which happens once all of the timers registered with it have
+/// either fired or been dropped. Delays which have already fired and are
+/// reset afterwards will fail as their timer has gone away.
+///
+/// After this function returns the next call to `TimerHandle::default` will
+/// transparently spawn a fresh helper thread. If a fallback handle was
+/// configured through `TimerHandle::set_as_global_fallback` then this has no
+/// effect on that handle.
+///
+/// As this waits for the pending timers of the helper thread, the calling
+/// thread must not hold any itself, or it deadlocks: a `Delay` which is
+/// pending on the global timer never fires before it's dropped, if it fires
+/// at all. This includes a `Delay::never` which has been reset onto the
+/// global timer, and delays like `Delay::new(Duration::MAX)` whose deadline
+/// is decades away. Use `shutdown_timeout` to bound the wait instead.
+pub fn shutdown() {
+    global::shutdown()
+}
 
-impl PartialOrd for HeapTimer {
-    fn partial_cmp(&self, other: &HeapTimer) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
+/// Shuts down the global timer's helper thread like `shutdown`, but waits at
+/// most `timeout` for it to exit.
+///
+/// Returns whether the helper thread exited in time. If it didn't, because
+/// some of its timers are still pending, then it's left to exit on its own
+/// once they've all fired or been dropped, while the next call to
+/// `TimerHandle::default` spawns a fresh helper thread as usual. This can be
+/// called while holding delays, unlike `shutdown`.
+pub fn shutdown_timeout(timeout: Duration) -> bool {
+    global::shutdown_timeout(timeout)
 }
 
-impl Ord for HeapTimer {
-    fn cmp(&self, other: &HeapTimer) -> Ordering {
-        self.at.cmp(&other.at)
-    }
+/// Returns the number of delays registered with the default timer which have
+/// neither fired nor been dropped yet.
+///
+/// This is equivalent to `TimerHandle::default().active_timers()`, see
+/// `TimerHandle::active_timers` for more information.
+pub fn active_timers() -> usize {
+    TimerHandle::default().active_timers()
 }
 
 static HANDLE_FALLBACK: AtomicUsize = ATOMIC_USIZE_INIT;
@@ -317,17 +638,75 @@ static HANDLE_FALLBACK: AtomicUsize = ATOMIC_USIZE_INIT;
 pub struct SetDefaultError(());
 
 impl TimerHandle {
+    /// Returns the current time according to the clock of the timer this
+    /// handle refers to.
+    ///
+    /// If the timer has gone away then this falls back to `Instant::now()`.
+    pub fn now(&self) -> Instant {
+        match self.inner.upgrade() {
+            Some(inner) => inner.clock.now(),
+            None => Instant::now(),
+        }
+    }
+
+    /// Returns the number of delays registered with the timer this handle
+    /// refers to which have neither fired nor been dropped yet.
+    ///
+    /// This is a cheap read of a counter which doesn't need to synchronize
+    /// with the timer, and can be used to spot timeouts piling up because the
+    /// futures waiting on them have been leaked. If the timer has gone away
+    /// then this returns zero.
+    pub fn active_timers(&self) -> usize {
+        match self.inner.upgrade() {
+            Some(inner) => inner.active.load(SeqCst),
+            None => 0,
+        }
+    }
+
+    /// Returns the earliest deadline among the delays registered with the
+    /// timer this handle refers to, or `None` if there are none.
+    ///
+    /// An external event loop driving the timer can use this to size the
+    /// timeout of its `epoll` or `select` call. Only delays which the timer
+    /// has processed are accounted for, so delays created since the timer
+    /// was last polled aren't until it's polled again. If the timer has gone
+    /// away then this returns `None`.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        match self.inner.upgrade() {
+            Some(inner) => inner.wheel.lock().unwrap().peek(),
+            None => None,
+        }
+    }
+
+    /// Fires every delay of the timer this handle refers to whose deadline
+    /// has passed, right away.
+    ///
+    /// The current time is read from the timer's clock, which is the real
+    /// time unless the timer was configured otherwise, and the tasks blocked
+    /// on the expired delays are woken before this returns. This is intended
+    /// for integration tests which sleep past a deadline and then expect the
+    /// delay to have fired, without having to wait on the thread driving the
+    /// timer to get around to it. Like `next_deadline` this only covers the
+    /// delays which the timer has processed already.
+    pub fn fire_all_expired(&self) {
+        if let Some(inner) = self.inner.upgrade() {
+            let now = inner.clock.now();
+            inner.fire_expired(now, usize::MAX);
+        }
+    }
+
     /// Configures this timer handle to be the one returned by
     /// `TimerHandle::default`.
     ///
     /// By default a global thread is initialized on the first call to
     /// `TimerHandle::default`. This first call can happen transitively through
-    /// `Delay::new`. If, however, that hasn't happened yet then the global
-    /// default timer handle can be configured through this method.
+    /// `Delay::new`. The global default timer handle can instead be configured
+    /// through this method.
     ///
     /// This method can be used to prevent the global helper thread from
     /// spawning. If this method is successful then the global helper thread
-    /// will never get spun up.
+    /// will not get spun up again, although a helper thread which is already
+    /// running keeps powering the timers which were created through it.
     ///
     /// On success this timer handle will have installed itself globally to be
     /// used as the return value for `TimerHandle::default` unless otherwise
@@ -360,49 +739,45 @@ impl TimerHandle {
 
     unsafe fn from_usize(val: usize) -> TimerHandle {
         let inner = mem::transmute::<usize, Weak<Inner>>(val);;
-        TimerHandle { inner }
+        TimerHandle { inner, scope: None }
     }
-}
 
-impl Default for TimerHandle {
-    fn default() -> TimerHandle {
-        let mut fallback = HANDLE_FALLBACK.load(SeqCst);
-
-        // If the fallback hasn't been previously initialized then let's spin
-        // up a helper thread and try to initialize with that. If we can't
-        // actually create a helper thread then we'll just return a "defunkt"
-        // handle which will return errors when timer objects are attempted to
-        // be associated.
+    /// Returns the handle configured through `set_as_global_fallback`, if
+    /// any.
+    pub(crate) fn fallback() -> Option<TimerHandle> {
+        let fallback = HANDLE_FALLBACK.load(SeqCst);
         if fallback == 0 {
-            let helper = match global::HelperThread::new() {
-                Ok(helper) => helper,
-                Err(_) => return TimerHandle { inner: Weak::new() },
-            };
-
-            // If we successfully set ourselves as the actual fallback then we
-            // want to `forget` the helper thread to ensure that it persists
-            // globally. If we fail to set ourselves as the fallback that means
-            // that someone was racing with this call to
-            // `TimerHandle::default`.  They ended up winning so we'll destroy
-            // our helper thread (which shuts down the thread) and reload the
-            // fallback.
-            if helper.handle().set_as_global_fallback().is_ok() {
-                let ret = helper.handle();
-                helper.forget();
-                return ret
-            }
-            fallback = HANDLE_FALLBACK.load(SeqCst);
+            return None
         }
 
         // At this point our fallback handle global was configured so we use
         // its value to reify a handle, clone it, and then forget our reified
         // handle as we don't actually have an owning reference to it.
-        assert!(fallback != 0);
         unsafe {
             let handle = TimerHandle::from_usize(fallback);
             let ret = handle.clone();
             drop(handle.into_usize());
-            return ret
+            Some(ret)
         }
     }
+
+    /// Returns the current time according to the clock of the default timer,
+    /// without spawning the global helper thread to read it.
+    ///
+    /// This is the clock of the fallback if one is configured, and otherwise
+    /// the system clock which the global timer runs on.
+    pub(crate) fn default_now() -> Instant {
+        match TimerHandle::fallback() {
+            Some(handle) => handle.now(),
+            None => Instant::now(),
+        }
+    }
+}
+
+impl Default for TimerHandle {
+    fn default() -> TimerHandle {
+        // If no fallback has been configured then we use the global helper
+        // thread, which is spun up on demand.
+        TimerHandle::fallback().unwrap_or_else(global::handle)
+    }
 }
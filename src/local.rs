@@ -0,0 +1,111 @@
+//! Support for driving a `Timer` as a task on an existing executor.
+
+use std::fmt;
+use std::time::Instant;
+
+use futures::{Future, Async, Poll, Never, task};
+
+use Timer;
+
+/// A `Timer` which runs as a task driven by a loop of your own, rather than
+/// on a helper thread.
+///
+/// A `TimerTask` is created through `Timer::task` or `Timer::into_task`, and
+/// is intended to be polled by the same loop as the tasks using its delays.
+/// Whenever it's polled it processes new timer registrations and fires every
+/// timer which has expired, waking the tasks blocked on them. The task is
+/// woken whenever a new delay is registered, but a task has no way to be
+/// woken once a deadline passes, so that's left to the loop: after polling
+/// the task it should sleep no longer than until `next_event`, for example
+/// with `thread::park_timeout` when the waker the task was polled with
+/// unparks the thread. This means that no thread besides the loop's own is
+/// needed, which is useful for single threaded and embedded-style
+/// deployments, and the loop only wakes up when there's work to do.
+///
+/// Executors which sleep until a task is woken, like `LocalPool`, can't
+/// host a `TimerTask` for this reason, as its delays would never fire.
+///
+/// The task never resolves on its own, dropping it invalidates all timers
+/// still associated with it just like dropping a `Timer` would.
+///
+/// # Examples
+///
+/// ```
+/// extern crate futures;
+/// extern crate futures_timer;
+///
+/// use std::sync::Arc;
+/// use std::thread;
+/// use std::time::Duration;
+/// use futures::prelude::*;
+/// use futures::task::{self, LocalMap, Wake, Waker};
+/// use futures_timer::{Delay, Timer};
+///
+/// // Wakes the loop by unparking its thread.
+/// struct Unpark(thread::Thread);
+///
+/// impl Wake for Unpark {
+///     fn wake(arc_self: &Arc<Self>) {
+///         arc_self.0.unpark()
+///     }
+/// }
+///
+/// fn main() {
+///     let (mut timer, handle) = Timer::task();
+///     let at = handle.now() + Duration::from_millis(10);
+///     let mut delay = Delay::new_handle(at, handle.clone());
+///
+///     let waker = Waker::from(Arc::new(Unpark(thread::current())));
+///     let mut map = LocalMap::new();
+///     let mut cx = task::Context::without_spawn(&mut map, &waker);
+///     loop {
+///         let _ = timer.poll(&mut cx);
+///         if delay.poll(&mut cx).unwrap().is_ready() {
+///             break
+///         }
+///         match timer.next_event() {
+///             Some(at) => thread::park_timeout(at.saturating_duration_since(handle.now())),
+///             None => thread::park(),
+///         }
+///     }
+/// }
+/// ```
+pub struct TimerTask {
+    timer: Timer,
+}
+
+impl TimerTask {
+    pub(crate) fn new(timer: Timer) -> TimerTask {
+        TimerTask { timer }
+    }
+
+    /// Returns the earliest deadline among the pending timers, according to
+    /// the clock of the timer, or `None` if there are none.
+    ///
+    /// The task needs to be polled again once this has passed for the timer
+    /// to fire. Only the registrations processed by the last poll are
+    /// accounted for, but new ones wake the task.
+    pub fn next_event(&self) -> Option<Instant> {
+        self.timer.next_event()
+    }
+}
+
+impl Future for TimerTask {
+    type Item = ();
+    type Error = Never;
+
+    fn poll(&mut self, cx: &mut task::Context) -> Poll<(), Never> {
+        self.timer.inner.waker.register(cx.waker());
+        self.timer.process_updates();
+        self.timer.advance();
+        Ok(Async::Pending)
+    }
+}
+
+impl fmt::Debug for TimerTask {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TimerTask")
+            .field("next_event", &self.timer.next_event())
+            .finish()
+    }
+}
@@ -0,0 +1,81 @@
+//! A timer which is driven entirely by hand, for deterministic tests.
+
+use std::time::{Duration, Instant};
+
+use {Clock, TestClock, Timer, TimerHandle};
+
+/// A `Timer` running on virtual time which only makes progress when told to.
+///
+/// A `ManualTimer` doesn't spawn any threads. Instead delays associated with
+/// its `handle` only fire when the virtual time of the timer is moved past
+/// their deadlines through the `advance` method, at which point the tasks
+/// blocked on them are woken synchronously, in the order of their deadlines.
+///
+/// This is intended for tests of timeout logic which shouldn't sleep for real
+/// or depend on the scheduling of a helper thread.
+///
+/// # Examples
+///
+/// ```
+/// extern crate futures_timer;
+///
+/// use std::time::Duration;
+/// use futures_timer::{Delay, ManualTimer};
+///
+/// fn main() {
+///     let mut timer = ManualTimer::new();
+///     let delay = Delay::new_handle(timer.now() + Duration::from_secs(60),
+///                                   timer.handle());
+///     assert!(!delay.is_elapsed());
+///
+///     // No need to wait for a full minute!
+///     timer.advance(Duration::from_secs(60));
+///     assert!(delay.is_elapsed());
+/// }
+/// ```
+pub struct ManualTimer {
+    timer: Timer,
+    clock: TestClock,
+}
+
+impl ManualTimer {
+    /// Creates a new timer whose virtual time starts at the current instant.
+    pub fn new() -> ManualTimer {
+        let clock = TestClock::new();
+        ManualTimer {
+            timer: Timer::with_clock(clock.clone()),
+            clock,
+        }
+    }
+
+    /// Returns a handle to this timer, used to create new delays.
+    pub fn handle(&self) -> TimerHandle {
+        self.timer.handle()
+    }
+
+    /// Returns the current virtual time of this timer.
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    /// Processes all timer updates and fires every timer which is due at the
+    /// current virtual time, without moving time forward.
+    pub fn turn(&mut self) {
+        self.timer.process_updates();
+        self.timer.advance();
+    }
+
+    /// Moves the virtual time of this timer forward by `dur`, firing every
+    /// timer whose deadline is passed along the way.
+    pub fn advance(&mut self, dur: Duration) {
+        self.timer.process_updates();
+        self.clock.advance(dur);
+        self.turn();
+    }
+}
+
+impl Default for ManualTimer {
+    fn default() -> ManualTimer {
+        ManualTimer::new()
+    }
+}
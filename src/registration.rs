@@ -0,0 +1,404 @@
+//! The registration of a deadline with a `Timer`, which all timer types are
+//! built on.
+
+use std::fmt;
+use std::io;
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::SeqCst;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use futures::{Poll, Async};
+use futures::task::{self, AtomicWaker};
+
+use arc_list::Node;
+use delay;
+use {TimerHandle, ScheduledTimer, current_shard};
+
+/// A deadline registered with a timer, which wakes a task once it passes.
+///
+/// This is the building block all of the timer types in this crate are made
+/// of, `Delay` included. It offers the same functionality as a `Delay`, but
+/// through a `poll` method rather than an implementation of `Future`, which
+/// makes it convenient for writing custom timer types. A `Registration` can
+/// be moved to another deadline with `reset` and `reset_at` as often as
+/// necessary, reusing its slot in the timer rather than allocating a new one.
+///
+/// Dropping a `Registration` cancels it.
+///
+/// # Examples
+///
+/// ```
+/// extern crate futures;
+/// extern crate futures_timer;
+///
+/// use std::time::{Duration, Instant};
+/// use futures::future;
+/// use futures::prelude::*;
+/// use futures::executor::block_on;
+/// use futures_timer::{Registration, TimerHandle};
+///
+/// fn main() {
+///     // Wait for two deadlines one after another with the same registration.
+///     let now = Instant::now();
+///     let mut registration = Registration::new(now + Duration::from_millis(10),
+///                                              TimerHandle::default());
+///     let mut second = Some(now + Duration::from_millis(20));
+///     block_on(future::poll_fn(|cx| {
+///         while registration.poll(cx)?.is_ready() {
+///             match second.take() {
+///                 Some(at) => registration.reset_at(at),
+///                 None => return Ok(Async::Ready(())),
+///             }
+///         }
+///         Ok::<_, std::io::Error>(Async::Pending)
+///     })).unwrap();
+///     assert!(now.elapsed() >= Duration::from_millis(20));
+/// }
+/// ```
+pub struct Registration {
+    state: Option<Arc<Node<ScheduledTimer>>>,
+    when: Instant,
+    never: bool,
+}
+
+impl Registration {
+    /// Registers the deadline `at` with the timer specified by `handle`.
+    ///
+    /// If the timer has gone away, or `handle` belongs to a `TimerScope` which
+    /// has ended, then the returned registration is inert, and polling it
+    /// returns an error.
+    pub fn new(at: Instant, handle: TimerHandle) -> Registration {
+        Registration::new_slack(at, None, handle)
+    }
+
+    /// Registers the deadline `at` like `new`, using `slack` as the
+    /// coalescing tolerance rather than that of the timer if it's given.
+    pub(crate) fn new_slack(at: Instant,
+                            slack: Option<Duration>,
+                            handle: TimerHandle) -> Registration {
+        let inner = match handle.inner.upgrade() {
+            Some(i) => i,
+            None => return Registration { state: None, when: at, never: false },
+        };
+        // A deadline which has already passed fires immediately, there's no
+        // need to round-trip through the timer to find that out.
+        let fired = at <= inner.clock.now();
+        let state = inner.alloc(ScheduledTimer {
+            at: Mutex::new(if fired { None } else { Some(at) }),
+            state: AtomicUsize::new(if fired { 0b01 } else { 0 }),
+            waker: AtomicWaker::new(),
+            inner: handle.inner,
+            slot: Mutex::new(None),
+            shard: current_shard(),
+            slack,
+            seq: inner.next_seq.fetch_add(1, SeqCst),
+        });
+        if let Some(ref scope) = handle.scope {
+            if !scope.track(&state) {
+                inner.recycle(state);
+                return Registration { state: None, when: at, never: false }
+            }
+        }
+        if fired {
+            return Registration { state: Some(state), when: at, never: false }
+        }
+
+        // If we fail to actually push our node then we've become an inert
+        // timer, meaning that we'll want to immediately return an error from
+        // `poll`.
+        inner.active.fetch_add(1, SeqCst);
+        if inner.list.push(&state).is_err() {
+            inner.active.fetch_sub(1, SeqCst);
+            return Registration { state: None, when: at, never: false }
+        }
+
+        inner.waker.wake();
+        Registration {
+            state: Some(state),
+            when: at,
+            never: false,
+        }
+    }
+
+    /// Creates a registration which never fires, without registering with
+    /// any timer.
+    ///
+    /// Polling the returned registration always returns `Pending`, and never
+    /// arranges for a task to be woken. Its deadline is an instant far enough
+    /// into the future that it would never be reached in practice. Resetting
+    /// it registers the new deadline with the default timer.
+    pub fn never() -> Registration {
+        Registration {
+            state: None,
+            when: delay::after(Instant::now(), Duration::MAX),
+            never: true,
+        }
+    }
+
+    /// Returns the instant at which this registration fires.
+    pub fn deadline(&self) -> Instant {
+        self.when
+    }
+
+    /// Returns the amount of time left until the deadline of this
+    /// registration, or a zero duration if the deadline has already passed.
+    pub fn remaining(&self) -> Duration {
+        self.when.saturating_duration_since(self.now())
+    }
+
+    /// Returns how long ago the deadline of this registration passed, or a
+    /// zero duration if it hasn't passed yet.
+    pub fn overrun(&self) -> Duration {
+        self.now().saturating_duration_since(self.when)
+    }
+
+    /// Returns whether the deadline of this registration has passed,
+    /// according to the clock of the associated timer.
+    pub fn is_elapsed(&self) -> bool {
+        self.now() >= self.when
+    }
+
+    /// Returns whether this registration has fired or its deadline has
+    /// passed, without registering the current task to be woken.
+    ///
+    /// See `Delay::poll_no_register` for the caveats.
+    pub fn poll_no_register(&mut self) -> bool {
+        let fired = self.state.as_ref()
+            .is_some_and(|state| state.state.load(SeqCst) & 0b01 != 0);
+        fired || self.is_elapsed()
+    }
+
+    /// Moves this registration to fire `dur` time from now, according to the
+    /// clock of the associated timer.
+    pub fn reset(&mut self, dur: Duration) {
+        let at = delay::after(self.now(), dur);
+        self.reset_at(at)
+    }
+
+    /// Moves this registration to fire `dur` time from now like `reset`,
+    /// returning whether its previous deadline had passed.
+    pub fn reset_checked(&mut self, dur: Duration) -> bool {
+        let now = self.now();
+        let elapsed = now >= self.when;
+        self.reset_at(delay::after(now, dur));
+        elapsed
+    }
+
+    /// Moves this registration to fire at the instant `at`, whether or not
+    /// it has fired already.
+    ///
+    /// The task blocked on this registration, if any, needs to poll it again
+    /// to be woken for the new deadline.
+    pub fn reset_at(&mut self, at: Instant) {
+        if self.never {
+            *self = Registration::new(at, TimerHandle::default());
+            return
+        }
+        self.when = at;
+        if self._reset(at).is_err() {
+            self.state = None
+        }
+    }
+
+    fn _reset(&mut self, at: Instant) -> Result<(), ()> {
+        let state = match self.state {
+            Some(ref state) => state,
+            None => return Err(()),
+        };
+        if let Some(timeouts) = state.inner.upgrade() {
+            let fired = at <= timeouts.clock.now();
+            let mut bits = state.state.load(SeqCst);
+            loop {
+                // If we've been invalidated, cancel this reset
+                if bits & 0b10 != 0 {
+                    return Err(())
+                }
+                let mut new = bits.wrapping_add(0b100) & !0b11;
+                if fired {
+                    new |= 0b01;
+                }
+                match state.state.compare_exchange(bits, new, SeqCst, SeqCst) {
+                    Ok(_) => break,
+                    Err(s) => bits = s,
+                }
+            }
+            match (bits & 0b01 != 0, fired) {
+                (true, false) => { timeouts.active.fetch_add(1, SeqCst); }
+                (false, true) => { timeouts.active.fetch_sub(1, SeqCst); }
+                _ => {}
+            }
+            let prev = mem::replace(&mut *state.at.lock().unwrap(),
+                                    if fired { None } else { Some(at) });
+            // A deadline in the past has fired already, so the timer only
+            // needs to hear about it if it may still be tracking a previous
+            // deadline for us.
+            if fired && prev.is_none() {
+                return Ok(())
+            }
+            // If we fail to push our node then we've become an inert timer, so
+            // we'll want to clear our `state` field accordingly
+            timeouts.list.push(state)?;
+            timeouts.waker.wake();
+        }
+
+        Ok(())
+    }
+    /// Polls whether this registration has fired, arranging for the current
+    /// task to be woken once it does otherwise.
+    ///
+    /// Only the task which polled most recently is woken. Polling is cheap
+    /// and doesn't involve the timer, so it's fine for a task to poll a
+    /// registration whenever it's woken, for whatever reason.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the associated timer has gone away.
+    pub fn poll(&mut self, cx: &mut task::Context) -> Poll<(), io::Error> {
+        let state = match self.state {
+            Some(ref state) => state,
+            None if self.never => return Ok(Async::Pending),
+            None => return Err(gone()),
+        };
+        if state.state.load(SeqCst) & 1 != 0 {
+            return Ok(Async::Ready(()))
+        }
+
+        state.waker.register(cx.waker());
+
+        // Now that we've registered, do the full check of our own internal
+        // state. If we've fired the first bit is set, and if we've been
+        // invalidated the second bit is set.
+        let bits = match state.state.load(SeqCst) {
+            n if n & 0b01 != 0 => return Ok(Async::Ready(())),
+            n if n & 0b10 != 0 => return Err(invalidated(state)),
+            n => n,
+        };
+
+        // If the deadline is close enough, spin until it passes rather than
+        // waiting for the timer to get around to firing us.
+        let inner = match state.inner.upgrade() {
+            Some(inner) => inner,
+            None => return Ok(Async::Pending),
+        };
+        let threshold = match inner.spin_threshold {
+            Some(threshold) => threshold,
+            None => return Ok(Async::Pending),
+        };
+        if self.when.saturating_duration_since(inner.clock.now()) > threshold {
+            return Ok(Async::Pending)
+        }
+        // The spin is bounded by the threshold in real time too, as the clock
+        // of the timer may not advance on its own, in which case we're left
+        // for the timer to fire after all. It's also cut short should the
+        // timer fire us or we get invalidated, say by a scope ending.
+        let spin_start = Instant::now();
+        while inner.clock.now() < self.when && state.state.load(SeqCst) == bits {
+            if spin_start.elapsed() > threshold {
+                return Ok(Async::Pending)
+            }
+            thread::yield_now();
+        }
+
+        // Flag ourselves as fired just like the timer would have, unless it
+        // beat us to it or we got invalidated in the meantime.
+        match state.state.compare_exchange(bits, bits | 0b01, SeqCst, SeqCst) {
+            Ok(_) => {
+                inner.active.fetch_sub(1, SeqCst);
+                Ok(Async::Ready(()))
+            }
+            Err(n) if n & 0b01 != 0 => Ok(Async::Ready(())),
+            Err(_) => Err(invalidated(state)),
+        }
+    }
+
+    /// Returns the current time according to the clock of the associated
+    /// timer, or `Instant::now()` if there's no such timer.
+    ///
+    /// A registration which never fires is bound to the default timer once
+    /// it's reset, so it reads the clock of that instead.
+    pub(crate) fn now(&self) -> Instant {
+        if self.never {
+            return TimerHandle::default_now()
+        }
+        let inner = self.state.as_ref().and_then(|state| state.inner.upgrade());
+        match inner {
+            Some(inner) => inner.clock.now(),
+            None => Instant::now(),
+        }
+    }
+}
+
+/// Returns the error produced by registrations whose timer has gone away.
+fn gone() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "timer has gone away")
+}
+
+/// Returns the error produced by the invalidated registration `state`, which
+/// is that of its timer's clock if that's why it was invalidated.
+fn invalidated(state: &ScheduledTimer) -> io::Error {
+    state.inner.upgrade().and_then(|inner| inner.error()).unwrap_or_else(gone)
+}
+
+impl fmt::Debug for Registration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Registration")
+            .field("when", &self.when)
+            .field("elapsed", &self.is_elapsed())
+            .finish()
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let state = match self.state.take() {
+            Some(s) => s,
+            None => return,
+        };
+        if let Some(timeouts) = state.inner.upgrade() {
+            // Flag ourselves as fired so the timer won't fire us as well, and
+            // stop counting as active if we were still waiting to fire.
+            if state.state.fetch_or(0b01, SeqCst) & 0b11 == 0 {
+                timeouts.active.fetch_sub(1, SeqCst);
+            }
+
+            // If we don't have a deadline then the timer isn't tracking us
+            // and there's nothing to remove, so our registration can be
+            // reused right away. Otherwise the timer takes care of that once
+            // it has removed us.
+            if state.at.lock().unwrap().take().is_none() {
+                timeouts.recycle(state);
+                return
+            }
+            if timeouts.list.push(&state).is_ok() {
+                timeouts.waker.wake();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::executor::block_on;
+    use futures::future;
+    use futures::Async;
+
+    use super::Registration;
+
+    #[test]
+    fn never_is_unregistered() {
+        let mut never = Registration::never();
+        assert!(never.state.is_none());
+        assert!(!never.is_elapsed());
+        assert!(never.remaining() > Duration::from_secs(86400 * 365));
+        let pending = block_on(future::poll_fn(|cx| {
+            Ok::<_, ::std::io::Error>(Async::Ready(never.poll(cx)?.is_pending()))
+        })).unwrap();
+        assert!(pending);
+        assert!(never.state.is_none());
+    }
+}
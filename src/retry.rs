@@ -0,0 +1,184 @@
+//! Support for retrying fallible futures with exponential backoff.
+
+use std::cmp;
+use std::fmt;
+use std::time::Duration;
+
+use futures::{Future, Poll, Async};
+use futures::task;
+
+use Delay;
+
+/// Policy describing how often and how quickly `retry` retries a failed
+/// future.
+///
+/// The wait before the `n`th retry is `base * multiplier^(n - 1)`, capped at
+/// `max_delay`, so by default the waits double after every failure.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    base: Duration,
+    multiplier: f64,
+    max_attempts: usize,
+    max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Creates a new policy which waits `base` before the first retry.
+    ///
+    /// The policy defaults to a multiplier of two, a total of three attempts
+    /// and waits of at most one minute.
+    pub fn new(base: Duration) -> RetryPolicy {
+        RetryPolicy {
+            base,
+            multiplier: 2.0,
+            max_attempts: 3,
+            max_delay: Duration::from_secs(60),
+        }
+    }
+
+    /// Configures the factor each wait is multiplied with compared to the
+    /// previous one.
+    pub fn multiplier(mut self, multiplier: f64) -> RetryPolicy {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Configures the total number of attempts, including the first one.
+    ///
+    /// A value of zero is treated the same as one, which never retries.
+    pub fn max_attempts(mut self, max_attempts: usize) -> RetryPolicy {
+        self.max_attempts = cmp::max(max_attempts, 1);
+        self
+    }
+
+    /// Configures the upper bound for each wait between attempts.
+    pub fn max_delay(mut self, max_delay: Duration) -> RetryPolicy {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Returns how long to wait before the `retry`th retry, starting at one.
+    pub fn delay_for(&self, retry: u32) -> Duration {
+        let exponent = cmp::min(retry.saturating_sub(1), i32::MAX as u32) as i32;
+        let factor = self.multiplier.powi(exponent);
+        match Duration::try_from_secs_f64(self.base.as_secs_f64() * factor) {
+            Ok(dur) => cmp::min(dur, self.max_delay),
+            Err(_) => self.max_delay,
+        }
+    }
+}
+
+/// Creates a future which runs the future created by `factory`, retrying with
+/// a fresh one after waiting whenever it fails.
+///
+/// The waits between attempts and the number of attempts are controlled by
+/// `policy`. The returned future resolves to the first successful result, or
+/// to the error of the last attempt once all attempts have failed. The first
+/// attempt is created right away, and every following attempt only once the
+/// wait before it has finished.
+///
+/// The waits use the default timer. Should that timer go away then the
+/// remaining attempts are made without waiting.
+///
+/// # Examples
+///
+/// ```
+/// extern crate futures;
+/// extern crate futures_timer;
+///
+/// use std::time::Duration;
+/// use futures::executor::block_on;
+/// use futures_timer::{retry, RetryPolicy};
+///
+/// # fn connect() -> futures::future::FutureResult<(), std::io::Error> {
+/// #   futures::future::ok(())
+/// # }
+/// #
+/// fn main() {
+///     let policy = RetryPolicy::new(Duration::from_millis(100)).max_attempts(5);
+///     let connection = block_on(retry(connect, policy));
+///     # drop(connection);
+/// }
+/// ```
+pub fn retry<F, R>(mut factory: F, policy: RetryPolicy) -> Retry<F, R>
+    where F: FnMut() -> R,
+          R: Future,
+{
+    let future = factory();
+    Retry {
+        factory,
+        policy,
+        attempts: 1,
+        state: State::Running(future),
+    }
+}
+
+/// Future returned by the `retry` function.
+pub struct Retry<F, R> {
+    factory: F,
+    policy: RetryPolicy,
+    attempts: usize,
+    state: State<R>,
+}
+
+enum State<R> {
+    Running(R),
+    Waiting(Delay),
+}
+
+impl<F, R> Retry<F, R> {
+    /// Returns the number of attempts which have been started so far.
+    pub fn attempts(&self) -> usize {
+        self.attempts
+    }
+}
+
+impl<F, R> fmt::Debug for Retry<F, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Retry")
+            .field("policy", &self.policy)
+            .field("attempts", &self.attempts)
+            .field("waiting", &match self.state {
+                State::Running(_) => false,
+                State::Waiting(_) => true,
+            })
+            .finish()
+    }
+}
+
+impl<F, R> Future for Retry<F, R>
+    where F: FnMut() -> R,
+          R: Future,
+{
+    type Item = R::Item;
+    type Error = R::Error;
+
+    fn poll(&mut self, cx: &mut task::Context) -> Poll<R::Item, R::Error> {
+        loop {
+            let next = match self.state {
+                State::Running(ref mut future) => {
+                    match future.poll(cx) {
+                        Ok(res) => return Ok(res),
+                        Err(e) => {
+                            if self.attempts >= self.policy.max_attempts {
+                                return Err(e)
+                            }
+                            let retry = self.attempts as u32;
+                            State::Waiting(Delay::new(self.policy.delay_for(retry)))
+                        }
+                    }
+                }
+                State::Waiting(ref mut delay) => {
+                    match delay.poll(cx) {
+                        Ok(Async::Pending) => return Ok(Async::Pending),
+                        Ok(Async::Ready(())) | Err(_) => {
+                            self.attempts += 1;
+                            State::Running((self.factory)())
+                        }
+                    }
+                }
+            };
+            self.state = next;
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! Support for cancelling every delay created within a scope at once.
+
+use std::fmt;
+use std::sync::{Arc, Mutex, Weak};
+use std::sync::atomic::Ordering::SeqCst;
+
+use arc_list::Node;
+use {ScheduledTimer, TimerHandle};
+
+/// A scope which cancels every delay created within it once it's dropped.
+///
+/// A `TimerScope` hands out a `TimerHandle` through its `handle` method, and
+/// all delays, intervals and other timer types created through that handle
+/// are tied to the scope. When the scope is dropped they're all cancelled:
+/// they won't fire anymore, nor wake the tasks blocked on them, so a
+/// subsystem which is being torn down doesn't see a straggling timeout
+/// firing afterwards. Polling a cancelled delay returns an error, just like
+/// polling a delay whose timer has gone away, as does polling any delay
+/// created through the scope's handle after the scope has ended.
+///
+/// Scopes nest, a scope created from the handle of another scope is ended
+/// along with it.
+///
+/// # Examples
+///
+/// ```
+/// extern crate futures_timer;
+///
+/// use std::time::Duration;
+/// use futures_timer::{Delay, TimerScope};
+///
+/// fn main() {
+///     let scope = TimerScope::new();
+///     let handle = scope.handle();
+///     let delay = Delay::new_handle(handle.now() + Duration::from_secs(60), handle);
+///
+///     // Tear down the scope before the delay fires, which cancels it.
+///     drop(scope);
+///     assert!(delay.wait().is_err());
+/// }
+/// ```
+pub struct TimerScope {
+    handle: TimerHandle,
+}
+
+/// The delays tied to a scope, shared between all handles of the scope.
+pub(crate) struct Registry {
+    parent: Option<Arc<Registry>>,
+
+    // The registrations of the delays created within the scope, or `None`
+    // once the scope has ended. The weak references keep the registrations
+    // from being recycled while they're held, so they never refer to a delay
+    // created outside of the scope.
+    delays: Mutex<Option<Vec<Weak<Node<ScheduledTimer>>>>>,
+}
+
+impl TimerScope {
+    /// Creates a new scope for delays on the default timer.
+    pub fn new() -> TimerScope {
+        TimerScope::new_handle(TimerHandle::default())
+    }
+
+    /// Creates a new scope for delays on the timer specified by `handle`.
+    ///
+    /// If `handle` belongs to another scope then the new scope is nested
+    /// within it, and ends no later than it does.
+    pub fn new_handle(handle: TimerHandle) -> TimerScope {
+        let registry = Registry {
+            parent: handle.scope.clone(),
+            delays: Mutex::new(Some(Vec::new())),
+        };
+        TimerScope {
+            handle: TimerHandle {
+                inner: handle.inner,
+                scope: Some(Arc::new(registry)),
+            },
+        }
+    }
+
+    /// Returns a handle to the timer of this scope, which ties all delays
+    /// created through it to this scope.
+    pub fn handle(&self) -> TimerHandle {
+        self.handle.clone()
+    }
+
+    fn registry(&self) -> &Registry {
+        self.handle.scope.as_ref().expect("scope handle without a registry")
+    }
+}
+
+impl Default for TimerScope {
+    fn default() -> TimerScope {
+        TimerScope::new()
+    }
+}
+
+impl fmt::Debug for TimerScope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TimerScope").finish()
+    }
+}
+
+impl Drop for TimerScope {
+    fn drop(&mut self) {
+        self.registry().cancel_all();
+    }
+}
+
+impl Registry {
+    /// Ties `node` to this scope and all the scopes it's nested within,
+    /// returning `false` if any of them has ended already.
+    pub(crate) fn track(&self, node: &Arc<Node<ScheduledTimer>>) -> bool {
+        let mut delays = self.delays.lock().unwrap();
+        let delays = match *delays {
+            Some(ref mut delays) => delays,
+            None => return false,
+        };
+        if let Some(ref parent) = self.parent {
+            if !parent.track(node) {
+                return false
+            }
+        }
+        // Forget about delays which have been dropped before growing.
+        if delays.len() == delays.capacity() {
+            delays.retain(|n| n.strong_count() > 0);
+        }
+        delays.push(Arc::downgrade(node));
+        true
+    }
+
+    fn cancel_all(&self) {
+        let delays = match self.delays.lock().unwrap().take() {
+            Some(delays) => delays,
+            None => return,
+        };
+        for node in delays.iter().filter_map(Weak::upgrade) {
+            let timeouts = match node.inner.upgrade() {
+                Some(timeouts) => timeouts,
+                None => continue,
+            };
+
+            // Flag the delay as invalidated, which keeps the timer from
+            // firing it, without waking its task.
+            if node.state.fetch_or(0b10, SeqCst) & 0b11 == 0 {
+                timeouts.active.fetch_sub(1, SeqCst);
+            }
+
+            // Have the timer forget about the delay's deadline right away,
+            // rather than holding on to it until it passes.
+            if node.at.lock().unwrap().take().is_some() &&
+                timeouts.list.push(&node).is_ok() {
+                timeouts.waker.wake();
+            }
+        }
+    }
+}
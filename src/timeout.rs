@@ -0,0 +1,160 @@
+//! Errors produced when a timeout elapses or is cancelled, and the hook
+//! observing timeouts as they elapse.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Error returned when a timeout's deadline has elapsed before the future or
+/// stream it was guarding produced a value.
+///
+/// This can be converted into an `io::Error` with the `TimedOut` kind, which
+/// is how the `timeout` combinators in the `ext` module surface it. The
+/// original `Elapsed` can be recovered from such an `io::Error` through its
+/// `get_ref` method and `downcast_ref`.
+///
+/// The error records both the duration the timeout was configured with and
+/// how long had actually passed when it was observed, both of which are also
+/// part of its `Display` output, and therefore of the message of the
+/// `io::Error` it's converted into.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Elapsed {
+    timeout: Duration,
+    elapsed: Duration,
+}
+
+impl Elapsed {
+    pub(crate) fn new(timeout: Duration, elapsed: Duration) -> Elapsed {
+        Elapsed { timeout, elapsed }
+    }
+
+    /// Returns the duration the timeout was configured with.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Returns how long had passed since the timeout was started when it was
+    /// observed to have elapsed.
+    ///
+    /// This is at least `timeout`, and usually slightly more, depending on
+    /// how promptly the timer fired and the timed out task was polled.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+}
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "deadline of {:?} elapsed after {:?}", self.timeout, self.elapsed)
+    }
+}
+
+impl Error for Elapsed {}
+
+impl From<Elapsed> for io::Error {
+    fn from(err: Elapsed) -> io::Error {
+        io::Error::new(io::ErrorKind::TimedOut, err)
+    }
+}
+
+/// Error returned when a future guarded by `FutureExt::timeout_cancellable`
+/// was cancelled through its flag before it completed.
+///
+/// Like `Elapsed` this can be converted into an `io::Error`, of the
+/// `Interrupted` kind, which is how `timeout_cancellable` surfaces it. The
+/// original `Cancelled` can be recovered from such an `io::Error` through its
+/// `get_ref` method and `downcast_ref`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cancelled(());
+
+impl Cancelled {
+    pub(crate) fn new() -> Cancelled {
+        Cancelled(())
+    }
+}
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("cancelled before completion")
+    }
+}
+
+impl Error for Cancelled {}
+
+impl From<Cancelled> for io::Error {
+    fn from(err: Cancelled) -> io::Error {
+        io::Error::new(io::ErrorKind::Interrupted, err)
+    }
+}
+
+/// The kind of combinator whose timeout elapsed, as reported to the hook
+/// registered through `on_timeout_fired`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeoutKind {
+    /// A timeout of a future, such as `FutureExt::timeout` or
+    /// `select_timeout`.
+    Future,
+    /// A timeout between the items of a stream, `StreamExt::timeout` and its
+    /// variants.
+    Stream,
+    /// A deadline of a stream as a whole, such as `StreamExt::timeout_total`.
+    StreamDeadline,
+    /// A timeout of sending an item into a sink, `SinkExt::send_timeout`.
+    Sink,
+}
+
+/// Error returned from `on_timeout_fired` when a hook has been registered
+/// already.
+#[derive(Clone, Debug)]
+pub struct SetHookError(());
+
+type Hook = Box<dyn Fn(TimeoutKind, Duration) + Send + Sync>;
+
+static HOOK: OnceLock<Hook> = OnceLock::new();
+
+/// Registers a hook which is called whenever a timeout combinator of this
+/// crate produces an `Elapsed` error.
+///
+/// The hook is passed the kind of the combinator along with the duration
+/// its timeout was configured with, which is enough to maintain metrics of
+/// timeouts without threading a handle through every call site. It's called
+/// on whichever thread polls the timed out combinator, right before the
+/// error is returned, so it should be quick and must not panic.
+///
+/// Only a single hook can be registered for the lifetime of the process, and
+/// until one is, observing a timeout costs no more than a single atomic load.
+///
+/// # Errors
+///
+/// Returns an error if a hook has already been registered.
+///
+/// # Examples
+///
+/// ```
+/// extern crate futures_timer;
+///
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// static TIMEOUTS: AtomicUsize = AtomicUsize::new(0);
+///
+/// fn main() {
+///     futures_timer::on_timeout_fired(|_kind, _dur| {
+///         TIMEOUTS.fetch_add(1, Ordering::Relaxed);
+///     }).unwrap();
+/// }
+/// ```
+pub fn on_timeout_fired<F>(hook: F) -> Result<(), SetHookError>
+    where F: Fn(TimeoutKind, Duration) + Send + Sync + 'static,
+{
+    HOOK.set(Box::new(hook)).map_err(|_| SetHookError(()))
+}
+
+/// Reports the timeout `err` of a combinator of the given kind to the hook,
+/// if one is registered.
+pub(crate) fn fired(kind: TimeoutKind, err: &Elapsed) {
+    if let Some(hook) = HOOK.get() {
+        hook(kind, err.timeout)
+    }
+}
@@ -0,0 +1,669 @@
+//! A hierarchical timing wheel with support for removal of arbitrary elements
+//!
+//! This wheel is used to manage timer state in the event loop. All timeouts go
+//! into this wheel and we also cancel timeouts from this wheel. Compared to a
+//! binary heap, inserting and removing an element are O(1) operations, which
+//! matters when hundreds of thousands of timeouts are being reset all the
+//! time.
+//!
+//! Time is divided into ticks of a configurable duration. The wheel has
+//! `LEVELS` levels of `SLOTS` slots each, where a slot on level `n` spans
+//! `SLOTS^n` ticks. An element is stored in the lowest level whose current
+//! rotation contains its tick, and as time advances the slots of higher
+//! levels are "cascaded" down into the lower ones. Elements too far in the
+//! future for even the highest level are kept on a separate overflow list.
+//!
+//! Each element retains its exact deadline, so ticks only determine how
+//! elements are bucketed and never make an element fire early or late.
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::mem;
+use std::time::{Duration, Instant};
+
+const LEVELS: usize = 6;
+const SLOT_BITS: usize = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+
+/// Number of ticks covered by all levels of the wheel.
+const RANGE: u64 = 1 << (LEVELS * SLOT_BITS);
+
+/// Sentinel for the end of an intrusive list.
+const NONE: usize = usize::MAX;
+
+pub struct Wheel<T> {
+    origin: Instant,
+    tick_nanos: u64,
+
+    // All ticks before `elapsed` have been fully processed. Elements may still
+    // be waiting in the slot for `elapsed` itself if their exact deadline
+    // hasn't passed yet.
+    elapsed: u64,
+
+    levels: Vec<Level>,
+
+    // Elements whose tick is at least `RANGE` ticks past `elapsed`, along with
+    // the smallest tick among them.
+    overflow: List,
+    overflow_min: u64,
+
+    // Elements which have expired, sorted by their deadline, and waiting to be
+    // returned from `poll`. Elements removed while on this queue are left
+    // behind as tombstones until they're popped.
+    ready: VecDeque<usize>,
+
+    // Slab of elements, with vacant entries linked through their `next` field.
+    entries: Vec<Entry<T>>,
+    next_free: usize,
+}
+
+struct Level {
+    // Bit `n` is set if slot `n` is non-empty.
+    occupied: u64,
+    slots: Vec<List>,
+}
+
+#[derive(Clone, Copy)]
+struct List {
+    head: usize,
+    tail: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Place {
+    Vacant,
+    Slot(usize, usize),
+    Overflow,
+    Ready,
+}
+
+struct Entry<T> {
+    value: Option<T>,
+    at: Instant,
+    seq: u64,
+    tick: u64,
+    prev: usize,
+    next: usize,
+    place: Place,
+}
+
+pub struct Slot {
+    idx: usize,
+}
+
+impl<T> Wheel<T> {
+    /// Creates a new wheel whose ticks are `tick` long, measured from
+    /// `origin`.
+    pub fn new(origin: Instant, tick: Duration) -> Wheel<T> {
+        Wheel {
+            origin,
+            tick_nanos: cmp::max(cmp::min(tick.as_nanos(), u128::from(u64::MAX)) as u64, 1),
+            elapsed: 0,
+            levels: (0..LEVELS).map(|_| Level {
+                occupied: 0,
+                slots: vec![List::new(); SLOTS],
+            }).collect(),
+            overflow: List::new(),
+            overflow_min: u64::MAX,
+            ready: VecDeque::new(),
+            entries: Vec::new(),
+            next_free: NONE,
+        }
+    }
+
+    /// Inserts an element into this wheel which expires at `at`, returning a
+    /// slot token indicating where it was inserted.
+    ///
+    /// Elements with equal deadlines expire in the order of their `seq`, so
+    /// that callers can keep the relative order of elements which are
+    /// reinserted with a new deadline.
+    ///
+    /// The slot can later get passed to `remove` to remove the element from
+    /// the wheel, but only if the element was previously not removed from the
+    /// wheel or returned from `poll`.
+    pub fn insert(&mut self, at: Instant, seq: u64, value: T) -> Slot {
+        let entry = Entry {
+            value: Some(value),
+            at,
+            seq,
+            tick: self.tick_of(at),
+            prev: NONE,
+            next: NONE,
+            place: Place::Vacant,
+        };
+        let idx = if self.next_free == NONE {
+            self.entries.push(entry);
+            self.entries.len() - 1
+        } else {
+            let idx = self.next_free;
+            self.next_free = self.entries[idx].next;
+            self.entries[idx] = entry;
+            idx
+        };
+        self.place(idx);
+        Slot { idx }
+    }
+
+    /// Removes the element identified by `slot` from this wheel.
+    pub fn remove(&mut self, slot: Slot) -> T {
+        let idx = slot.idx;
+        match self.entries[idx].place {
+            Place::Slot(level, slot) => self.unlink_slot(level, slot, idx),
+            Place::Overflow => {
+                let mut list = self.overflow;
+                self.unlink(&mut list, idx);
+                self.overflow = list;
+            }
+            // Leave a tombstone behind which `poll` will clean up.
+            Place::Ready => return self.entries[idx].value.take().unwrap(),
+            Place::Vacant => panic!("removing a vacant slot"),
+        }
+        self.release(idx)
+    }
+
+    /// Returns the deadline of the element which expires first, if any.
+    pub fn peek(&self) -> Option<Instant> {
+        let ready = self.ready.iter()
+            .map(|&idx| &self.entries[idx])
+            .find(|e| e.value.is_some());
+        if let Some(entry) = ready {
+            return Some(entry.at)
+        }
+        let list = match self.next_expiration() {
+            Some((level, slot, _)) => self.levels[level].slots[slot],
+            None => self.overflow,
+        };
+        self.iter(list).min_by_key(|e| (e.at, e.seq)).map(|e| e.at)
+    }
+
+    /// Removes and returns an element whose deadline is at or before `now`,
+    /// if any.
+    ///
+    /// Expired elements are returned in the order of their deadline, and
+    /// elements with equal deadlines in the order of their `seq`.
+    pub fn poll(&mut self, now: Instant) -> Option<T> {
+        loop {
+            while let Some(idx) = self.ready.pop_front() {
+                let value = self.entries[idx].value.take();
+                self.free(idx);
+                if value.is_some() {
+                    return value
+                }
+            }
+            if !self.process(now) {
+                return None
+            }
+        }
+    }
+
+    /// Removes and returns an arbitrary element of this wheel, if any.
+    pub fn pop(&mut self) -> Option<T> {
+        while let Some(idx) = self.ready.pop_front() {
+            let value = self.entries[idx].value.take();
+            self.free(idx);
+            if value.is_some() {
+                return value
+            }
+        }
+        let level = self.levels.iter().position(|l| l.occupied != 0);
+        let idx = match level {
+            Some(level) => {
+                let slot = self.levels[level].occupied.trailing_zeros() as usize;
+                let idx = self.levels[level].slots[slot].head;
+                self.unlink_slot(level, slot, idx);
+                idx
+            }
+            None if self.overflow.head != NONE => {
+                let idx = self.overflow.head;
+                let mut list = self.overflow;
+                self.unlink(&mut list, idx);
+                self.overflow = list;
+                idx
+            }
+            None => return None,
+        };
+        Some(self.release(idx))
+    }
+
+    /// Moves elements which have expired by `now` onto the ready queue,
+    /// returning whether any progress was made.
+    fn process(&mut self, now: Instant) -> bool {
+        let now_tick = self.tick_of(now);
+        loop {
+            let (level, slot, deadline) = match self.next_expiration() {
+                Some(e) if e.2 <= now_tick => e,
+                _ => return self.set_elapsed(now_tick),
+            };
+            self.set_elapsed(deadline);
+            let list = mem::replace(&mut self.levels[level].slots[slot], List::new());
+            self.levels[level].occupied &= !(1 << slot);
+            let idxs = self.drain(list);
+
+            // Elements on higher levels are cascaded down into lower levels
+            // now that time has caught up with them.
+            if level > 0 {
+                for idx in idxs {
+                    self.place(idx);
+                }
+                continue
+            }
+
+            // Elements on the lowest level all share the same tick, but only
+            // those whose exact deadline has passed are expired.
+            let mut expired = Vec::new();
+            for idx in idxs {
+                if self.entries[idx].at <= now {
+                    expired.push(idx);
+                } else {
+                    self.place(idx);
+                }
+            }
+            if expired.is_empty() {
+                return false
+            }
+            {
+                let entries = &self.entries;
+                expired.sort_by_key(|&idx| (entries[idx].at, entries[idx].seq));
+            }
+            for &idx in expired.iter() {
+                self.entries[idx].place = Place::Ready;
+            }
+            self.ready.extend(expired);
+            return true
+        }
+    }
+
+    /// Finds the slot which expires first, returning its level, index and the
+    /// tick at which it starts.
+    ///
+    /// Slots on lower levels always expire before those on higher levels, so
+    /// this only needs to find the first level with an occupied slot.
+    fn next_expiration(&self) -> Option<(usize, usize, u64)> {
+        for (level, l) in self.levels.iter().enumerate() {
+            if l.occupied == 0 {
+                continue
+            }
+            let shift = level * SLOT_BITS;
+            let slot_range = 1u64 << shift;
+            let level_range = slot_range << SLOT_BITS;
+            let now_slot = ((self.elapsed >> shift) as usize) & (SLOTS - 1);
+            let offset = l.occupied.rotate_right(now_slot as u32).trailing_zeros();
+            let slot = (now_slot + offset as usize) & (SLOTS - 1);
+            let mut deadline = (self.elapsed & !(level_range - 1))
+                + slot as u64 * slot_range;
+            if slot < now_slot {
+                deadline += level_range;
+            }
+            return Some((level, slot, deadline))
+        }
+        None
+    }
+
+    /// Advances `elapsed` to `tick`, migrating elements off the overflow list
+    /// if they're now within range of the wheel. Returns whether any elements
+    /// were migrated.
+    fn set_elapsed(&mut self, tick: u64) -> bool {
+        if tick <= self.elapsed {
+            return false
+        }
+        self.elapsed = tick;
+        if self.overflow.head == NONE || self.overflow_min - self.elapsed >= RANGE {
+            return false
+        }
+        let idxs = self.drain(self.overflow);
+        self.overflow = List::new();
+        self.overflow_min = u64::MAX;
+        for idx in idxs {
+            self.place(idx);
+        }
+        true
+    }
+
+    /// Links the element at `idx` into the slot or list appropriate for its
+    /// tick.
+    fn place(&mut self, idx: usize) {
+        let tick = cmp::max(self.entries[idx].tick, self.elapsed);
+        if tick - self.elapsed >= RANGE {
+            self.overflow_min = cmp::min(self.overflow_min, tick);
+            let mut list = self.overflow;
+            self.link(&mut list, idx);
+            self.overflow = list;
+            self.entries[idx].place = Place::Overflow;
+            return
+        }
+
+        // The level is determined by the most significant bit in which the
+        // tick differs from the current time.
+        let masked = (self.elapsed ^ tick) | (SLOTS as u64 - 1);
+        let significant = 63 - masked.leading_zeros() as usize;
+        let level = significant / SLOT_BITS;
+        let slot = ((tick >> (level * SLOT_BITS)) as usize) & (SLOTS - 1);
+        let mut list = self.levels[level].slots[slot];
+        self.link(&mut list, idx);
+        self.levels[level].slots[slot] = list;
+        self.levels[level].occupied |= 1 << slot;
+        self.entries[idx].place = Place::Slot(level, slot);
+    }
+
+    fn unlink_slot(&mut self, level: usize, slot: usize, idx: usize) {
+        let mut list = self.levels[level].slots[slot];
+        self.unlink(&mut list, idx);
+        self.levels[level].slots[slot] = list;
+        if list.head == NONE {
+            self.levels[level].occupied &= !(1 << slot);
+        }
+    }
+
+    fn link(&mut self, list: &mut List, idx: usize) {
+        self.entries[idx].prev = list.tail;
+        self.entries[idx].next = NONE;
+        if list.tail == NONE {
+            list.head = idx;
+        } else {
+            self.entries[list.tail].next = idx;
+        }
+        list.tail = idx;
+    }
+
+    fn unlink(&mut self, list: &mut List, idx: usize) {
+        let (prev, next) = (self.entries[idx].prev, self.entries[idx].next);
+        if prev == NONE {
+            list.head = next;
+        } else {
+            self.entries[prev].next = next;
+        }
+        if next == NONE {
+            list.tail = prev;
+        } else {
+            self.entries[next].prev = prev;
+        }
+    }
+
+    /// Returns the indices of all elements of `list`, in order.
+    fn drain(&self, list: List) -> Vec<usize> {
+        let mut idxs = Vec::new();
+        let mut cur = list.head;
+        while cur != NONE {
+            idxs.push(cur);
+            cur = self.entries[cur].next;
+        }
+        idxs
+    }
+
+    fn iter<'a>(&'a self, list: List) -> Iter<'a, T> {
+        Iter { wheel: self, cur: list.head }
+    }
+
+    /// Takes the value out of an unlinked element and frees its entry.
+    fn release(&mut self, idx: usize) -> T {
+        let value = self.entries[idx].value.take().unwrap();
+        self.free(idx);
+        value
+    }
+
+    fn free(&mut self, idx: usize) {
+        self.entries[idx].place = Place::Vacant;
+        self.entries[idx].next = self.next_free;
+        self.next_free = idx;
+    }
+
+    fn tick_of(&self, at: Instant) -> u64 {
+        let since = at.saturating_duration_since(self.origin);
+        let ticks = match since.as_secs().checked_mul(1_000_000_000) {
+            Some(nanos) => (nanos + u64::from(since.subsec_nanos())) / self.tick_nanos,
+            None => (since.as_nanos() / u128::from(self.tick_nanos)) as u64,
+        };
+        cmp::min(ticks, u64::MAX - RANGE)
+    }
+}
+
+impl List {
+    fn new() -> List {
+        List { head: NONE, tail: NONE }
+    }
+}
+
+struct Iter<'a, T: 'a> {
+    wheel: &'a Wheel<T>,
+    cur: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a Entry<T>;
+
+    fn next(&mut self) -> Option<&'a Entry<T>> {
+        if self.cur == NONE {
+            return None
+        }
+        let entry = &self.wheel.entries[self.cur];
+        self.cur = entry.next;
+        Some(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::time::{Duration, Instant};
+
+    use heap::Heap;
+    use super::{Wheel, Slot, RANGE};
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    fn drain(w: &mut Wheel<u32>, now: Instant) -> Vec<u32> {
+        let mut v = Vec::new();
+        while let Some(i) = w.poll(now) {
+            v.push(i);
+        }
+        v
+    }
+
+    #[test]
+    fn simple() {
+        let start = Instant::now();
+        let mut w = Wheel::new(start, ms(1));
+        w.insert(start + ms(8), 8, 8);
+        w.insert(start + ms(1), 1, 1);
+        w.insert(start + ms(4), 4, 4);
+        w.insert(start + ms(2), 2, 2);
+        assert_eq!(w.peek(), Some(start + ms(1)));
+        assert_eq!(drain(&mut w, start), vec![]);
+        assert_eq!(drain(&mut w, start + ms(2)), vec![1, 2]);
+        assert_eq!(w.peek(), Some(start + ms(4)));
+        assert_eq!(drain(&mut w, start + ms(100)), vec![4, 8]);
+        assert_eq!(w.peek(), None);
+        assert_eq!(w.poll(start + ms(1000)), None);
+    }
+
+    #[test]
+    fn exact_deadlines() {
+        let start = Instant::now();
+        let mut w = Wheel::new(start, ms(10));
+        w.insert(start + Duration::from_micros(1500), 2, 2);
+        w.insert(start + Duration::from_micros(1000), 1, 1);
+        assert_eq!(w.peek(), Some(start + Duration::from_micros(1000)));
+        assert_eq!(drain(&mut w, start + Duration::from_micros(1200)), vec![1]);
+        assert_eq!(w.peek(), Some(start + Duration::from_micros(1500)));
+        assert_eq!(drain(&mut w, start + Duration::from_micros(1499)), vec![]);
+        assert_eq!(drain(&mut w, start + Duration::from_micros(1500)), vec![2]);
+    }
+
+    #[test]
+    fn equal_deadlines_in_insertion_order() {
+        let start = Instant::now();
+        let mut w = Wheel::new(start, ms(1));
+        w.insert(start + ms(5000), 1, 1);
+        drain(&mut w, start + ms(4000));
+        w.insert(start + ms(5000), 2, 2);
+        w.insert(start + ms(5000), 3, 3);
+        assert_eq!(drain(&mut w, start + ms(5000)), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn equal_deadlines_in_seq_order() {
+        let start = Instant::now();
+        let mut w = Wheel::new(start, ms(1));
+        w.insert(start + ms(5), 3, 3);
+        w.insert(start + ms(5), 1, 1);
+        w.insert(start + ms(5), 2, 2);
+        assert_eq!(drain(&mut w, start + ms(5)), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn remove() {
+        let start = Instant::now();
+        let mut w = Wheel::new(start, ms(1));
+        w.insert(start + ms(5), 5, 5);
+        let four = w.insert(start + ms(4), 4, 4);
+        let far = w.insert(start + ms(100_000), 100, 100);
+        w.insert(start + ms(3), 3, 3);
+        assert_eq!(w.remove(four), 4);
+        assert_eq!(w.remove(far), 100);
+        assert_eq!(drain(&mut w, start + ms(1_000_000)), vec![3, 5]);
+    }
+
+    #[test]
+    fn remove_ready() {
+        let start = Instant::now();
+        let mut w = Wheel::new(start, ms(1));
+        let one = w.insert(start + ms(1), 1, 1);
+        w.insert(start + ms(1), 2, 2);
+        assert!(w.process(start + ms(1)));
+        assert_eq!(w.remove(one), 1);
+        assert_eq!(w.peek(), Some(start + ms(1)));
+        assert_eq!(drain(&mut w, start + ms(1)), vec![2]);
+    }
+
+    #[test]
+    fn past_and_overflow() {
+        let start = Instant::now();
+        let mut w = Wheel::new(start, ms(1));
+        drain(&mut w, start + ms(100));
+        w.insert(start, 1, 1);
+        let far = start + ms(RANGE * 3 + 7);
+        w.insert(far, 2, 2);
+        assert_eq!(w.peek(), Some(start));
+        assert_eq!(drain(&mut w, start + ms(100)), vec![1]);
+        assert_eq!(w.peek(), Some(far));
+        assert_eq!(drain(&mut w, far - ms(1)), vec![]);
+        assert_eq!(drain(&mut w, far), vec![2]);
+    }
+
+    #[test]
+    fn pop_everything() {
+        let start = Instant::now();
+        let mut w = Wheel::new(start, ms(1));
+        for i in 0..100u32 {
+            w.insert(start + ms(u64::from(i) * 997), u64::from(i), i);
+        }
+        w.insert(start + ms(RANGE * 2), 100, 100);
+        let mut v = Vec::new();
+        while let Some(i) = w.pop() {
+            v.push(i);
+        }
+        v.sort();
+        assert_eq!(v, (0..101).collect::<Vec<_>>());
+    }
+
+    // Drives a wheel and a simple model with the same pseudo-random sequence
+    // of operations, checking that they always agree.
+    #[test]
+    fn model() {
+        let start = Instant::now();
+        let mut w = Wheel::new(start, Duration::from_micros(100));
+        let mut model = BTreeMap::new();
+        let mut slots: Vec<Option<(Slot, (Instant, u64))>> = Vec::new();
+        let mut now = start;
+        let mut seq = 0;
+        let mut rng = 0x2545_f491_4f6c_dd1du64;
+        let mut next = move || {
+            rng ^= rng << 13;
+            rng ^= rng >> 7;
+            rng ^= rng << 17;
+            rng
+        };
+
+        for _ in 0..20_000 {
+            match next() % 8 {
+                0..=3 => {
+                    let offset = match next() % 4 {
+                        0 => Duration::from_micros(next() % 1_000),
+                        1 => Duration::from_micros(next() % 1_000_000),
+                        2 => Duration::from_micros(next() % 1_000_000_000),
+                        _ => Duration::from_secs(next() % (1 << 30)),
+                    };
+                    let at = now + offset;
+                    let id = slots.len() as u32;
+                    slots.push(Some((w.insert(at, seq, id), (at, seq))));
+                    model.insert((at, seq), id);
+                    seq += 1;
+                }
+                4 => {
+                    if slots.is_empty() {
+                        continue
+                    }
+                    let i = (next() % slots.len() as u64) as usize;
+                    if let Some((slot, key)) = slots[i].take() {
+                        assert_eq!(w.remove(slot), model.remove(&key).unwrap());
+                    }
+                }
+                5 => assert_eq!(w.peek(), model.keys().next().map(|k| k.0)),
+                _ => {
+                    now += Duration::from_micros(next() % 5_000);
+                    let expected = model.iter()
+                        .take_while(|e| (e.0).0 <= now)
+                        .map(|e| *e.1)
+                        .collect::<Vec<_>>();
+                    for id in expected.iter() {
+                        let (_, key) = slots[*id as usize].take().unwrap();
+                        model.remove(&key);
+                    }
+                    assert_eq!(drain(&mut w, now), expected);
+                }
+            }
+        }
+    }
+
+    // Compares the throughput of inserting and then resetting timers in the
+    // old heap and the wheel. Run with:
+    //
+    //     cargo test --release --lib bench_insert_reset -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_insert_reset() {
+        for &n in [1_000, 10_000, 100_000].iter() {
+            let start = Instant::now();
+            let deadline = |i: usize, round: usize| {
+                start + Duration::from_micros(((i * 7919 + round * 104_729) % 60_000_000) as u64)
+            };
+
+            let begin = Instant::now();
+            let mut heap = Heap::new();
+            let mut slots = (0..n).map(|i| Some(heap.push((deadline(i, 0), i)))).collect::<Vec<_>>();
+            for round in 1..10 {
+                for (i, slot) in slots.iter_mut().enumerate() {
+                    heap.remove(slot.take().unwrap());
+                    *slot = Some(heap.push((deadline(i, round), i)));
+                }
+            }
+            let heap_time = begin.elapsed();
+
+            let begin = Instant::now();
+            let mut wheel = Wheel::new(start, Duration::from_millis(1));
+            let mut slots = (0..n).map(|i| Some(wheel.insert(deadline(i, 0), i as u64, i))).collect::<Vec<_>>();
+            for round in 1..10 {
+                for (i, slot) in slots.iter_mut().enumerate() {
+                    wheel.remove(slot.take().unwrap());
+                    *slot = Some(wheel.insert(deadline(i, round), i as u64, i));
+                }
+            }
+            let wheel_time = begin.elapsed();
+
+            println!("{:>6} timers, {} inserts and {} resets: heap {:?}, wheel {:?}",
+                     n, n, n * 9, heap_time, wheel_time);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+extern crate futures_timer;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::time::Duration;
+
+use futures_timer::{Delay, ManualTimer};
+
+struct Counting;
+
+// Only allocations made by the test's own thread are counted, so that the
+// test harness allocating on other threads doesn't get in the way.
+thread_local!(static ALLOCATIONS: Cell<usize> = const { Cell::new(0) });
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(|a| a.get())
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|a| a.set(a.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Creates and drops `n` delays registered with `timer`, returning the number
+/// of allocations made per delay.
+fn churn(timer: &mut ManualTimer, delays: &mut Vec<Delay>, n: usize) -> f64 {
+    let before = allocations();
+    for _ in 0..n / delays.capacity() {
+        for _ in 0..delays.capacity() {
+            let at = timer.now() + Duration::from_secs(1);
+            delays.push(Delay::new_handle(at, timer.handle()));
+        }
+        timer.turn();
+        delays.clear();
+        timer.turn();
+    }
+    (allocations() - before) as f64 / n as f64
+}
+
+#[test]
+fn registrations_are_reused() {
+    let mut timer = ManualTimer::new();
+    let mut delays = Vec::with_capacity(100);
+
+    let cold = churn(&mut timer, &mut delays, 100);
+    let warm = churn(&mut timer, &mut delays, 100_000);
+    assert!(cold >= 1.0);
+    assert_eq!(warm, 0.0);
+}
@@ -0,0 +1,572 @@
+extern crate futures;
+extern crate futures_timer;
+
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use futures::future;
+use futures::prelude::*;
+use futures::executor::block_on;
+use futures::task;
+use futures_timer::{Clock, Deadline, Delay, DelaySet, ManualTimer, Registration, TestClock};
+use futures_timer::{Timer, TimerBuilder, TimerHandle, TimerScope};
+
+#[test]
+fn test_clock() {
+    let clock = TestClock::new();
+    let mut timer = Timer::with_clock(clock.clone());
+    let handle = timer.handle();
+    let start = handle.now();
+    let mut d = Delay::new_handle(start + Duration::from_secs(10), handle);
+
+    let mut poll = |clock: &TestClock, dur: Duration| {
+        clock.advance(dur);
+        block_on(future::poll_fn(|cx| {
+            let _ = timer.poll(cx);
+            timer.advance();
+            Ok::<_, io::Error>(Async::Ready(d.poll(cx)?.is_ready()))
+        })).unwrap()
+    };
+
+    assert!(!poll(&clock, Duration::from_secs(0)));
+    assert!(!poll(&clock, Duration::from_secs(9)));
+    assert!(poll(&clock, Duration::from_secs(1)));
+}
+
+#[test]
+fn delay_uses_timer_clock() {
+    let clock = TestClock::new();
+    let timer = Timer::with_clock(clock.clone());
+    let handle = timer.handle();
+    let d = Delay::new_handle(handle.now() + Duration::from_secs(10), handle);
+    assert!(!d.is_elapsed());
+    assert_eq!(d.remaining(), Duration::from_secs(10));
+    clock.advance(Duration::from_secs(4));
+    assert_eq!(d.remaining(), Duration::from_secs(6));
+    clock.advance(Duration::from_secs(6));
+    assert!(d.is_elapsed());
+}
+
+struct Recorder {
+    id: usize,
+    fired: Arc<Mutex<Vec<usize>>>,
+}
+
+impl task::Wake for Recorder {
+    fn wake(arc_self: &Arc<Self>) {
+        arc_self.fired.lock().unwrap().push(arc_self.id);
+    }
+}
+
+fn register(d: &mut Delay, id: usize, fired: &Arc<Mutex<Vec<usize>>>) {
+    let waker = task::Waker::from(Arc::new(Recorder { id, fired: fired.clone() }));
+    let mut map = task::LocalMap::new();
+    let mut cx = task::Context::without_spawn(&mut map, &waker);
+    assert!(d.poll(&mut cx).unwrap().is_pending());
+}
+
+#[test]
+fn next_deadline() {
+    let mut timer = ManualTimer::new();
+    let handle = timer.handle();
+    assert_eq!(handle.next_deadline(), None);
+    let early = Delay::new_handle(timer.now() + Duration::from_millis(10), timer.handle());
+    let late = Delay::new_handle(timer.now() + Duration::from_millis(20), timer.handle());
+    timer.turn();
+    assert_eq!(handle.next_deadline(), Some(early.deadline()));
+    timer.advance(Duration::from_millis(10));
+    assert_eq!(handle.next_deadline(), Some(late.deadline()));
+    timer.advance(Duration::from_millis(10));
+    assert_eq!(handle.next_deadline(), None);
+}
+
+#[test]
+fn fire_all_expired() {
+    let mut timer = Timer::new();
+    let handle = timer.handle();
+    let fired = Arc::new(Mutex::new(Vec::new()));
+    let mut delay = Delay::new_handle(handle.now() + Duration::from_millis(50), handle.clone());
+    register(&mut delay, 0, &fired);
+    block_on(future::poll_fn(|cx| timer.poll(cx).map(|_| Async::Ready(())))).unwrap();
+
+    // Nothing drives the timer from here on, so only `fire_all_expired` can
+    // fire the delay.
+    handle.fire_all_expired();
+    assert!(fired.lock().unwrap().is_empty());
+    thread::sleep(Duration::from_millis(100));
+    handle.fire_all_expired();
+    assert_eq!(*fired.lock().unwrap(), [0]);
+    assert!(block_on(future::poll_fn(|cx| delay.poll(cx).map(Async::Ready))).unwrap().is_ready());
+    assert_eq!(handle.next_deadline(), None);
+}
+
+#[test]
+fn equal_deadlines_fire_in_registration_order() {
+    let mut timer = ManualTimer::new();
+    let fired = Arc::new(Mutex::new(Vec::new()));
+    let at = timer.now() + Duration::from_millis(10);
+    let mut delays = (0..3).map(|_| Delay::new_handle(at, timer.handle())).collect::<Vec<_>>();
+    for (id, delay) in delays.iter_mut().enumerate() {
+        register(delay, id, &fired);
+    }
+    timer.advance(Duration::from_millis(10));
+    assert_eq!(*fired.lock().unwrap(), [0, 1, 2]);
+}
+
+#[test]
+fn delay_set_expires_in_order() {
+    let mut timer = ManualTimer::new();
+    let fired = Arc::new(Mutex::new(Vec::new()));
+    let mut set = DelaySet::new_handle(timer.handle());
+    set.insert("a", Duration::from_millis(30));
+    set.insert("b", Duration::from_millis(10));
+    set.insert("c", Duration::from_millis(20));
+    set.insert("d", Duration::from_millis(40));
+    assert!(set.remove(&"d").is_some());
+    assert!(set.reset(&"a", Duration::from_millis(25)));
+    assert!(!set.reset(&"d", Duration::from_millis(5)));
+    assert_eq!(set.len(), 3);
+
+    let waker = task::Waker::from(Arc::new(Recorder { id: 0, fired: fired.clone() }));
+    let mut map = task::LocalMap::new();
+    let mut cx = task::Context::without_spawn(&mut map, &waker);
+    assert!(set.poll_expired(&mut cx).unwrap().is_pending());
+    // Only the earliest deadline is registered with the timer.
+    timer.turn();
+    assert_eq!(timer.handle().active_timers(), 1);
+
+    let mut expired = Vec::new();
+    for _ in 0..3 {
+        timer.advance(Duration::from_millis(10));
+        while let Async::Ready(Some(key)) = set.poll_expired(&mut cx).unwrap() {
+            expired.push(key);
+        }
+    }
+    assert_eq!(expired, ["b", "c", "a"]);
+    assert_eq!(*fired.lock().unwrap(), [0, 0, 0]);
+    assert!(set.is_empty());
+    assert!(set.poll_expired(&mut cx).unwrap().is_ready());
+}
+
+#[test]
+fn manual_timer_fires_in_order() {
+    let mut timer = ManualTimer::new();
+    let fired = Arc::new(Mutex::new(Vec::new()));
+    let mut late = Delay::new_handle(timer.now() + Duration::from_millis(20), timer.handle());
+    let mut early = Delay::new_handle(timer.now() + Duration::from_millis(10), timer.handle());
+    register(&mut late, 2, &fired);
+    register(&mut early, 1, &fired);
+
+    timer.advance(Duration::from_millis(5));
+    assert!(fired.lock().unwrap().is_empty());
+    timer.advance(Duration::from_millis(5));
+    assert_eq!(*fired.lock().unwrap(), [1]);
+    timer.advance(Duration::from_millis(5));
+    assert_eq!(*fired.lock().unwrap(), [1]);
+    timer.advance(Duration::from_millis(5));
+    assert_eq!(*fired.lock().unwrap(), [1, 2]);
+}
+
+#[test]
+fn resolution_rounds_up() {
+    let clock = TestClock::new();
+    let mut timer = Timer::builder()
+        .clock(clock.clone())
+        .resolution(Duration::from_millis(10))
+        .build();
+    let start = clock.now();
+    let mut d = Delay::new_handle(start + Duration::from_millis(3), timer.handle());
+
+    let mut poll = |clock: &TestClock, dur: Duration| {
+        clock.advance(dur);
+        block_on(future::poll_fn(|cx| {
+            let _ = timer.poll(cx);
+            timer.advance();
+            let next = timer.next_event();
+            Ok::<_, io::Error>(Async::Ready((d.poll(cx)?.is_ready(), next)))
+        })).unwrap()
+    };
+
+    let tick = Some(start + Duration::from_millis(10));
+    assert_eq!(poll(&clock, Duration::from_millis(0)), (false, tick));
+    assert_eq!(poll(&clock, Duration::from_millis(3)), (false, tick));
+    assert_eq!(poll(&clock, Duration::from_millis(6)), (false, tick));
+    assert_eq!(poll(&clock, Duration::from_millis(1)), (true, None));
+}
+
+fn wakeups(builder: TimerBuilder) -> usize {
+    wakeups_with(builder, Delay::new_handle)
+}
+
+fn wakeups_with<F>(builder: TimerBuilder, new_delay: F) -> usize
+    where F: Fn(Instant, TimerHandle) -> Delay,
+{
+    let clock = TestClock::new();
+    let mut timer = builder.clock(clock.clone()).build();
+    let start = clock.now();
+    let delays = (1..10_001).map(|i| {
+        new_delay(start + Duration::from_micros(i), timer.handle())
+    }).collect::<Vec<_>>();
+    block_on(future::poll_fn(|cx| timer.poll(cx).map(|_| Async::Ready(())))).unwrap();
+
+    let mut wakeups = 0;
+    while let Some(next) = timer.next_event() {
+        timer.advance_to(next);
+        wakeups += 1;
+    }
+    drop(delays);
+    wakeups
+}
+
+#[test]
+fn coalescing_reduces_wakeups() {
+    assert_eq!(wakeups(Timer::builder()), 10_000);
+    assert_eq!(wakeups(Timer::builder().coalescing(Duration::from_millis(10))), 1);
+    assert_eq!(wakeups(Timer::builder().coalescing(Duration::from_millis(1))), 10);
+}
+
+#[test]
+fn delay_slack_overrides_coalescing() {
+    let slack = |slack| move |at, handle| {
+        Delay::builder().at(at).slack(slack).handle(handle).build()
+    };
+    assert_eq!(wakeups_with(Timer::builder(), slack(Duration::from_millis(10))), 1);
+    assert_eq!(wakeups_with(Timer::builder().coalescing(Duration::from_millis(10)),
+                            slack(Duration::from_secs(0))),
+               10_000);
+}
+
+#[test]
+fn drain_budget_fires_in_batches() {
+    let clock = TestClock::new();
+    let mut timer = Timer::builder()
+        .clock(clock.clone())
+        .drain_budget(10)
+        .build();
+    let handle = timer.handle();
+    let start = clock.now();
+    let delays = (1..26).map(|i| {
+        Delay::new_handle(start + Duration::from_secs(i), handle.clone())
+    }).collect::<Vec<_>>();
+    block_on(future::poll_fn(|cx| timer.poll(cx).map(|_| Async::Ready(())))).unwrap();
+    assert_eq!(handle.active_timers(), 25);
+
+    // Simulate a resume from suspend, jumping past all the deadlines at once.
+    clock.advance(Duration::from_secs(60));
+    let mut batches = Vec::new();
+    while handle.active_timers() > 0 {
+        assert!(timer.next_event().unwrap() <= clock.now());
+        let before = handle.active_timers();
+        timer.advance();
+        batches.push(before - handle.active_timers());
+    }
+    assert_eq!(batches, [10, 10, 5]);
+    assert_eq!(timer.next_event(), None);
+    assert!(delays.iter().all(|d| d.is_elapsed()));
+}
+
+#[test]
+fn shared_deadline() {
+    let mut timer = ManualTimer::new();
+    let fired = Arc::new(Mutex::new(Vec::new()));
+    let deadline = Deadline::new_handle(timer.now() + Duration::from_millis(10),
+                                        timer.handle());
+    let clones = (0..3).map(|_| deadline.clone()).collect::<Vec<_>>();
+    timer.turn();
+    assert_eq!(timer.handle().active_timers(), 1);
+
+    let mut map = task::LocalMap::new();
+    for (id, deadline) in clones.iter().enumerate() {
+        let waker = task::Waker::from(Arc::new(Recorder { id, fired: fired.clone() }));
+        let mut cx = task::Context::without_spawn(&mut map, &waker);
+        assert!(deadline.poll_elapsed(&mut cx).unwrap().is_pending());
+    }
+    assert_eq!(timer.handle().active_timers(), 1);
+
+    timer.advance(Duration::from_millis(10));
+    fired.lock().unwrap().sort();
+    assert_eq!(*fired.lock().unwrap(), [0, 1, 2]);
+    for mut deadline in clones {
+        block_on(future::poll_fn(|cx| deadline.poll(cx))).unwrap();
+    }
+    assert!(deadline.is_elapsed());
+    assert_eq!(timer.handle().active_timers(), 0);
+}
+
+#[test]
+fn delay_shared_between_tasks() {
+    let mut timer = ManualTimer::new();
+    let fired = Arc::new(Mutex::new(Vec::new()));
+    let delay = Delay::new_handle(timer.now() + Duration::from_millis(10), timer.handle());
+    let deadline = Deadline::from(delay);
+    let other = deadline.clone();
+
+    // Each task polls twice, but is only woken once.
+    let mut map = task::LocalMap::new();
+    let wakers = (0..2)
+        .map(|id| task::Waker::from(Arc::new(Recorder { id, fired: fired.clone() })))
+        .collect::<Vec<_>>();
+    for _ in 0..2 {
+        for (waker, deadline) in wakers.iter().zip(&[&deadline, &other]) {
+            let mut cx = task::Context::without_spawn(&mut map, waker);
+            assert!(deadline.poll_elapsed(&mut cx).unwrap().is_pending());
+        }
+    }
+
+    timer.advance(Duration::from_millis(10));
+    fired.lock().unwrap().sort();
+    assert_eq!(*fired.lock().unwrap(), [0, 1]);
+    block_on(other).unwrap();
+    assert!(deadline.is_elapsed());
+}
+
+#[test]
+fn cloned_delay() {
+    let mut timer = ManualTimer::new();
+    let fired = Arc::new(Mutex::new(Vec::new()));
+    let mut delay = Delay::new_handle(timer.now() + Duration::from_millis(10), timer.handle());
+    let mut other = delay.clone();
+    assert_eq!(other.deadline(), delay.deadline());
+    register(&mut delay, 0, &fired);
+    register(&mut other, 1, &fired);
+    timer.turn();
+    assert_eq!(timer.handle().active_timers(), 1);
+
+    timer.advance(Duration::from_millis(10));
+    fired.lock().unwrap().sort();
+    assert_eq!(*fired.lock().unwrap(), [0, 1]);
+    assert!(delay.is_elapsed());
+    assert!(other.is_elapsed());
+    block_on(future::poll_fn(|cx| delay.poll(cx))).unwrap();
+    block_on(future::poll_fn(|cx| other.poll(cx))).unwrap();
+    assert_eq!(timer.handle().active_timers(), 0);
+
+    // Only the last clone standing can be reset.
+    let reset = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        delay.reset(Duration::from_millis(10))
+    }));
+    assert!(reset.is_err());
+    drop(other);
+    delay.reset(Duration::from_millis(10));
+    assert!(!delay.is_elapsed());
+    assert_eq!(timer.handle().active_timers(), 1);
+}
+
+#[test]
+fn spurious_polls_keep_one_registration() {
+    let mut timer = ManualTimer::new();
+    let handle = timer.handle();
+    let fired = Arc::new(Mutex::new(Vec::new()));
+    let mut d = Delay::new_handle(timer.now() + Duration::from_millis(100), handle.clone());
+    for i in 0..1000 {
+        register(&mut d, i, &fired);
+        timer.turn();
+        assert_eq!(handle.active_timers(), 1);
+    }
+    assert!(fired.lock().unwrap().is_empty());
+
+    // Only the task which polled last is woken.
+    timer.advance(Duration::from_millis(100));
+    assert_eq!(*fired.lock().unwrap(), [999]);
+    assert_eq!(handle.active_timers(), 0);
+}
+
+// Fires once `warn` has passed and again once `fail` has, through a single
+// registration.
+struct TwoPhase {
+    registration: Registration,
+    fail: Option<Instant>,
+}
+
+impl Future for TwoPhase {
+    type Item = &'static str;
+    type Error = io::Error;
+
+    fn poll(&mut self, cx: &mut task::Context) -> Poll<&'static str, io::Error> {
+        if self.registration.poll(cx)?.is_pending() {
+            return Ok(Async::Pending)
+        }
+        match self.fail.take() {
+            Some(at) => {
+                self.registration.reset_at(at);
+                Ok(Async::Ready("warn"))
+            }
+            None => Ok(Async::Ready("fail")),
+        }
+    }
+}
+
+#[test]
+fn registration_two_phase() {
+    let mut timer = ManualTimer::new();
+    let handle = timer.handle();
+    let start = timer.now();
+    let mut phases = TwoPhase {
+        registration: Registration::new(start + Duration::from_millis(10), handle.clone()),
+        fail: Some(start + Duration::from_millis(30)),
+    };
+    let mut poll = |timer: &mut ManualTimer, dur| {
+        timer.advance(dur);
+        block_on(future::poll_fn(|cx| {
+            Ok::<_, io::Error>(Async::Ready(match phases.poll(cx)? {
+                Async::Ready(phase) => Some(phase),
+                Async::Pending => None,
+            }))
+        })).unwrap()
+    };
+
+    assert_eq!(poll(&mut timer, Duration::from_millis(5)), None);
+    assert_eq!(poll(&mut timer, Duration::from_millis(5)), Some("warn"));
+    assert_eq!(handle.active_timers(), 1);
+    assert_eq!(poll(&mut timer, Duration::from_millis(10)), None);
+    assert_eq!(poll(&mut timer, Duration::from_millis(10)), Some("fail"));
+    assert_eq!(handle.active_timers(), 0);
+}
+
+#[test]
+fn scope_cancels_delays() {
+    let mut timer = ManualTimer::new();
+    let fired = Arc::new(Mutex::new(Vec::new()));
+    let scope = TimerScope::new_handle(timer.handle());
+    let nested = TimerScope::new_handle(scope.handle());
+    let at = timer.now() + Duration::from_millis(10);
+    let mut outside = Delay::new_handle(at, timer.handle());
+    let mut scoped = Delay::new_handle(at, scope.handle());
+    let mut inner = Delay::new_handle(at, nested.handle());
+    register(&mut outside, 1, &fired);
+    register(&mut scoped, 2, &fired);
+    register(&mut inner, 3, &fired);
+    timer.turn();
+    assert_eq!(timer.handle().active_timers(), 3);
+
+    // Ending the outer scope ends the nested one along with it.
+    let handle = scope.handle();
+    drop(scope);
+    assert_eq!(timer.handle().active_timers(), 1);
+    timer.advance(Duration::from_millis(20));
+    assert_eq!(*fired.lock().unwrap(), [1]);
+    assert!(block_on(scoped).is_err());
+    assert!(block_on(inner).is_err());
+    let late = Delay::new_handle(timer.now() + Duration::from_millis(10), handle);
+    assert!(block_on(late).is_err());
+    drop(nested);
+}
+
+#[test]
+fn poll_no_register() {
+    let clock = TestClock::new();
+    let mut timer = Timer::with_clock(clock.clone());
+    let handle = timer.handle();
+    let mut d = Delay::new_handle(handle.now() + Duration::from_secs(10), handle.clone());
+    assert!(!d.poll_no_register());
+    assert_eq!(handle.active_timers(), 1);
+
+    clock.advance(Duration::from_secs(10));
+    assert!(d.poll_no_register());
+    assert_eq!(handle.active_timers(), 1);
+
+    // Once the timer has fired the delay it stops counting as active, which
+    // polling without registering has no say in.
+    block_on(future::poll_fn(|cx| {
+        let _ = timer.poll(cx);
+        timer.advance();
+        Ok::<_, ()>(Async::Ready(()))
+    })).unwrap();
+    assert_eq!(handle.active_timers(), 0);
+    assert!(d.poll_no_register());
+    assert_eq!(handle.active_timers(), 0);
+}
+
+/// A clock which fails to read the time whenever told to.
+struct FailingClock {
+    clock: TestClock,
+    fail: AtomicBool,
+}
+
+impl Clock for FailingClock {
+    fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    fn try_now(&self) -> io::Result<Instant> {
+        if self.fail.load(Ordering::SeqCst) {
+            return Err(io::Error::other("counter unavailable"))
+        }
+        Ok(self.clock.now())
+    }
+}
+
+#[test]
+fn clock_error_fails_delays() {
+    let clock = Arc::new(FailingClock { clock: TestClock::new(), fail: AtomicBool::new(false) });
+    let mut timer = Timer::with_clock(clock.clone());
+    let handle = timer.handle();
+    let fired = Arc::new(Mutex::new(Vec::new()));
+    let mut d = Delay::new_handle(handle.now() + Duration::from_secs(10), handle.clone());
+    register(&mut d, 1, &fired);
+    let mut turn = || {
+        block_on(future::poll_fn(|cx| {
+            let _ = timer.poll(cx);
+            timer.advance();
+            Ok::<_, ()>(Async::Ready(()))
+        })).unwrap()
+    };
+    turn();
+    assert_eq!(handle.active_timers(), 1);
+
+    // Rather than leaving the delay pending forever its task is woken to
+    // find out about the error.
+    clock.fail.store(true, Ordering::SeqCst);
+    turn();
+    assert_eq!(*fired.lock().unwrap(), [1]);
+    assert_eq!(handle.active_timers(), 0);
+    let err = block_on(future::poll_fn(|cx| d.poll(cx))).unwrap_err();
+    assert!(err.to_string().contains("counter unavailable"));
+}
+
+#[test]
+fn drop_deregisters() {
+    let clock = TestClock::new();
+    let mut timer = Timer::with_clock(clock.clone());
+    let handle = timer.handle();
+    let fired = Arc::new(Mutex::new(Vec::new()));
+    let mut d = Delay::new_handle(handle.now() + Duration::from_secs(10), handle.clone());
+    register(&mut d, 1, &fired);
+    let turn = |timer: &mut Timer| {
+        block_on(future::poll_fn(|cx| {
+            let _ = timer.poll(cx);
+            timer.advance();
+            Ok::<_, ()>(Async::Ready(()))
+        })).unwrap()
+    };
+    turn(&mut timer);
+    assert!(timer.next_event().is_some());
+    assert_eq!(handle.active_timers(), 1);
+
+    drop(d);
+    assert_eq!(handle.active_timers(), 0);
+    turn(&mut timer);
+    assert_eq!(timer.next_event(), None);
+    clock.advance(Duration::from_secs(10));
+    turn(&mut timer);
+    assert!(fired.lock().unwrap().is_empty());
+}
+
+#[test]
+fn reset_checked() {
+    let mut timer = ManualTimer::new();
+    let period = Duration::from_millis(10);
+    let mut d = Delay::new_handle(timer.now() + period, timer.handle());
+
+    timer.advance(Duration::from_millis(5));
+    assert!(!d.reset_checked(period));
+    assert_eq!(d.deadline(), timer.now() + period);
+
+    timer.advance(Duration::from_millis(10));
+    assert!(d.reset_checked(period));
+    assert_eq!(d.deadline(), timer.now() + period);
+    assert!(!d.is_elapsed());
+}
@@ -0,0 +1,41 @@
+extern crate futures_timer;
+
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use futures_timer::Delay;
+
+// Measures the throughput of many threads creating and dropping delays on the
+// global timer at the same time. Run with:
+//
+//     cargo test --release --test contention -- --ignored --nocapture
+#[test]
+#[ignore]
+fn many_threads_creating_delays() {
+    const THREADS: usize = 8;
+    const DELAYS: usize = 200_000;
+
+    // Spin up the global timer ahead of time.
+    drop(Delay::new(Duration::from_secs(1)));
+
+    let barrier = Arc::new(Barrier::new(THREADS + 1));
+    let threads = (0..THREADS).map(|_| {
+        let barrier = barrier.clone();
+        thread::spawn(move || {
+            barrier.wait();
+            for _ in 0..DELAYS {
+                drop(Delay::new(Duration::from_secs(1)));
+            }
+        })
+    }).collect::<Vec<_>>();
+
+    barrier.wait();
+    let start = Instant::now();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    let elapsed = start.elapsed();
+    println!("{} threads created {} delays each in {:?}, {:?} per delay",
+             THREADS, DELAYS, elapsed, elapsed / (THREADS * DELAYS) as u32);
+}
@@ -0,0 +1,887 @@
+extern crate futures;
+extern crate futures_timer;
+
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use futures::{future, stream, task};
+use futures::future::Either;
+use futures::prelude::*;
+use futures::executor::block_on;
+use futures_timer::{Cancelled, Delay, Elapsed, TimeoutKind, on_timeout_fired, select_timeout};
+use futures_timer::remaining_timeout;
+use futures_timer::ext::{Event, Heartbeat, InterItemClock};
+use futures_timer::{FutureExt as TimerFutureExt, StreamExt as TimerStreamExt};
+use futures_timer::SinkExt as TimerSinkExt;
+
+fn delayed(millis: Vec<u64>) -> impl Stream<Item = (), Error = io::Error> {
+    stream::iter_ok(millis).and_then(|ms| Delay::new(Duration::from_millis(ms)))
+}
+
+#[test]
+fn timeout_elapsed() {
+    let f = future::empty::<(), io::Error>();
+    let err = block_on(f.timeout(Duration::from_millis(10))).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    let elapsed = err.get_ref().and_then(|e| e.downcast_ref::<Elapsed>());
+    assert!(elapsed.is_some());
+}
+
+#[test]
+fn elapsed_durations() {
+    let dur = Duration::from_millis(10);
+    let f = future::empty::<(), ()>();
+    let elapsed = match block_on(f.timeout_result(dur)) {
+        Ok(Err(elapsed)) => elapsed,
+        other => panic!("expected timeout, got {:?}", other),
+    };
+    assert_eq!(elapsed.timeout(), dur);
+    assert!(elapsed.elapsed() >= dur);
+
+    let f = future::empty::<(), io::Error>();
+    let err = block_on(f.timeout(dur)).unwrap_err();
+    let msg = err.to_string();
+    assert!(msg.starts_with("deadline of 10ms elapsed after "), "{}", msg);
+
+    let s = future::empty::<(), io::Error>().into_stream().timeout(dur);
+    let (err, _) = block_on(s.next()).map(|_| ()).unwrap_err();
+    let elapsed = err.get_ref().and_then(|e| e.downcast_ref::<Elapsed>()).unwrap();
+    assert_eq!(elapsed.timeout(), dur);
+}
+
+#[test]
+fn timeout_result_elapsed() {
+    let f = future::empty::<(), ()>();
+    let res = block_on(f.timeout_result(Duration::from_millis(10)));
+    match res {
+        Ok(Err(_elapsed)) => {}
+        other => panic!("expected timeout, got {:?}", other),
+    }
+}
+
+#[test]
+fn timeout_result_passes_through() {
+    let f = future::ok::<u32, ()>(3);
+    assert_eq!(block_on(f.timeout_result(Duration::from_secs(10))), Ok(Ok(3)));
+
+    let f = future::err::<u32, ()>(());
+    assert_eq!(block_on(f.timeout_result(Duration::from_secs(10))), Err(()));
+}
+
+#[test]
+fn timeout_into_inner() {
+    let mut t = future::empty::<(), io::Error>().timeout(Duration::from_millis(10));
+    assert!(block_on(future::poll_fn(|cx| t.poll(cx))).is_err());
+    let f = t.into_inner();
+    assert!(block_on(f.timeout(Duration::from_millis(10))).is_err());
+}
+
+#[test]
+fn timeout_stream_accessors() {
+    let dur = Duration::from_millis(10);
+    let mut s = stream::empty::<(), io::Error>().timeout(dur);
+    assert_eq!(s.timeout_duration(), dur);
+    assert!(block_on(future::poll_fn(|cx| s.get_mut().poll_next(cx))).unwrap().is_none());
+    let mut s = s.into_inner();
+    assert!(block_on(future::poll_fn(|cx| s.poll_next(cx))).unwrap().is_none());
+}
+
+#[test]
+fn debug() {
+    let t = future::ok::<(), io::Error>(()).timeout(Duration::from_secs(10));
+    let s = format!("{:?}", t);
+    assert!(s.starts_with("Timeout { timeout: Delay { when: "), "{}", s);
+    assert!(s.contains("elapsed: false"), "{}", s);
+
+    let t = stream::empty::<(), io::Error>().timeout(Duration::from_secs(10));
+    let s = format!("{:?}", t);
+    assert!(s.contains("dur: 10s"), "{}", s);
+}
+
+#[derive(Debug)]
+enum MyError {
+    Timeout,
+    Io(io::Error),
+}
+
+impl From<io::Error> for MyError {
+    fn from(err: io::Error) -> MyError {
+        MyError::Io(err)
+    }
+}
+
+#[test]
+fn on_elapsed_map() {
+    let dur = Duration::from_millis(10);
+    let f = future::empty::<(), io::Error>().timeout_result(dur);
+    match block_on(f.on_elapsed_map(|| MyError::Timeout)) {
+        Err(MyError::Timeout) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    let err = io::Error::new(io::ErrorKind::BrokenPipe, "boom");
+    let f = future::err::<(), io::Error>(err).timeout_result(dur);
+    match block_on(f.on_elapsed_map(|| MyError::Timeout)) {
+        Err(MyError::Io(e)) => assert_eq!(e.to_string(), "boom"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    let f = future::ok::<u32, io::Error>(3).timeout_result(dur);
+    let res: Result<u32, MyError> = block_on(f.on_elapsed_map(|| MyError::Timeout));
+    assert_eq!(res.unwrap(), 3);
+}
+
+#[test]
+fn timeout_either() {
+    let dur = Duration::from_millis(10);
+    let f = future::empty::<u32, ()>().timeout_either(dur);
+    assert_eq!(block_on(f), Ok(Either::Right(())));
+
+    let f = future::ok::<u32, ()>(3).timeout_either(Duration::from_secs(10));
+    assert_eq!(block_on(f), Ok(Either::Left(3)));
+
+    let f = future::err::<u32, ()>(()).timeout_either(Duration::from_secs(10));
+    assert_eq!(block_on(f), Err(()));
+}
+
+#[test]
+fn heartbeat_progress() {
+    let work = Delay::new(Duration::from_millis(110));
+    let events = work.with_heartbeat(Duration::from_millis(20), Duration::from_secs(10));
+    let events: Vec<_> = block_on(events.collect()).unwrap();
+    let (done, progress) = events.split_last().unwrap();
+    assert_eq!(*done, Heartbeat::Done(()));
+    assert!(progress.iter().all(|e| *e == Heartbeat::Progress));
+    assert!((4..=5).contains(&progress.len()), "{:?}", events);
+
+    let work = future::empty::<(), io::Error>();
+    let mut events = work.with_heartbeat(Duration::from_millis(20), Duration::from_millis(50));
+    let mut progress = 0;
+    let err = loop {
+        match block_on(events.next()) {
+            Ok((Some(Heartbeat::Progress), rest)) => { progress += 1; events = rest; }
+            Ok((other, _)) => panic!("expected a timeout, got {:?}", other),
+            Err((err, _)) => break err,
+        }
+    };
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    assert!((1..=2).contains(&progress), "{}", progress);
+}
+
+#[test]
+fn heartbeat_zero_period() {
+    let work = Delay::new(Duration::from_millis(20));
+    let events = work.with_heartbeat(Duration::from_secs(0), Duration::from_secs(10));
+    let events: Vec<_> = block_on(events.collect()).unwrap();
+    assert_eq!(events, vec![Heartbeat::Done(())]);
+}
+
+#[test]
+fn timed() {
+    let dur = Duration::from_millis(20);
+    let ((), took) = block_on(Delay::new(dur).timed()).unwrap();
+    assert!(took >= dur, "{:?}", took);
+
+    let err = block_on(future::err::<(), u32>(3).timed()).unwrap_err();
+    assert_eq!(err, 3);
+}
+
+#[test]
+fn select_timeout_fast_winner() {
+    let futures = [200, 10, 100].iter().map(|&ms| {
+        Delay::new(Duration::from_millis(ms)).map(move |()| ms)
+    });
+    let res = block_on(select_timeout(futures, Duration::from_secs(10))).unwrap();
+    assert_eq!(res, (10, 1));
+
+    let futures = vec![
+        Either::Left(future::empty::<(), io::Error>()),
+        Either::Right(future::err(io::Error::from(io::ErrorKind::BrokenPipe))),
+    ];
+    let err = block_on(select_timeout(futures, Duration::from_secs(10))).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+}
+
+#[test]
+fn select_timeout_all_slow() {
+    let futures = (0..3).map(|_| Delay::new(Duration::from_secs(10)));
+    let err = block_on(select_timeout(futures, Duration::from_millis(10))).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+    let none = Vec::<future::Empty<(), io::Error>>::new();
+    let err = block_on(select_timeout(none, Duration::from_millis(10))).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+}
+
+// Completes on its third poll, without ever waking its task.
+struct ThirdPoll(usize);
+
+impl Future for ThirdPoll {
+    type Item = usize;
+    type Error = io::Error;
+
+    fn poll(&mut self, _cx: &mut task::Context) -> Poll<usize, io::Error> {
+        self.0 += 1;
+        Ok(if self.0 >= 3 { Async::Ready(self.0) } else { Async::Pending })
+    }
+}
+
+#[test]
+fn timeout_graceful() {
+    // The future is polled once initially and once more when the timeout
+    // fires, so only the final poll of a graceful timeout completes it.
+    let dur = Duration::from_millis(10);
+    let err = block_on(ThirdPoll(0).timeout(dur)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    assert_eq!(block_on(ThirdPoll(0).timeout_graceful(dur)).unwrap(), 3);
+
+    let err = block_on(future::empty::<(), io::Error>().timeout_graceful(dur)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+}
+
+#[test]
+fn timeout_cancellable() {
+    let dur = Duration::from_millis(10);
+    let cancel = Arc::new(AtomicBool::new(false));
+    let f = future::ok::<u32, io::Error>(3);
+    assert_eq!(block_on(f.timeout_cancellable(dur, cancel.clone())).unwrap(), 3);
+
+    let f = future::empty::<(), io::Error>();
+    let err = block_on(f.timeout_cancellable(dur, cancel.clone())).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    assert!(err.get_ref().unwrap().downcast_ref::<Elapsed>().is_some());
+
+    // A flag which is set already keeps the future from being polled at all.
+    cancel.store(true, Ordering::SeqCst);
+    let f = future::poll_fn(|_| -> Poll<(), io::Error> { panic!("polled") });
+    let err = block_on(f.timeout_cancellable(Duration::from_secs(10), cancel)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+    assert!(err.get_ref().unwrap().downcast_ref::<Cancelled>().is_some());
+}
+
+#[test]
+fn delay_gates_first_poll() {
+    let dur = Duration::from_millis(50);
+    let start = Instant::now();
+    let mut first_poll = None;
+    let mut f = future::poll_fn(|_| -> Poll<(), io::Error> {
+        first_poll.get_or_insert_with(Instant::now);
+        Ok(Async::Ready(()))
+    }).delay(dur);
+    let res = block_on(future::poll_fn(|cx| Ok::<_, io::Error>(Async::Ready(f.poll(cx)?))));
+    assert!(res.unwrap().is_pending());
+    assert!(!f.is_started());
+    block_on(f).unwrap();
+    assert!(first_poll.unwrap() - start >= dur);
+}
+
+#[test]
+fn timeout_with_calls_closure_once() {
+    let mut calls = 0;
+    {
+        let f = future::empty::<(), u32>();
+        let t = f.timeout_with(Duration::from_millis(10), || { calls += 1; 5u32 });
+        assert_eq!(block_on(t), Err(5));
+    }
+    assert_eq!(calls, 1);
+
+    let f = future::ok::<(), u32>(());
+    let t = f.timeout_with(Duration::from_millis(10), || -> u32 { panic!() });
+    assert_eq!(block_on(t), Ok(()));
+}
+
+#[test]
+fn timeout_map_calls_closure_per_timeout() {
+    let mut calls = 0;
+    {
+        let s = delayed(vec![150, 5, 150]).map_err(|_| 0u32);
+        let mut s = s.timeout_map(Duration::from_millis(60), || { calls += 1; calls });
+        let mut errors = Vec::new();
+        let mut items = 0;
+        while items < 3 {
+            match block_on(future::poll_fn(|cx| s.poll_next(cx))) {
+                Ok(item) => items += item.into_iter().count(),
+                Err(e) => errors.push(e),
+            }
+        }
+        assert_eq!(errors, [1, 2, 3, 4]);
+        assert_eq!(block_on(future::poll_fn(|cx| s.poll_next(cx))), Ok(None));
+    }
+    assert_eq!(calls, 4);
+}
+
+#[test]
+fn timeout_within_parent_deadline() {
+    let parent_deadline = Instant::now() + Duration::from_millis(100);
+    let child = remaining_timeout(parent_deadline);
+    assert!(child > Duration::from_millis(0));
+    assert!(child <= Duration::from_millis(100));
+
+    let f = Delay::new(Duration::from_millis(10)).timeout_within(parent_deadline);
+    assert_eq!(f.deadline(), parent_deadline);
+    block_on(f).unwrap();
+
+    let start = Instant::now();
+    let err = block_on(future::empty::<(), io::Error>().timeout_within(parent_deadline))
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    assert!(start.elapsed() < Duration::from_millis(500));
+}
+
+#[test]
+fn timeout_within_expired_parent_deadline() {
+    let parent_deadline = Instant::now();
+    assert_eq!(remaining_timeout(parent_deadline), Duration::from_secs(0));
+
+    // The future isn't driven at all, even though it'd be ready right away.
+    let mut polled = false;
+    let f = future::poll_fn(|_| { polled = true; Ok::<_, io::Error>(Async::Ready(())) });
+    let err = block_on(f.timeout_within(parent_deadline)).unwrap_err();
+    assert!(err.get_ref().and_then(|e| e.downcast_ref::<Elapsed>()).is_some());
+    assert!(!polled);
+}
+
+#[test]
+fn timeout_first_then() {
+    let each = Duration::from_millis(50);
+    let s = delayed(vec![150, 5, 5]).timeout_first_then(Duration::from_secs(10), each);
+    assert_eq!(s.timeout_duration(), each);
+    assert_eq!(block_on(s.collect::<Vec<_>>()).unwrap().len(), 3);
+
+    let s = delayed(vec![150, 5, 5]).timeout(each);
+    assert!(block_on(s.collect::<Vec<_>>()).is_err());
+}
+
+#[test]
+fn timeout_after_first() {
+    let each = Duration::from_millis(50);
+    let s = delayed(vec![150, 5, 5]).timeout_after_first(each);
+    assert_eq!(s.timeout_duration(), each);
+    assert_eq!(block_on(s.collect::<Vec<_>>()).unwrap().len(), 3);
+
+    // Once the first item is out the following ones are timed out.
+    let mut s = delayed(vec![150, 200]).timeout_after_first(each);
+    assert!(block_on(future::poll_fn(|cx| s.poll_next(cx))).unwrap().is_some());
+    let err = block_on(future::poll_fn(|cx| s.poll_next(cx))).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+    // A stream which ends without an item never times out.
+    let s = delayed(vec![150]).filter(|_| Ok(false)).timeout_after_first(each);
+    assert!(block_on(s.collect::<Vec<_>>()).unwrap().is_empty());
+}
+
+#[test]
+fn stream_timeout_at() {
+    let each = Duration::from_millis(50);
+
+    // The first item may take until the absolute deadline to show up.
+    let first_at = Instant::now() + Duration::from_millis(300);
+    let s = delayed(vec![150, 5, 5]).timeout_at(first_at, each);
+    assert_eq!(s.timeout_duration(), each);
+    assert_eq!(block_on(s.collect::<Vec<_>>()).unwrap().len(), 3);
+
+    // But not any longer than that.
+    let start = Instant::now();
+    let first_at = start + Duration::from_millis(100);
+    let mut s = delayed(vec![300]).timeout_at(first_at, Duration::from_secs(10));
+    let err = block_on(future::poll_fn(|cx| s.poll_next(cx))).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    assert!(Instant::now() >= first_at);
+    assert!(start.elapsed() < Duration::from_millis(300));
+
+    // Items after the first one are bound by the relative timeout.
+    let first_at = Instant::now() + Duration::from_secs(10);
+    let s = delayed(vec![5, 150]).timeout_at(first_at, each);
+    assert!(block_on(s.collect::<Vec<_>>()).is_err());
+}
+
+#[test]
+fn take_for() {
+    let start = Instant::now();
+    let s = delayed(vec![10, 10, 10, 500, 10]).take_for(Duration::from_millis(200));
+    assert_eq!(block_on(s.collect::<Vec<_>>()).unwrap().len(), 3);
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(200));
+    assert!(elapsed < Duration::from_millis(500));
+
+    // A stream which is always ready is cut off as well.
+    let at = Instant::now() + Duration::from_millis(50);
+    let mut s = stream::repeat::<u32, io::Error>(1).take_until_deadline(at);
+    assert_eq!(s.deadline(), at);
+    let mut items = 0;
+    while block_on(future::poll_fn(|cx| s.poll_next(cx))).unwrap().is_some() {
+        items += 1;
+    }
+    assert!(items > 0);
+    assert!(Instant::now() >= at);
+    assert!(block_on(future::poll_fn(|cx| s.poll_next(cx))).unwrap().is_none());
+}
+
+#[test]
+fn timeout_total() {
+    let mut s = delayed(vec![20; 100]).timeout(Duration::from_millis(100))
+        .timeout_total(Duration::from_millis(100));
+    let mut items = 0;
+    loop {
+        match block_on(future::poll_fn(|cx| s.poll_next(cx))) {
+            Ok(Some(())) => items += 1,
+            Ok(None) => panic!("stream ended before the deadline"),
+            Err(e) => {
+                assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+                break
+            }
+        }
+    }
+    assert!(items < 100);
+    assert!(block_on(future::poll_fn(|cx| s.poll_next(cx))).unwrap().is_none());
+}
+
+fn items_before_timeout(clock: InterItemClock) -> (usize, bool) {
+    let mut s = delayed(vec![40; 4]).timeout(Duration::from_millis(100))
+        .inter_item_clock(clock);
+    let mut items = 0;
+    loop {
+        match block_on(future::poll_fn(|cx| s.poll_next(cx))) {
+            Ok(Some(())) => items += 1,
+            Ok(None) => return (items, false),
+            Err(e) => {
+                assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+                assert!(block_on(future::poll_fn(|cx| s.poll_next(cx))).unwrap().is_none());
+                return (items, true)
+            }
+        }
+    }
+}
+
+#[test]
+fn timeout_stream_inter_item_clock() {
+    // Items arrive every 40ms, which is within the timeout of each of them
+    // but not of the stream as a whole.
+    assert_eq!(items_before_timeout(InterItemClock::SinceLastItem), (4, false));
+    assert_eq!(items_before_timeout(InterItemClock::SinceSubscribe), (2, true));
+}
+
+#[test]
+fn timeout_stream_since_subscribe_cuts_off_ready_stream() {
+    cuts_off_ready_stream(|s, dur| {
+        s.timeout(dur).inter_item_clock(InterItemClock::SinceSubscribe)
+    });
+}
+
+#[test]
+fn timeout_stream_error_keeps_timer() {
+    let dur = Duration::from_millis(100);
+    let start = Instant::now();
+    let mut first = true;
+    let s = stream::poll_fn(|_cx| -> Poll<Option<()>, io::Error> {
+        if first {
+            first = false;
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "boom"))
+        } else {
+            Ok(Async::Pending)
+        }
+    });
+    let mut s = s.timeout(dur);
+    thread::sleep(Duration::from_millis(60));
+
+    let err = block_on(future::poll_fn(|cx| s.poll_next(cx))).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    let err = block_on(future::poll_fn(|cx| s.poll_next(cx))).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    assert!(start.elapsed() < Duration::from_millis(150));
+}
+
+#[test]
+fn debounce_bursts() {
+    let items = delayed(vec![0, 0, 0, 200, 0, 0, 200])
+        .zip(stream::iter_ok(1..))
+        .map(|((), i)| i)
+        .debounce(Duration::from_millis(50));
+    let items = block_on(items.collect::<Vec<u32>>()).unwrap();
+    assert_eq!(items, vec![3, 6, 7]);
+}
+
+#[test]
+fn debounce_waits_for_quiet() {
+    let start = Instant::now();
+    let dur = Duration::from_millis(50);
+    let items = stream::iter_ok(vec![1, 2])
+        .chain(future::empty::<u32, io::Error>().into_stream())
+        .debounce(dur);
+    let (item, _rest) = block_on(items.next()).map_err(|(e, _)| e).unwrap();
+    assert_eq!(item, Some(2));
+    assert!(start.elapsed() >= dur);
+}
+
+#[test]
+fn chunks_timeout_full_batches() {
+    let items = stream::iter_ok::<_, io::Error>(0..7)
+        .chunks_timeout(3, Duration::from_secs(10));
+    let start = Instant::now();
+    let batches: Vec<_> = block_on(items.collect()).unwrap();
+    assert_eq!(batches, vec![vec![0, 1, 2], vec![3, 4, 5], vec![6]]);
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn chunks_timeout_flushes_partial_batch() {
+    let items = delayed(vec![0, 0, 200, 0])
+        .zip(stream::iter_ok(1..))
+        .map(|((), i)| i)
+        .chunks_timeout(10, Duration::from_millis(50));
+    let batches = block_on(items.collect::<Vec<Vec<u32>>>()).unwrap();
+    assert_eq!(batches, vec![vec![1, 2], vec![3, 4]]);
+}
+
+#[test]
+fn chunks_timeout_flushes_on_end() {
+    let items = delayed(vec![0, 0])
+        .chunks_timeout(10, Duration::from_secs(10));
+    let start = Instant::now();
+    let batches: Vec<_> = block_on(items.collect()).unwrap();
+    assert_eq!(batches, vec![vec![(), ()]]);
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn with_ticks_idle() {
+    let idle = future::empty::<(), io::Error>().into_stream();
+    let start = Instant::now();
+    let events = idle.with_ticks(Duration::from_millis(10)).take(3);
+    let events = block_on(events.collect::<Vec<_>>()).unwrap();
+    assert_eq!(events, vec![Event::Tick, Event::Tick, Event::Tick]);
+    assert!(start.elapsed() >= Duration::from_millis(30));
+}
+
+#[test]
+fn with_ticks_zero_period() {
+    let idle = future::empty::<(), io::Error>().into_stream();
+    let events = idle.with_ticks(Duration::from_secs(0)).take(3);
+    let events = block_on(events.collect::<Vec<_>>()).unwrap();
+    assert_eq!(events, vec![Event::Tick, Event::Tick, Event::Tick]);
+}
+
+#[test]
+fn with_ticks_interleaved() {
+    let items = delayed(vec![0, 50, 50])
+        .zip(stream::iter_ok(1..))
+        .map(|((), i)| i)
+        .with_ticks(Duration::from_millis(20));
+    let events = block_on(items.collect::<Vec<_>>()).unwrap();
+    let items = events.iter()
+        .filter_map(|e| match *e { Event::Item(i) => Some(i), Event::Tick => None })
+        .collect::<Vec<_>>();
+    assert_eq!(items, [1, 2, 3]);
+
+    // The first item is ready right away, and every gap between items
+    // spans at least one tick.
+    assert_eq!(events[0], Event::Item(1));
+    let positions = events.iter()
+        .enumerate()
+        .filter(|&(_, e)| *e != Event::Tick)
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    assert!(positions.windows(2).all(|w| w[1] - w[0] >= 2), "{:?}", events);
+}
+
+#[test]
+fn sample_cadence() {
+    let start = Instant::now();
+    let dur = Duration::from_millis(50);
+    let items = delayed(vec![10; 20])
+        .zip(stream::iter_ok(1..))
+        .map(|((), i)| i)
+        .sample(dur)
+        .map(move |i| (i, start.elapsed()));
+    let items = block_on(items.collect::<Vec<_>>()).unwrap();
+
+    // Twenty items arrive over at least 200ms, so roughly one sample per 50ms
+    // tick is emitted and the last item always makes it out.
+    assert!(items.len() >= 3 && items.len() <= 6, "{:?}", items);
+    assert_eq!(items.last().unwrap().0, 20);
+    for (n, &(i, elapsed)) in items.iter().enumerate() {
+        assert!(elapsed >= dur * (n as u32 + 1), "{:?}", items);
+        if n > 0 {
+            assert!(i > items[n - 1].0);
+        }
+    }
+}
+
+#[test]
+fn sample_zero_period() {
+    let items = delayed(vec![1; 5])
+        .zip(stream::iter_ok(1..))
+        .map(|((), i)| i)
+        .sample(Duration::from_secs(0));
+    let items = block_on(items.collect::<Vec<_>>()).unwrap();
+    assert_eq!(items.last(), Some(&5));
+}
+
+#[test]
+fn min_interval_spaces_items() {
+    let start = Instant::now();
+    let dur = Duration::from_millis(50);
+    let items = stream::iter_ok::<_, io::Error>(1..6)
+        .min_interval(dur)
+        .map(|i| (i, Instant::now()));
+    let items = block_on(items.collect::<Vec<_>>()).unwrap();
+
+    assert_eq!(items.iter().map(|e| e.0).collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+    assert!(items[0].1 - start < dur);
+    for pair in items.windows(2) {
+        assert!(pair[1].1 - pair[0].1 >= dur);
+    }
+}
+
+/// A sink which only becomes ready once `ready` resolves, collecting the items
+/// sent to it.
+struct SlowSink<F> {
+    ready: F,
+    items: Vec<u32>,
+}
+
+impl<F: Future<Item = (), Error = io::Error>> Sink for SlowSink<F> {
+    type SinkItem = u32;
+    type SinkError = io::Error;
+
+    fn poll_ready(&mut self, cx: &mut task::Context) -> Poll<(), io::Error> {
+        self.ready.poll(cx)
+    }
+
+    fn start_send(&mut self, item: u32) -> Result<(), io::Error> {
+        self.items.push(item);
+        Ok(())
+    }
+
+    fn poll_flush(&mut self, _cx: &mut task::Context) -> Poll<(), io::Error> {
+        Ok(Async::Ready(()))
+    }
+
+    fn poll_close(&mut self, _cx: &mut task::Context) -> Poll<(), io::Error> {
+        Ok(Async::Ready(()))
+    }
+}
+
+#[test]
+fn send_timeout_blocked_sink() {
+    let mut sink = SlowSink { ready: future::empty(), items: Vec::new() };
+    let mut send = (&mut sink).send_timeout(1, Duration::from_millis(10));
+    match block_on(future::poll_fn(|cx| send.poll(cx))) {
+        Err(e) => assert_eq!(e.kind(), io::ErrorKind::TimedOut),
+        Ok(_) => panic!("expected timeout"),
+    }
+    let (_, item) = send.into_inner();
+    assert_eq!(item, Some(1));
+    assert!(sink.items.is_empty());
+}
+
+#[test]
+fn send_timeout_slow_sink() {
+    let sink = SlowSink { ready: Delay::new(Duration::from_millis(10)), items: Vec::new() };
+    let sink = block_on(sink.send_timeout(1, Duration::from_secs(10))).unwrap();
+    assert_eq!(sink.items, vec![1]);
+}
+
+fn assert_unpin<T: Unpin>() {}
+
+#[test]
+fn unpin() {
+    assert_unpin::<futures_timer::ext::Timeout<future::Empty<(), io::Error>>>();
+    assert_unpin::<futures_timer::ext::TimeoutStream<stream::Empty<(), io::Error>>>();
+}
+
+#[test]
+fn timeout_reset_extends_deadline() {
+    let start = Instant::now();
+    let dur = Duration::from_millis(40);
+    let mut t = Delay::new(Duration::from_millis(100)).timeout(dur);
+    let mut progress = futures_timer::Interval::new(Duration::from_millis(10));
+    block_on(future::poll_fn(|cx| {
+        while let Async::Ready(_) = progress.poll_next(cx)? {
+            t.reset(dur);
+        }
+        t.poll(cx)
+    })).unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(100));
+}
+
+#[test]
+fn timeout_accessors() {
+    let dur = Duration::from_millis(10);
+    let before = Instant::now();
+    let t = future::empty::<(), io::Error>().timeout(dur);
+    assert!(t.deadline() >= before + dur);
+    assert!(t.remaining() <= dur);
+    assert!(!t.is_elapsed());
+
+    thread::sleep(dur);
+    assert!(t.is_elapsed());
+    assert_eq!(t.remaining(), Duration::from_millis(0));
+}
+
+// Each of these stages a result which is ready on the very same poll that
+// observes an elapsed deadline, in which case the result must win.
+
+#[test]
+fn ready_future_beats_elapsed_timeout() {
+    let dur = Duration::from_millis(10);
+    let t = future::ok::<u32, io::Error>(1).timeout(dur);
+    let r = future::ok::<u32, ()>(2).timeout_result(dur);
+    let w = future::ok::<u32, io::Error>(3)
+        .timeout_with(dur, || io::Error::new(io::ErrorKind::BrokenPipe, "late"));
+    thread::sleep(dur * 2);
+    assert!(t.is_elapsed());
+    assert_eq!(block_on(t).unwrap(), 1);
+    assert_eq!(block_on(r), Ok(Ok(2)));
+    assert_eq!(block_on(w).unwrap(), 3);
+
+    let err = future::err::<(), io::Error>(io::Error::new(io::ErrorKind::BrokenPipe, "inner"));
+    let err = block_on(err.timeout(Duration::from_millis(0))).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+}
+
+#[test]
+fn ready_item_beats_elapsed_stream_timeout() {
+    let dur = Duration::from_millis(10);
+    let s = stream::iter_ok::<_, io::Error>(vec![1, 2, 3]).timeout(dur);
+    thread::sleep(dur * 2);
+    assert_eq!(block_on(s.collect::<Vec<_>>()).unwrap(), vec![1, 2, 3]);
+}
+
+// Puts `deadline` around a stream which is always ready, with a duration of
+// 20ms, and checks that it's cut off once that has passed. The stream is
+// returned once it has ended for further checks.
+fn cuts_off_ready_stream<S, F>(deadline: F) -> S
+    where F: FnOnce(stream::Repeat<u32, io::Error>, Duration) -> S,
+          S: Stream<Item = u32, Error = io::Error>,
+{
+    let mut s = deadline(stream::repeat(1), Duration::from_millis(20));
+    let start = Instant::now();
+    let mut items = 0;
+    let err = loop {
+        match block_on(future::poll_fn(|cx| s.poll_next(cx))) {
+            Ok(item) => {
+                assert_eq!(item, Some(1));
+                items += 1;
+                thread::sleep(Duration::from_millis(2));
+            }
+            Err(e) => break e,
+        }
+    };
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    assert!(items < 50);
+    assert!(start.elapsed() < Duration::from_millis(500));
+    assert!(block_on(future::poll_fn(|cx| s.poll_next(cx))).unwrap().is_none());
+    s
+}
+
+// A stream which ends right away, and mustn't be polled after that.
+struct Finished(bool);
+
+impl Stream for Finished {
+    type Item = ();
+    type Error = io::Error;
+
+    fn poll_next(&mut self, _cx: &mut task::Context) -> Poll<Option<()>, io::Error> {
+        assert!(!self.0, "finished stream polled again");
+        self.0 = true;
+        Ok(Async::Ready(None))
+    }
+}
+
+// Puts `deadline` around a stream which ends right away, and checks that it
+// isn't polled again once it has. The stream is returned for further checks.
+fn releases_finished_stream<S, F>(deadline: F) -> S
+    where F: FnOnce(Finished, Duration) -> S,
+          S: Stream<Item = (), Error = io::Error>,
+{
+    let mut s = deadline(Finished(false), Duration::from_secs(10));
+    assert!(block_on(future::poll_fn(|cx| s.poll_next(cx))).unwrap().is_none());
+    assert!(block_on(future::poll_fn(|cx| s.poll_next(cx))).unwrap().is_none());
+    s
+}
+
+#[test]
+fn timeout_total_cuts_off_ready_stream() {
+    cuts_off_ready_stream(|s, dur| s.timeout_total(dur));
+
+    // A deadline which has passed wins over ready items.
+    let dur = Duration::from_millis(20);
+    let mut total = stream::iter_ok::<_, io::Error>(vec![1, 2, 3]).timeout_total(dur);
+    thread::sleep(dur * 2);
+    assert!(block_on(future::poll_fn(|cx| total.poll_next(cx))).is_err());
+}
+
+#[test]
+fn timeout_total_releases_finished_stream() {
+    releases_finished_stream(|s, dur| s.timeout_total(dur));
+}
+
+#[test]
+fn deadline_per_window_manual_reset() {
+    let window = Duration::from_millis(100);
+
+    // Items alone don't extend the deadline.
+    let items = delayed(vec![60, 60, 60]).deadline_per_window(window);
+    let (item, items) = block_on(items.next()).map_err(|(e, _)| e).unwrap();
+    assert_eq!(item, Some(()));
+    let (err, items) = block_on(items.next()).map(|_| ()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    let (item, _) = block_on(items.next()).map_err(|(e, _)| e).unwrap();
+    assert_eq!(item, None);
+
+    // Resetting after every item keeps the stream alive.
+    let mut items = delayed(vec![60, 60, 60]).deadline_per_window(window);
+    let mut count = 0;
+    while let (Some(()), rest) = block_on(items.next()).map_err(|(e, _)| e).unwrap() {
+        count += 1;
+        items = rest;
+        let before = items.deadline();
+        items.reset_deadline();
+        assert!(items.deadline() > before);
+    }
+    assert_eq!(count, 3);
+}
+
+#[test]
+fn deadline_per_window_cuts_off_ready_stream() {
+    let mut s = cuts_off_ready_stream(|s, dur| s.deadline_per_window(dur));
+
+    // Resetting revives it until the next window passes.
+    s.reset_deadline();
+    assert_eq!(block_on(future::poll_fn(|cx| s.poll_next(cx))).unwrap(), Some(1));
+}
+
+#[test]
+fn deadline_per_window_releases_finished_stream() {
+    let mut s = releases_finished_stream(|s, dur| s.deadline_per_window(dur));
+    s.reset_deadline();
+    assert!(block_on(future::poll_fn(|cx| s.poll_next(cx))).unwrap().is_none());
+}
+
+#[test]
+fn timeout_fired_hook() {
+    // Other tests time out concurrently, so only look for the durations used
+    // here.
+    let fired = Arc::new(Mutex::new(Vec::new()));
+    let fired2 = fired.clone();
+    on_timeout_fired(move |kind, dur| {
+        if dur == Duration::from_millis(17) || dur == Duration::from_millis(19) {
+            fired2.lock().unwrap().push((kind, dur));
+        }
+    }).unwrap();
+    assert!(on_timeout_fired(|_, _| {}).is_err());
+
+    let f = future::empty::<(), io::Error>();
+    assert!(block_on(f.timeout(Duration::from_millis(17))).is_err());
+    assert_eq!(*fired.lock().unwrap(),
+               vec![(TimeoutKind::Future, Duration::from_millis(17))]);
+
+    let s = stream::repeat::<(), io::Error>(()).and_then(|()| future::empty::<(), io::Error>());
+    assert!(block_on(s.timeout(Duration::from_millis(19)).next()).is_err());
+    assert_eq!(fired.lock().unwrap()[1..],
+               [(TimeoutKind::Stream, Duration::from_millis(19))]);
+}
@@ -0,0 +1,84 @@
+//! Tests which install their own timer as the global fallback, and hence get
+//! a test binary of their own.
+
+extern crate futures;
+extern crate futures_timer;
+
+use std::io;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+
+use futures::stream;
+use futures::prelude::*;
+use futures::executor::block_on;
+use futures_timer::{StreamExt as TimerStreamExt, Timer, TimerHandle, TimerThread};
+
+// Returns a handle to the fallback timer, installing it on first use, along
+// with a guard which keeps other tests from adding timers while it's held.
+fn fallback() -> (MutexGuard<'static, ()>, TimerHandle) {
+    static SERIAL: Mutex<()> = Mutex::new(());
+    static THREAD: OnceLock<TimerThread> = OnceLock::new();
+    let serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let thread = THREAD.get_or_init(|| {
+        let thread = Timer::builder().spawn().unwrap();
+        thread.handle().set_as_global_fallback().unwrap();
+        thread
+    });
+    (serial, thread.handle())
+}
+
+#[test]
+fn timeout_stream_releases_timer_on_end() {
+    let (_serial, handle) = fallback();
+
+    let mut items = stream::iter_ok::<_, io::Error>(0..3).timeout(Duration::from_secs(10));
+    let mut seen = Vec::new();
+    loop {
+        let (item, rest) = block_on(items.next()).map_err(|(e, _)| e).unwrap();
+        items = rest;
+        match item {
+            Some(item) => seen.push(item),
+            None => break,
+        }
+        assert_eq!(handle.active_timers(), 1);
+    }
+    assert_eq!(seen, [0, 1, 2]);
+
+    // The stream is still around, but its timer is gone.
+    assert_eq!(handle.active_timers(), 0);
+    drop(items);
+}
+
+#[test]
+fn chunks_timeout_idle_without_timer() {
+    let (_serial, handle) = fallback();
+
+    // The timer of a batch is only armed once its first item arrives.
+    let chunks = stream::empty::<u32, io::Error>().chunks_timeout(2, Duration::from_secs(10));
+    assert_eq!(handle.active_timers(), 0);
+    drop(chunks);
+}
+
+#[test]
+fn min_interval_idle_without_timer() {
+    let (_serial, handle) = fallback();
+
+    // Items are only ever held back on a timer armed for the purpose.
+    let spaced = stream::iter_ok::<_, io::Error>(0..2).min_interval(Duration::from_secs(10));
+    assert_eq!(handle.active_timers(), 0);
+    let (item, rest) = block_on(spaced.next()).map_err(|(e, _)| e).unwrap();
+    assert_eq!(item, Some(0));
+    assert_eq!(handle.active_timers(), 0);
+    drop(rest);
+}
+
+#[test]
+fn debounce_idle_without_timer() {
+    let (_serial, handle) = fallback();
+
+    // The quiet period is only timed once an item has arrived.
+    let debounced = stream::empty::<u32, io::Error>().debounce(Duration::from_secs(10));
+    assert_eq!(handle.active_timers(), 0);
+    drop(debounced);
+}
+
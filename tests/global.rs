@@ -0,0 +1,114 @@
+extern crate futures;
+extern crate futures_timer;
+
+#[cfg(target_os = "linux")]
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Instant, Duration};
+
+use futures::executor::block_on;
+use futures_timer::{Clock, Delay, Timer};
+
+#[test]
+fn shutdown_and_respawn() {
+    let dur = Duration::from_millis(10);
+    block_on(Delay::new(dur)).unwrap();
+
+    // A pending delay keeps the helper thread alive until it fires.
+    let pending = Delay::new(dur);
+    futures_timer::shutdown();
+    assert!(pending.is_elapsed());
+    block_on(pending).unwrap();
+
+    let i = Instant::now();
+    block_on(Delay::new(dur)).unwrap();
+    assert!(i.elapsed() >= dur);
+    futures_timer::shutdown();
+}
+
+#[test]
+fn shutdown_timeout_with_pending_delay() {
+    // Shutting down while holding a pending delay gives up rather than
+    // waiting on the delay forever.
+    let pending = Delay::new(Duration::from_secs(3600));
+    block_on(Delay::new(Duration::from_millis(1))).unwrap();
+    let timeout = Duration::from_millis(50);
+    let i = Instant::now();
+    assert!(!futures_timer::shutdown_timeout(timeout));
+    assert!(i.elapsed() >= timeout);
+
+    // The pending delay lets the old helper thread exit once it's dropped,
+    // while new delays get a fresh one.
+    drop(pending);
+    block_on(Delay::new(Duration::from_millis(10))).unwrap();
+}
+
+// Records the name of the thread reading the time, which for a timer running
+// on a helper thread includes that thread.
+struct NameClock(Arc<Mutex<Vec<Option<String>>>>);
+
+impl Clock for NameClock {
+    fn now(&self) -> Instant {
+        let name = thread::current().name().map(|s| s.to_string());
+        self.0.lock().unwrap().push(name);
+        Instant::now()
+    }
+}
+
+#[test]
+fn custom_thread_name() {
+    let names = Arc::new(Mutex::new(Vec::new()));
+    let timer = Timer::builder()
+        .clock(NameClock(names.clone()))
+        .thread_name("my-timers")
+        .spawn()
+        .unwrap();
+    let handle = timer.handle();
+    block_on(Delay::new_handle(handle.now() + Duration::from_millis(10), handle)).unwrap();
+    drop(timer);
+    assert!(names.lock().unwrap().iter().any(|n| n.as_ref().map(|s| &s[..]) == Some("my-timers")));
+}
+
+// Stands still at the instant it was created, counting how often it's read.
+struct FrozenClock(Instant, Arc<AtomicUsize>);
+
+impl Clock for FrozenClock {
+    fn now(&self) -> Instant {
+        self.1.fetch_add(1, Ordering::SeqCst);
+        self.0
+    }
+}
+
+#[test]
+fn frozen_clock_sleeps() {
+    let reads = Arc::new(AtomicUsize::new(0));
+    let timer = Timer::builder()
+        .clock(FrozenClock(Instant::now(), reads.clone()))
+        .spawn()
+        .unwrap();
+    let handle = timer.handle();
+    let delay = Delay::new_handle(handle.now() + Duration::from_millis(10), handle);
+
+    // The deadline stays 10ms away on the timer's clock once it has passed on
+    // the system clock, so the thread keeps sleeping for that long rather than
+    // checking over and over.
+    thread::sleep(Duration::from_millis(100));
+    assert!(reads.load(Ordering::SeqCst) < 100, "{:?}", reads);
+    drop(delay);
+    drop(timer);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn global_thread_name() {
+    // Keep the helper thread alive, and make sure it's running, while we look
+    // for it.
+    let _delay = Delay::new(Duration::from_secs(10));
+    block_on(Delay::new(Duration::from_millis(1))).unwrap();
+    let names = fs::read_dir("/proc/self/task").unwrap().filter_map(|task| {
+        fs::read_to_string(task.unwrap().path().join("comm")).ok()
+    }).collect::<Vec<_>>();
+    assert!(names.iter().any(|n| n.trim() == "futures-timer"), "{:?}", names);
+}
@@ -1,11 +1,15 @@
 extern crate futures;
 extern crate futures_timer;
 
+use std::io;
+use std::thread;
 use std::time::{Instant, Duration};
 
+use futures::future;
 use futures::prelude::*;
 use futures::executor::block_on;
-use futures_timer::Interval;
+use futures_timer::{BackoffInterval, Interval, ManualTimer, MissedTickBehavior, countdown};
+use futures_timer::ScheduledInterval;
 
 #[test]
 fn single() {
@@ -25,3 +29,317 @@ fn two_times() {
     assert!(start.elapsed() >= dur*2);
     assert_eq!(result, vec![(), ()]);
 }
+
+fn first_ticks<F: FnOnce() -> Interval>(new_interval: F) -> (Duration, Duration) {
+    let start = Instant::now();
+    let interval = new_interval();
+    let (_, interval) = block_on(interval.next()).map_err(|e| e.0).unwrap();
+    let first = start.elapsed();
+    block_on(interval.next()).map_err(|e| e.0).unwrap();
+    (first, start.elapsed())
+}
+
+#[test]
+fn first_tick() {
+    let dur = Duration::from_millis(100);
+    let (first, second) = first_ticks(|| Interval::new(dur));
+    assert!(first >= dur);
+    assert!(second >= dur * 2);
+
+    let (first, second) = first_ticks(|| Interval::new_immediate(dur));
+    assert!(first < dur / 2);
+    assert!(second >= dur);
+    assert!(second < dur * 2);
+}
+
+#[test]
+fn scheduled_instants() {
+    let mut timer = ManualTimer::new();
+    let period = Duration::from_millis(10);
+    let start = timer.now();
+    let mut interval = Interval::new_handle(start + period, period, timer.handle())
+        .into_scheduled();
+
+    // Ticks serviced late still carry the instant they were scheduled for.
+    let mut ticks = Vec::new();
+    for _ in 0..3 {
+        timer.advance(Duration::from_millis(13));
+        while let Async::Ready(at) = block_on(future::poll_fn(|cx| {
+            Ok::<_, io::Error>(Async::Ready(interval.poll_next(cx)?))
+        })).unwrap() {
+            ticks.push(at.unwrap());
+        }
+    }
+    assert_eq!(ticks, (1..4).map(|i| start + period * i).collect::<Vec<_>>());
+    assert!(ticks.windows(2).all(|w| w[1] - w[0] == period));
+
+    let items = block_on(ScheduledInterval::new(period).take(2).collect::<Vec<_>>()).unwrap();
+    assert_eq!(items[1] - items[0], period);
+}
+
+#[test]
+fn tick_count() {
+    let dur = Duration::from_millis(20);
+    let start = Instant::now();
+    let interval = Interval::new(dur);
+    let result = block_on(interval.take(10).collect::<Vec<_>>()).unwrap();
+    let elapsed = start.elapsed();
+    assert_eq!(result.len(), 10);
+    assert!(elapsed >= dur * 10);
+    assert!(elapsed < dur * 10 + Duration::from_secs(1));
+}
+
+fn two_ticks_after_sleep(behavior: MissedTickBehavior) -> Duration {
+    let dur = Duration::from_millis(100);
+    let start = Instant::now();
+    let mut interval = Interval::new(dur);
+    interval.set_missed_tick_behavior(behavior);
+    thread::sleep(Duration::from_millis(250));
+    block_on(interval.take(2).collect::<Vec<_>>()).unwrap();
+    start.elapsed()
+}
+
+#[test]
+fn missed_tick_burst() {
+    let elapsed = two_ticks_after_sleep(MissedTickBehavior::Burst);
+    assert!(elapsed < Duration::from_millis(290), "{:?}", elapsed);
+}
+
+#[test]
+fn missed_tick_delay() {
+    let elapsed = two_ticks_after_sleep(MissedTickBehavior::Delay);
+    assert!(elapsed >= Duration::from_millis(350), "{:?}", elapsed);
+}
+
+#[test]
+fn missed_tick_skip() {
+    let elapsed = two_ticks_after_sleep(MissedTickBehavior::Skip);
+    assert!(elapsed >= Duration::from_millis(300), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(345), "{:?}", elapsed);
+}
+
+#[test]
+fn reset() {
+    let dur = Duration::from_millis(100);
+    let mut interval = Interval::new(dur);
+    thread::sleep(Duration::from_millis(60));
+    let start = Instant::now();
+    interval.reset();
+    block_on(interval.take(1).collect::<Vec<_>>()).unwrap();
+    assert!(start.elapsed() >= dur);
+}
+
+#[test]
+fn reset_at() {
+    let dur = Duration::from_secs(100);
+    let mut interval = Interval::new(dur);
+    let start = Instant::now();
+    interval.reset_at(start + Duration::from_millis(10));
+    block_on(interval.take(1).collect::<Vec<_>>()).unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(10));
+    assert!(start.elapsed() < dur);
+}
+
+#[test]
+fn backoff_progression() {
+    let ms = Duration::from_millis;
+    let mut backoff = BackoffInterval::new(ms(10), 2.0, ms(40));
+    let mut expected = Duration::from_millis(0);
+    let start = Instant::now();
+    for &next in [ms(20), ms(40), ms(40)].iter() {
+        expected += backoff.current();
+        backoff = block_on(backoff.next()).map_err(|(e, _)| e).unwrap().1;
+        assert!(start.elapsed() >= expected);
+        assert_eq!(backoff.current(), next);
+    }
+}
+
+#[test]
+fn backoff_reset() {
+    let ms = Duration::from_millis;
+    let mut backoff = BackoffInterval::new(ms(10), 3.0, ms(1000));
+    backoff = block_on(backoff.next()).map_err(|(e, _)| e).unwrap().1;
+    assert_eq!(backoff.current(), ms(30));
+
+    backoff.reset();
+    assert_eq!(backoff.current(), ms(10));
+    let start = Instant::now();
+    backoff = block_on(backoff.next()).map_err(|(e, _)| e).unwrap().1;
+    assert!(start.elapsed() >= ms(10));
+    assert!(start.elapsed() < ms(30));
+    assert_eq!(backoff.current(), ms(30));
+}
+
+#[test]
+fn countdown_values_and_duration() {
+    let period = Duration::from_millis(10);
+    let start = Instant::now();
+    let items = block_on(countdown(5, period).collect::<Vec<_>>()).unwrap();
+    let elapsed = start.elapsed();
+    assert_eq!(items, vec![5, 4, 3, 2, 1]);
+    assert!(elapsed >= period * 5);
+    assert!(elapsed < period * 50);
+
+    let items = block_on(countdown(0, period).collect::<Vec<_>>()).unwrap();
+    assert!(items.is_empty());
+}
+
+#[test]
+fn jitter_spread() {
+    let period = Duration::from_millis(100);
+    let ticks = |seed| {
+        let mut timer = ManualTimer::new();
+        let mut interval = Interval::new_handle(timer.now() + period, period, timer.handle());
+        interval.set_jitter(0.2);
+        interval.set_jitter_seed(seed);
+        let mut ticks = Vec::new();
+        let mut last = timer.now();
+        while ticks.len() < 50 {
+            timer.advance(Duration::from_millis(1));
+            let ready = block_on(future::poll_fn(|cx| {
+                Ok::<_, io::Error>(Async::Ready(interval.poll_next(cx)?.is_ready()))
+            })).unwrap();
+            if ready {
+                ticks.push(timer.now() - last);
+                last = timer.now();
+            }
+        }
+        ticks
+    };
+
+    let spacings = ticks(7);
+    // The first tick is unjittered, the others are spread within 20% of the
+    // period, give or take the millisecond the timer is advanced by.
+    assert_eq!(spacings[0], period);
+    assert!(spacings[1..].iter().all(|&d| {
+        d >= Duration::from_millis(80) && d <= Duration::from_millis(121)
+    }));
+    assert!(spacings[1..].iter().any(|&d| d < Duration::from_millis(95)));
+    assert!(spacings[1..].iter().any(|&d| d > Duration::from_millis(105)));
+    assert_eq!(ticks(7), spacings);
+}
+
+#[test]
+fn missed_ticks() {
+    let period = Duration::from_millis(100);
+    let observe = |behavior| {
+        let mut timer = ManualTimer::new();
+        let mut interval = Interval::new_handle(timer.now() + period, period, timer.handle());
+        interval.set_missed_tick_behavior(behavior);
+        let mut poll = |timer: &mut ManualTimer, dur| {
+            timer.advance(dur);
+            block_on(future::poll_fn(|cx| {
+                Ok::<_, io::Error>(Async::Ready(interval.poll_next(cx)?.is_ready()))
+            })).unwrap()
+        };
+
+        // Observe the first tick 250ms late, then the following ones on time
+        // for a while.
+        assert!(poll(&mut timer, Duration::from_millis(350)));
+        let mut ticks = 1;
+        for _ in 0..199 {
+            if poll(&mut timer, Duration::from_millis(1)) {
+                ticks += 1;
+            }
+        }
+        (ticks, interval.missed_ticks())
+    };
+
+    assert_eq!(observe(MissedTickBehavior::Burst), (5, 0));
+    assert_eq!(observe(MissedTickBehavior::Skip), (3, 2));
+    assert_eq!(observe(MissedTickBehavior::Delay), (2, 2));
+}
+
+#[test]
+fn huge_period() {
+    let period = Duration::from_secs(u64::MAX);
+    for &behavior in &[MissedTickBehavior::Burst,
+                       MissedTickBehavior::Delay,
+                       MissedTickBehavior::Skip] {
+        let mut timer = ManualTimer::new();
+        let mut interval = Interval::new_handle(timer.now(), period, timer.handle());
+        interval.set_missed_tick_behavior(behavior);
+        let mut poll = |timer: &mut ManualTimer| {
+            timer.advance(Duration::from_secs(1));
+            block_on(future::poll_fn(|cx| {
+                Ok::<_, io::Error>(Async::Ready(interval.poll_next(cx)?.is_ready()))
+            })).unwrap()
+        };
+        assert!(poll(&mut timer));
+        assert!(!poll(&mut timer));
+    }
+
+    let mut interval = Interval::new_immediate(period);
+    block_on(future::poll_fn(|cx| interval.poll_tick(cx))).unwrap();
+}
+
+#[test]
+fn poll_tick() {
+    let dur = Duration::from_millis(10);
+    let start = Instant::now();
+    let mut interval = Interval::new(dur);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut ticks = 0;
+    block_on(future::poll_fn(|cx| {
+        while ticks < 3 {
+            if interval.poll_tick(cx)?.is_pending() {
+                return Ok(Async::Pending)
+            }
+            ticks += 1;
+        }
+        Ok::<_, io::Error>(Async::Ready(()))
+    })).unwrap();
+    assert!(start.elapsed() >= dur * 3);
+}
+
+#[test]
+fn set_period() {
+    let mut timer = ManualTimer::new();
+    let start = timer.now();
+    let period = Duration::from_millis(100);
+    let mut deferred = Interval::new_handle(start + period, period, timer.handle());
+    let mut immediate = Interval::new_handle(start + period, period, timer.handle());
+    let poll = |timer: &mut ManualTimer, interval: &mut Interval, dur| {
+        timer.advance(dur);
+        block_on(future::poll_fn(|cx| {
+            Ok::<_, io::Error>(Async::Ready(interval.poll_next(cx)?.is_ready()))
+        })).unwrap()
+    };
+
+    // Speeding up after 40ms leaves the pending tick at 100ms when deferred,
+    // but moves it to 50ms when immediate.
+    timer.advance(Duration::from_millis(40));
+    deferred.set_period(Duration::from_millis(10));
+    immediate.set_period_immediate(Duration::from_millis(10));
+    assert_eq!(deferred.period(), Duration::from_millis(10));
+    assert!(poll(&mut timer, &mut immediate, Duration::from_millis(10)));
+    assert!(!poll(&mut timer, &mut deferred, Duration::from_millis(0)));
+    assert!(poll(&mut timer, &mut deferred, Duration::from_millis(50)));
+    assert!(!poll(&mut timer, &mut deferred, Duration::from_millis(9)));
+    assert!(poll(&mut timer, &mut deferred, Duration::from_millis(1)));
+    assert_eq!(timer.now() - start, Duration::from_millis(110));
+}
+
+#[test]
+fn fixed_rate() {
+    let mut timer = ManualTimer::new();
+    let start = timer.now();
+    let period = Duration::from_millis(10);
+    let mut interval = Interval::new_handle(start + period, period, timer.handle());
+    let mut poll = || {
+        block_on(future::poll_fn(|cx| {
+            Ok::<_, io::Error>(Async::Ready(interval.poll_next(cx)?.is_ready()))
+        })).unwrap()
+    };
+
+    // Every tick is observed a little late and then takes a while to handle,
+    // neither of which pushes back the ticks after it.
+    for _ in 0..100 {
+        timer.advance(Duration::from_millis(1));
+        while !poll() {
+            timer.advance(Duration::from_millis(1));
+        }
+        timer.advance(Duration::from_millis(3));
+    }
+    assert_eq!(timer.now() - start, period * 100 + Duration::from_millis(3));
+}
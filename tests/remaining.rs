@@ -0,0 +1,27 @@
+//! Tests which check that the global timer's helper thread isn't spawned, and
+//! hence get a test binary of their own.
+
+extern crate futures_timer;
+
+#[cfg(target_os = "linux")]
+use std::fs;
+use std::time::{Duration, Instant};
+
+use futures_timer::remaining_timeout;
+
+// Counts the threads of this process, which the helper thread would add to as
+// soon as it's spawned, but before it has got around to naming itself.
+#[cfg(target_os = "linux")]
+fn threads() -> usize {
+    fs::read_dir("/proc/self/task").unwrap().count()
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn remaining_timeout_spawns_no_thread() {
+    let before = threads();
+    let parent_deadline = Instant::now() + Duration::from_secs(1);
+    assert!(remaining_timeout(parent_deadline) <= Duration::from_secs(1));
+    assert_eq!(remaining_timeout(Instant::now()), Duration::from_secs(0));
+    assert_eq!(threads(), before);
+}
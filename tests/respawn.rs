@@ -0,0 +1,52 @@
+//! Tests which kill the global timer's helper thread, and hence get a test
+//! binary of their own.
+
+extern crate futures;
+extern crate futures_timer;
+
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use futures::prelude::*;
+use futures::executor::block_on;
+use futures::task;
+use futures_timer::Delay;
+
+// Panics when woken by the global timer's thread, killing it.
+struct Doomed;
+
+impl task::Wake for Doomed {
+    fn wake(_: &Arc<Self>) {
+        if thread::current().name() == Some("futures-timer") {
+            panic!("simulated failure of the timer thread")
+        }
+    }
+}
+
+#[test]
+fn respawn_after_panic() {
+    let witness = Delay::new(Duration::from_secs(10));
+    let mut doomed = Delay::new(Duration::from_millis(1));
+    let mut map = task::LocalMap::new();
+    let waker = task::Waker::from(Arc::new(Doomed));
+    let mut cx = task::Context::without_spawn(&mut map, &waker);
+    assert!(doomed.poll(&mut cx).unwrap().is_pending());
+
+    // Firing the doomed delay takes its timer down, invalidating the other
+    // delays registered with it.
+    assert!(block_on(witness).is_err());
+
+    // A dead global timer is replaced by a fresh one on demand. Delays
+    // created while the dying thread is still unwinding fail along with it,
+    // so give it some time to finish.
+    let respawned = (0..100).any(|_| {
+        if block_on(Delay::new(Duration::from_millis(10))).is_ok() {
+            return true
+        }
+        thread::sleep(Duration::from_millis(10));
+        false
+    });
+    assert!(respawned);
+    futures_timer::shutdown();
+}
@@ -0,0 +1,51 @@
+extern crate futures;
+extern crate futures_timer;
+
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use futures::future;
+use futures::executor::block_on;
+use futures_timer::{retry, RetryPolicy};
+
+#[test]
+fn fails_twice_then_succeeds() {
+    let calls = Cell::new(0);
+    let base = Duration::from_millis(10);
+    let start = Instant::now();
+    let res = block_on(retry(|| {
+        calls.set(calls.get() + 1);
+        if calls.get() < 3 {
+            future::err(calls.get())
+        } else {
+            future::ok("done")
+        }
+    }, RetryPolicy::new(base)));
+    assert_eq!(res, Ok("done"));
+    assert_eq!(calls.get(), 3);
+    assert!(start.elapsed() >= base + base * 2);
+}
+
+#[test]
+fn gives_up_with_last_error() {
+    let calls = Cell::new(0);
+    let policy = RetryPolicy::new(Duration::from_millis(1)).max_attempts(4);
+    let res = block_on(retry(|| {
+        calls.set(calls.get() + 1);
+        future::err::<(), _>(calls.get())
+    }, policy));
+    assert_eq!(res, Err(4));
+    assert_eq!(calls.get(), 4);
+}
+
+#[test]
+fn delays() {
+    let policy = RetryPolicy::new(Duration::from_millis(100))
+        .multiplier(3.0)
+        .max_delay(Duration::from_secs(1));
+    assert_eq!(policy.delay_for(1), Duration::from_millis(100));
+    assert_eq!(policy.delay_for(2), Duration::from_millis(300));
+    assert_eq!(policy.delay_for(3), Duration::from_millis(900));
+    assert_eq!(policy.delay_for(4), Duration::from_secs(1));
+    assert_eq!(policy.delay_for(1000), Duration::from_secs(1));
+}
@@ -1,12 +1,15 @@
 extern crate futures;
 extern crate futures_timer;
 
+use std::sync::Arc;
+use std::thread;
 use std::time::{Instant, Duration};
 
 use futures::future;
 use futures::prelude::*;
 use futures::executor::block_on;
-use futures_timer::{Timer, Delay};
+use futures::task::{self, LocalMap, Wake, Waker};
+use futures_timer::{Timer, TimerBuilder, TimerTask, Delay, ManualTimer, TestClock};
 
 fn far_future() -> Instant {
     Instant::now() + Duration::new(5000, 0)
@@ -21,6 +24,13 @@ fn works() {
     assert!(i.elapsed() > dur);
 }
 
+#[test]
+fn into_stream() {
+    let stream = Delay::new(Duration::from_millis(10)).into_stream();
+    let items: Vec<_> = block_on(stream.collect()).unwrap();
+    assert_eq!(items, vec![()]);
+}
+
 #[test]
 fn error_after_inert() {
     let t = Timer::new();
@@ -67,3 +77,183 @@ fn drop_timer_wakes() {
         Ok(Async::Pending)
     })).is_err());
 }
+
+#[test]
+fn reset_at_earlier() {
+    let mut d = Delay::new_at(far_future());
+    assert!(block_on(future::poll_fn(|cx| {
+        Ok::<_, ()>(Async::Ready(d.poll(cx).unwrap().is_pending()))
+    })).unwrap());
+
+    let i = Instant::now();
+    let dur = Duration::from_millis(100);
+    d.reset_at(i + dur);
+    block_on(future::poll_fn(|cx| d.poll(cx))).unwrap();
+    assert!(i.elapsed() >= dur);
+}
+
+#[test]
+fn custom_timer() {
+    let mut timer = Timer::new();
+    let dur = Duration::from_millis(10);
+    let i = Instant::now();
+    let mut d = Delay::new_handle(i + dur, timer.handle());
+    block_on(future::poll_fn(|cx| {
+        let _ = timer.poll(cx);
+        timer.advance();
+        match d.poll(cx) {
+            Ok(Async::Pending) => {}
+            other => return other,
+        }
+        // Nothing else drives our timer, so keep this task spinning.
+        cx.waker().wake();
+        Ok(Async::Pending)
+    })).unwrap();
+    assert!(i.elapsed() >= dur);
+}
+
+#[test]
+fn active_timers() {
+    let mut timer = ManualTimer::new();
+    let handle = timer.handle();
+    let mut delays = (1..11)
+        .map(|i| Delay::new_handle(timer.now() + Duration::from_millis(i), handle.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(handle.active_timers(), 10);
+
+    delays.truncate(5);
+    assert_eq!(handle.active_timers(), 5);
+
+    delays[0].reset(Duration::from_millis(0));
+    assert_eq!(handle.active_timers(), 4);
+    delays[0].reset(Duration::from_millis(100));
+    assert_eq!(handle.active_timers(), 5);
+
+    timer.advance(Duration::from_millis(5));
+    assert_eq!(handle.active_timers(), 1);
+
+    drop(delays);
+    assert_eq!(handle.active_timers(), 0);
+}
+
+fn assert_unpin<T: Unpin>() {}
+
+#[test]
+fn unpin() {
+    assert_unpin::<Delay>();
+    assert_unpin::<futures_timer::Interval>();
+}
+
+#[test]
+fn poll_by_mut_ref() {
+    let dur = Duration::from_millis(10);
+    let mut d = Delay::new(dur);
+    block_on(&mut d).unwrap();
+
+    let i = Instant::now();
+    d.reset(dur);
+    block_on(&mut d).unwrap();
+    assert!(i.elapsed() >= dur);
+}
+
+struct Unpark(thread::Thread);
+
+impl Wake for Unpark {
+    fn wake(arc_self: &Arc<Self>) {
+        arc_self.0.unpark()
+    }
+}
+
+// Drives `timer` and `f` on the current thread until `f` completes, sleeping
+// in between until the next deadline or wakeup. Returns the number of times
+// the loop went around along with the result of `f`.
+fn run_with<F: Future>(timer: &mut TimerTask, mut f: F) -> (usize, Result<F::Item, F::Error>) {
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut map = LocalMap::new();
+    let mut cx = task::Context::without_spawn(&mut map, &waker);
+    let mut turns = 0;
+    loop {
+        turns += 1;
+        let _ = timer.poll(&mut cx);
+        match f.poll(&mut cx) {
+            Ok(Async::Pending) => {}
+            Ok(Async::Ready(item)) => return (turns, Ok(item)),
+            Err(e) => return (turns, Err(e)),
+        }
+        match timer.next_event() {
+            Some(at) => thread::park_timeout(at.saturating_duration_since(Instant::now())),
+            None => thread::park(),
+        }
+    }
+}
+
+#[test]
+fn timer_task() {
+    let (mut timer, handle) = Timer::task();
+
+    let start = Instant::now();
+    let dur = Duration::from_millis(50);
+    let first = Delay::new_handle(handle.now() + dur, handle.clone());
+    let second = Delay::new_handle(handle.now() + dur * 2, handle.clone());
+    let (turns, res) = run_with(&mut timer, first.join(second));
+    res.unwrap();
+    assert!(start.elapsed() >= dur * 2);
+    assert_eq!(handle.active_timers(), 0);
+
+    // The loop sleeps until the deadlines rather than polling all along.
+    assert!(turns < 20, "{} turns", turns);
+    assert_eq!(timer.next_event(), None);
+
+    // Delays created once the task has gone idle wake it back up.
+    let third = Delay::new_handle(handle.now() + dur, handle.clone());
+    run_with(&mut timer, third).1.unwrap();
+}
+
+// Returns the median overshoot of a short delay, which unlike the worst one
+// isn't thrown off by the test thread being descheduled now and then.
+fn overshoot(builder: TimerBuilder) -> Duration {
+    let timer = builder.spawn().unwrap();
+    let dur = Duration::from_micros(50);
+    let mut overshoots = (0..21).map(|_| {
+        let start = Instant::now();
+        Delay::new_handle(start + dur, timer.handle()).wait().unwrap();
+        start.elapsed() - dur
+    }).collect::<Vec<_>>();
+    overshoots.sort();
+    overshoots[overshoots.len() / 2]
+}
+
+#[test]
+fn spin_threshold() {
+    let parked = overshoot(Timer::builder());
+    let spun = overshoot(Timer::builder().spin_threshold(Duration::from_micros(100)));
+    assert!(spun < parked, "{:?} spinning, {:?} parked", spun, parked);
+    assert!(spun < Duration::from_millis(5));
+}
+
+#[test]
+fn spin_threshold_frozen_clock() {
+    let clock = TestClock::new();
+    let threshold = Duration::from_millis(10);
+    let mut timer = Timer::builder()
+        .clock(clock.clone())
+        .spin_threshold(threshold)
+        .build();
+    let handle = timer.handle();
+    let mut delay = Delay::new_handle(handle.now() + Duration::from_millis(1), handle);
+
+    // Spinning gives up after the threshold as the clock doesn't move, and
+    // the timer fires the delay once it does.
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut map = LocalMap::new();
+    let mut cx = task::Context::without_spawn(&mut map, &waker);
+    let start = Instant::now();
+    assert!(delay.poll(&mut cx).unwrap().is_pending());
+    assert!(start.elapsed() >= threshold);
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    let _ = timer.poll(&mut cx);
+    clock.advance(Duration::from_millis(1));
+    timer.advance();
+    assert!(delay.poll(&mut cx).unwrap().is_ready());
+}
@@ -0,0 +1,90 @@
+#![cfg(feature = "std-future")]
+
+extern crate futures;
+extern crate futures_timer;
+
+use std::future::Future as StdFuture;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+use futures::future;
+use futures_timer::{Delay, FutureExt, Timer};
+
+struct Unpark(Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark()
+    }
+}
+
+fn block_on<F: StdFuture>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[test]
+fn await_delay() {
+    let dur = Duration::from_millis(10);
+    let start = Instant::now();
+    block_on(Delay::new(dur));
+    assert!(start.elapsed() >= dur);
+}
+
+#[test]
+fn delay_without_timer_resolves() {
+    let timer = Timer::new();
+    let delay = Delay::new_handle(Instant::now() + Duration::from_secs(10), timer.handle());
+    drop(timer);
+    block_on(delay);
+}
+
+#[test]
+fn compat_timeout() {
+    let f = future::empty::<(), io::Error>().timeout(Duration::from_millis(10));
+    let err = block_on(f.compat()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+    let f = future::ok::<u32, io::Error>(3).timeout(Duration::from_secs(10));
+    assert_eq!(block_on(f.compat()).unwrap(), 3);
+}
+
+// A hand-written `std` future driving a `Delay`, standing in for an `async`
+// block.
+struct Twice {
+    delay: Delay,
+    left: u32,
+}
+
+impl StdFuture for Twice {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        while Pin::new(&mut self.delay).poll(cx).is_ready() {
+            if self.left == 0 {
+                return Poll::Ready(())
+            }
+            self.left -= 1;
+            self.delay.reset(Duration::from_millis(10));
+        }
+        Poll::Pending
+    }
+}
+
+#[test]
+fn delay_in_std_future() {
+    let start = Instant::now();
+    block_on(Twice { delay: Delay::new(Duration::from_millis(10)), left: 2 });
+    assert!(start.elapsed() >= Duration::from_millis(30));
+}
@@ -1,10 +1,14 @@
 extern crate futures;
 extern crate futures_timer;
 
+use std::io;
+use std::thread;
 use std::time::{Instant, Duration};
 
+use futures::future;
+use futures::prelude::*;
 use futures::executor::block_on;
-use futures_timer::Delay;
+use futures_timer::{Delay, FutureExt, Timer};
 
 #[test]
 fn smoke() {
@@ -15,6 +19,16 @@ fn smoke() {
     assert!(start.elapsed() >= (dur / 2));
 }
 
+#[test]
+fn sleep() {
+    let dur = Duration::from_millis(10);
+    let start = Instant::now();
+    block_on(futures_timer::sleep(dur)).unwrap();
+    assert!(start.elapsed() >= dur);
+    block_on(futures_timer::sleep_until(start + dur * 2)).unwrap();
+    assert!(start.elapsed() >= dur * 2);
+}
+
 #[test]
 fn two() {
     let dur = Duration::from_millis(10);
@@ -23,3 +37,147 @@ fn two() {
     let timeout = Delay::new(dur);
     block_on(timeout).unwrap();
 }
+
+#[test]
+fn is_elapsed() {
+    let dur = Duration::from_millis(10);
+    let d = Delay::new(dur);
+    assert!(!d.is_elapsed());
+    block_on(d).unwrap();
+
+    let d = Delay::new_at(Instant::now());
+    assert!(d.is_elapsed());
+}
+
+#[test]
+fn deadline() {
+    let at = Instant::now() + Duration::from_millis(100);
+    let d = Delay::new_at(at);
+    assert_eq!(d.deadline(), at);
+    assert!(d.remaining() <= Duration::from_millis(100));
+    assert!(d.remaining() > Duration::from_millis(0));
+    block_on(d).unwrap();
+
+    let d = Delay::new_at(Instant::now());
+    assert_eq!(d.remaining(), Duration::from_millis(0));
+}
+
+#[test]
+fn huge_duration() {
+    let mut d = Delay::new(Duration::from_secs(u64::MAX));
+    assert!(!d.is_elapsed());
+    assert!(block_on(future::poll_fn(|cx| {
+        Ok::<_, io::Error>(Async::Ready(d.poll(cx)?.is_pending()))
+    })).unwrap());
+}
+
+#[test]
+fn zero_ready_on_first_poll() {
+    let mut d = Delay::new(Duration::from_secs(0));
+    assert!(block_on(future::poll_fn(|cx| {
+        Ok::<_, io::Error>(Async::Ready(d.poll(cx)?.is_ready()))
+    })).unwrap());
+
+    let mut d = Delay::new(Duration::from_secs(100));
+    d.reset_at(Instant::now());
+    assert!(block_on(future::poll_fn(|cx| {
+        Ok::<_, io::Error>(Async::Ready(d.poll(cx)?.is_ready()))
+    })).unwrap());
+}
+
+#[test]
+fn zero_timeout_elapses() {
+    for _ in 0..100 {
+        let f = future::empty::<(), io::Error>();
+        let err = block_on(f.timeout(Duration::from_secs(0))).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+}
+
+#[test]
+fn timeout_fn() {
+    let dur = Duration::from_millis(10);
+    let err = block_on(futures_timer::timeout(dur, future::empty::<(), io::Error>())).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+    let at = Instant::now() + dur;
+    let err = block_on(futures_timer::timeout_at(at, future::empty::<(), io::Error>())).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    assert!(Instant::now() >= at);
+
+    let ok = future::ok::<u32, io::Error>(3);
+    assert_eq!(block_on(futures_timer::timeout(dur, ok)).unwrap(), 3);
+}
+
+#[test]
+fn wait() {
+    let dur = Duration::from_millis(50);
+    let start = Instant::now();
+    Delay::new(dur).wait().unwrap();
+    assert!(start.elapsed() >= dur);
+
+    // A delay whose timer has gone away fails rather than blocking forever.
+    let timer = Timer::new();
+    let delay = Delay::new_handle(Instant::now() + dur, timer.handle());
+    drop(timer);
+    assert!(delay.wait().is_err());
+}
+
+#[test]
+fn new_at_past() {
+    let timer = Timer::new();
+    let past = match Instant::now().checked_sub(Duration::from_secs(60)) {
+        Some(past) => past,
+        None => return,
+    };
+    let mut delay = Delay::new_handle(past, timer.handle());
+    assert_eq!(timer.handle().active_timers(), 0);
+    let res = block_on(future::poll_fn(|cx| {
+        Ok::<_, io::Error>(Async::Ready(delay.poll(cx)?))
+    })).unwrap();
+    assert!(res.is_ready());
+    assert_eq!(delay.remaining(), Duration::from_secs(0));
+}
+
+#[test]
+fn new_at_far_future() {
+    let timer = Timer::new();
+    let far = Instant::now() + Duration::from_secs(86400 * 365 * 100);
+    let mut delay = Delay::new_handle(far, timer.handle());
+    let res = block_on(future::poll_fn(|cx| {
+        Ok::<_, io::Error>(Async::Ready(delay.poll(cx)?))
+    })).unwrap();
+    assert!(res.is_pending());
+    assert!(!delay.is_elapsed());
+    assert!(delay.remaining() > Duration::from_secs(86400 * 365 * 99));
+}
+
+#[test]
+fn overrun() {
+    let mut delay = Delay::new(Duration::from_millis(10));
+    assert_eq!(delay.overrun(), Duration::from_secs(0));
+    block_on(future::poll_fn(|cx| delay.poll(cx))).unwrap();
+
+    let before = delay.overrun();
+    let extra = Duration::from_millis(50);
+    thread::sleep(extra);
+    let overrun = delay.overrun();
+    assert!(overrun >= before + extra);
+    assert!(overrun < before + extra + Duration::from_millis(500));
+}
+
+#[test]
+fn never() {
+    let mut never = Delay::never();
+    let res = block_on(future::poll_fn(|cx| {
+        Ok::<_, io::Error>(Async::Ready(never.poll(cx)?))
+    })).unwrap();
+    assert!(res.is_pending());
+    assert!(!never.is_elapsed());
+
+    let dur = Duration::from_millis(10);
+    let start = Instant::now();
+    never.reset(dur);
+    block_on(never).unwrap();
+    assert!(start.elapsed() >= (dur / 2));
+}